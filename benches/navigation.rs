@@ -0,0 +1,220 @@
+//! Compares navigating a ring through the database, the way webringer used to, with navigating it
+//! through the copy of its order [`RingState`] keeps in memory, with and without counting the hops
+//! like the handlers do
+//!
+//! ```sh
+//! cargo bench --bench navigation -- --sites 1000 --hops 20000
+//! ```
+#![warn(clippy::pedantic, clippy::all, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+use std::{
+	future::Future,
+	time::{Duration, Instant},
+};
+
+use axum_login::AuthUser;
+use clap::Parser;
+use sqlx::{AnyPool, any::AnyPoolOptions};
+
+use webringer::ring::{RingState, SiteProfile, rings::DEFAULT_RING_ID, sql, stats::NavigationKind};
+
+/// Benchmarks /next, /prev and /random
+#[derive(Parser, Debug)]
+struct Args {
+	/// How many approved sites are in the ring
+	#[arg(long, default_value_t = 500)]
+	sites: usize,
+	/// How many times each kind of navigation is timed
+	#[arg(long, default_value_t = 10_000)]
+	hops: usize,
+	/// Ignored, `cargo bench` passes it
+	#[arg(long, hide = true)]
+	bench: bool,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+	let args = Args::parse();
+
+	let path = std::env::temp_dir().join(format!("webringer-bench-{}.db", std::process::id()));
+	sqlx::any::install_default_drivers();
+	let pool = AnyPoolOptions::new()
+		.connect(&format!("sqlite://{}?mode=rwc", path.display()))
+		.await
+		.expect("Could not create the benchmark database");
+	sqlx::migrate!()
+		.run(&pool)
+		.await
+		.expect("Could not migrate the benchmark database");
+
+	let state = RingState::new(pool.clone());
+	let admin = state
+		.get_admin_by_username("admin")
+		.await
+		.expect("The default admin is missing");
+	let urls: Vec<String> = (0..args.sites)
+		.map(|site| format!("https://site{site}.example"))
+		.collect();
+	for url in &urls {
+		state
+			.add_site(url, "owner@example.com", &SiteProfile::default())
+			.await
+			.expect("Could not add a site");
+		state
+			.approve_site(url, admin.id())
+			.await
+			.expect("Could not approve a site");
+	}
+	let state = &state;
+	let pool = &pool;
+	let url = |hop: usize| urls[hop % urls.len()].as_str();
+
+	println!("{} sites, {} hops each", args.sites, args.hops);
+	println!("{:<20}{:>14}{:>14}", "", "database", "in memory");
+	report(
+		"next",
+		time(args.hops, |hop| database_next(pool, url(hop), Order::Next)).await,
+		time(args.hops, |hop| state.get_next(url(hop), None)).await,
+		args.hops,
+	);
+	report(
+		"prev",
+		time(args.hops, |hop| database_next(pool, url(hop), Order::Prev)).await,
+		time(args.hops, |hop| state.get_prev(url(hop), None)).await,
+		args.hops,
+	);
+	report(
+		"random",
+		time(args.hops, |_hop| {
+			sqlx::query_scalar::<_, String>(
+				"SELECT root_url FROM approved_sites WHERE ring_id = $1 ORDER BY random() LIMIT 1",
+			)
+			.bind(DEFAULT_RING_ID)
+			.fetch_one(pool)
+		})
+		.await,
+		time(args.hops, |_hop| state.get_random_site(None)).await,
+		args.hops,
+	);
+	report(
+		"next, counted",
+		time(args.hops, |hop| async move {
+			let next = database_next(pool, url(hop), Order::Next).await?;
+			database_count(pool, url(hop), &next).await;
+			Some(next)
+		})
+		.await,
+		time(args.hops, |hop| async move {
+			let next = state.get_next(url(hop), None).await.ok()?;
+			state
+				.record_navigation(NavigationKind::Next, Some(url(hop)), Some(&next))
+				.await;
+			Some(next)
+		})
+		.await,
+		args.hops,
+	);
+	let start = Instant::now();
+	let rows = state
+		.flush_navigation()
+		.await
+		.expect("Could not write the navigation stats");
+	println!(
+		"{:<20}{:>10.2}ms for {rows} rows",
+		"writing the counts",
+		start.elapsed().as_secs_f64() * 1000.0
+	);
+
+	pool.close().await;
+	for suffix in ["", "-wal", "-shm"] {
+		let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+	}
+}
+
+enum Order {
+	Next,
+	Prev,
+}
+
+/// Gets the site after or before `url` with a query for its id and another for its neighbour,
+/// like navigation did before the order of each ring was kept in memory
+async fn database_next(pool: &AnyPool, url: &str, order: Order) -> Option<String> {
+	let site_id: i64 = sqlx::query_scalar(
+		"SELECT site_id FROM approved_sites WHERE root_url = $1 AND ring_id = $2",
+	)
+	.bind(url)
+	.bind(DEFAULT_RING_ID)
+	.fetch_one(pool)
+	.await
+	.ok()?;
+	let query = match order {
+		Order::Next => {
+			"SELECT root_url FROM approved_sites WHERE site_id > $1 AND ring_id = $2
+			ORDER BY site_id ASC LIMIT 1"
+		}
+		Order::Prev => {
+			"SELECT root_url FROM approved_sites WHERE site_id < $1 AND ring_id = $2
+			ORDER BY site_id DESC LIMIT 1"
+		}
+	};
+	sqlx::query_scalar(query)
+		.bind(site_id)
+		.bind(DEFAULT_RING_ID)
+		.fetch_one(pool)
+		.await
+		.ok()
+}
+
+/// Counts a hop with an upsert of its own, like navigation did before the counts were kept in
+/// memory
+async fn database_count(pool: &AnyPool, source_url: &str, destination_url: &str) {
+	let site_id = |url: &str| {
+		sqlx::query_scalar::<_, i64>(
+			"SELECT site_id FROM approved_sites WHERE root_url = $1 AND ring_id = $2",
+		)
+		.bind(url.to_owned())
+		.bind(DEFAULT_RING_ID)
+		.fetch_one(pool)
+	};
+	let source_site_id = site_id(source_url).await.unwrap_or(0);
+	let destination_site_id = site_id(destination_url).await.unwrap_or(0);
+	sqlx::query(
+		"INSERT INTO navigation_stats
+		(ring_id, day, kind, source_site_id, destination_site_id, hits)
+		VALUES ($1, $2, 'next', $3, $4, 1)
+		ON CONFLICT (ring_id, day, kind, source_site_id, destination_site_id)
+		DO UPDATE SET hits = navigation_stats.hits + 1",
+	)
+	.bind(DEFAULT_RING_ID)
+	.bind(sql::today())
+	.bind(source_site_id)
+	.bind(destination_site_id)
+	.execute(pool)
+	.await
+	.expect("Could not count a hop");
+}
+
+/// Runs `hop` for every hop, after a hop to warm up
+async fn time<F, Fut, T>(hops: usize, hop: F) -> Duration
+where
+	F: Fn(usize) -> Fut,
+	Fut: Future<Output = T>,
+{
+	hop(0).await;
+	let start = Instant::now();
+	for i in 0..hops {
+		std::hint::black_box(hop(i).await);
+	}
+	start.elapsed()
+}
+
+/// Prints how long a hop took on average, through the database and in memory
+fn report(name: &str, database: Duration, in_memory: Duration, hops: usize) {
+	#[allow(clippy::cast_precision_loss)]
+	let per_hop = |elapsed: Duration| elapsed.as_secs_f64() * 1_000_000.0 / hops as f64;
+	println!(
+		"{name:<20}{:>12.2}µs{:>12.2}µs",
+		per_hop(database),
+		per_hop(in_memory)
+	);
+}
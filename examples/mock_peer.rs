@@ -0,0 +1,105 @@
+//! A stand-in for a webringer instance on another site, for trying out ring federation locally
+//!
+//! It serves a signed manifest at `/ring.json` for a made up ring, with a fresh signing key every
+//! time it starts. Link a local ring to it with `http://localhost:<port>/ring.json` from the peer
+//! rings admin page, restarting it then shows what happens when a peer's key changes.
+//!
+//! ```sh
+//! cargo run --example mock_peer -- --port 18090 --name "Mock Ring"
+//! ```
+#![warn(clippy::pedantic, clippy::all, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+	Router,
+	extract::State,
+	http::header,
+	response::{Html, IntoResponse},
+	routing::get,
+};
+use clap::Parser;
+use tracing::info;
+
+use webringer::ring::federation::{
+	MANIFEST_VERSION, ManifestMember, ManifestNavigation, RingManifest, SIGNATURE_HEADER,
+	generate_signing_key, sign_manifest,
+};
+
+/// A mock webring to federate with
+#[derive(Parser, Debug)]
+struct Args {
+	/// Port to listen on
+	#[arg(short, long, value_name = "PORT", default_value_t = 18090)]
+	port: u16,
+
+	/// Name of the mock ring
+	#[arg(short, long, value_name = "NAME", default_value = "Mock Ring")]
+	name: String,
+
+	/// Manifest urls of rings the mock ring links to
+	#[arg(long, value_name = "URL")]
+	peer: Vec<String>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+	tracing_subscriber::fmt::init();
+	let args = Args::parse();
+	let key = generate_signing_key();
+	let base_url = format!("http://localhost:{}", args.port);
+	let url = |path: &str| format!("{base_url}{path}");
+
+	let body = serde_json::to_string(&RingManifest {
+		version: MANIFEST_VERSION,
+		name: args.name.clone(),
+		description: Some("A ring that only exists for testing".to_owned()),
+		home_url: url("/"),
+		manifest_url: url("/ring.json"),
+		navigation: ManifestNavigation {
+			next: url("/next"),
+			prev: url("/prev"),
+			random: url("/random"),
+			next_ring: url("/next-ring"),
+			random_ring: url("/random-ring"),
+		},
+		members: vec![ManifestMember {
+			url: "https://example.com/".to_owned(),
+			title: Some("An example member".to_owned()),
+		}],
+		peers: args.peer,
+		public_key: hex::encode(key.verifying_key().to_bytes()),
+		generated_at: SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |duration| duration.as_secs()),
+	})
+	.expect("The manifest should always serialize");
+	let signature = sign_manifest(&key, &body);
+
+	let router = Router::new()
+		.route("/", get(home))
+		.route("/ring.json", get(manifest))
+		.with_state((args.name, body, signature));
+
+	let listener = tokio::net::TcpListener::bind(("127.0.0.1", args.port))
+		.await
+		.expect("Should be able to bind to the port");
+	info!("Mock peer ring listening on {}", base_url);
+	axum::serve(listener, router)
+		.await
+		.expect("The server should run until killed");
+}
+
+async fn home(State((name, _, _)): State<(String, String, String)>) -> impl IntoResponse {
+	Html(format!("<h1>{name}</h1><p>A mock webring</p>"))
+}
+
+async fn manifest(
+	State((_, body, signature)): State<(String, String, String)>,
+) -> impl IntoResponse {
+	(
+		[(header::CONTENT_TYPE, "application/json")],
+		[(SIGNATURE_HEADER, signature)],
+		body,
+	)
+}
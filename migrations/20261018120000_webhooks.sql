@@ -0,0 +1,28 @@
+CREATE TABLE webhooks (
+    id integer PRIMARY KEY,
+    url text CHECK (TRIM(url) <> '') NOT NULL UNIQUE,
+    secret text CHECK (TRIM(secret) <> '') NOT NULL,
+    date_added text NOT NULL,
+    admin_id integer,
+    FOREIGN KEY (admin_id) REFERENCES admins (id)
+    ON DELETE SET NULL
+);
+
+CREATE TABLE webhook_deliveries (
+    id integer PRIMARY KEY,
+    webhook_id integer NOT NULL,
+    event text CHECK (TRIM(event) <> '') NOT NULL,
+    payload text NOT NULL,
+    status text CHECK (status IN ('pending', 'delivered', 'failed')) NOT NULL
+    DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    date_created text NOT NULL,
+    next_attempt_at text NOT NULL,
+    last_attempt_at text,
+    last_response text,
+    FOREIGN KEY (webhook_id) REFERENCES webhooks (id)
+    ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_deliveries_due
+ON webhook_deliveries (status, next_attempt_at);
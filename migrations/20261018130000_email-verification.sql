@@ -0,0 +1,17 @@
+ALTER TABLE sites ADD COLUMN email_verification_token text;
+
+ALTER TABLE sites ADD COLUMN email_verified_at text;
+
+CREATE UNIQUE INDEX sites_email_verification_token
+ON sites (email_verification_token);
+
+DROP VIEW unapproved_sites;
+
+CREATE VIEW unapproved_sites
+AS SELECT
+    id,
+    root_url,
+    email,
+    email_verified_at
+FROM sites
+WHERE approval_id IS null AND denial_id IS null;
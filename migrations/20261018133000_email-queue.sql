@@ -0,0 +1,16 @@
+-- Emails to site owners, sent by the email worker rather than while the visitor waits. Sent emails
+-- are deleted, so only ones still to be sent or given up on are kept.
+CREATE TABLE queued_emails (
+    id integer PRIMARY KEY,
+    recipient text CHECK (TRIM(recipient) <> '') NOT NULL,
+    subject text NOT NULL,
+    body text NOT NULL,
+    status text CHECK (status IN ('pending', 'failed')) NOT NULL DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    date_created text NOT NULL,
+    next_attempt_at text NOT NULL,
+    last_error text
+);
+
+CREATE INDEX queued_emails_due
+ON queued_emails (status, next_attempt_at);
@@ -0,0 +1,29 @@
+ALTER TABLE sites ADD COLUMN title text;
+
+ALTER TABLE sites ADD COLUMN description text;
+
+ALTER TABLE sites ADD COLUMN owner_name text;
+
+ALTER TABLE sites ADD COLUMN feed_url text;
+
+ALTER TABLE sites ADD COLUMN button_url text;
+
+DROP VIEW approved_sites;
+
+CREATE VIEW approved_sites
+AS SELECT
+    s.id AS site_id,
+    s.root_url,
+    s.email AS site_email,
+    s.title,
+    s.description,
+    s.owner_name,
+    s.feed_url,
+    s.button_url,
+    ar.date_added,
+    a.id AS admin_id,
+    a.username AS admin_username,
+    a.email AS admin_email
+FROM sites AS s
+INNER JOIN approval_records AS ar ON s.approval_id = ar.id
+LEFT JOIN admins AS a ON ar.admin_id = a.id;
@@ -0,0 +1,7 @@
+CREATE TABLE member_login_tokens (
+    token_hash text PRIMARY KEY,
+    site_id integer NOT NULL,
+    expires_at text NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+CREATE TABLE site_url_aliases (
+    old_url text CHECK (TRIM(old_url) <> '') NOT NULL,
+    site_id integer NOT NULL,
+    expires_at text NOT NULL,
+    PRIMARY KEY (old_url, site_id),
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
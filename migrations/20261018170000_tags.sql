@@ -0,0 +1,40 @@
+CREATE TABLE tags (
+    id integer PRIMARY KEY,
+    name text CHECK (TRIM(name) <> '') NOT NULL UNIQUE
+);
+
+CREATE TABLE site_tags (
+    site_id integer NOT NULL,
+    tag_id integer NOT NULL,
+    PRIMARY KEY (site_id, tag_id),
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags (id)
+    ON DELETE CASCADE
+);
+
+DROP VIEW approved_sites;
+
+CREATE VIEW approved_sites
+AS SELECT
+    s.id AS site_id,
+    s.root_url,
+    s.email AS site_email,
+    s.title,
+    s.description,
+    s.owner_name,
+    s.feed_url,
+    s.button_url,
+    (
+        SELECT GROUP_CONCAT(t.name, ' ')
+        FROM site_tags AS st
+        INNER JOIN tags AS t ON st.tag_id = t.id
+        WHERE st.site_id = s.id
+    ) AS tags,
+    ar.date_added,
+    a.id AS admin_id,
+    a.username AS admin_username,
+    a.email AS admin_email
+FROM sites AS s
+INNER JOIN approval_records AS ar ON s.approval_id = ar.id
+LEFT JOIN admins AS a ON ar.admin_id = a.id;
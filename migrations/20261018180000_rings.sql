@@ -0,0 +1,146 @@
+CREATE TABLE rings (
+    id integer PRIMARY KEY,
+    slug text CHECK (TRIM(slug) <> '') NOT NULL UNIQUE,
+    name text CHECK (TRIM(name) <> '') NOT NULL,
+    description text
+);
+
+-- Everything created before rings existed belongs to the default ring
+INSERT INTO rings (id, slug, name) VALUES (1, 'default', 'WebRinger');
+
+DROP VIEW approved_sites;
+
+DROP VIEW denied_sites;
+
+DROP VIEW unapproved_sites;
+
+-- SQLite can't add a foreign key column with a non-null default, so sites are
+-- rebuilt with their ring. The same url can be a member of several rings.
+--
+-- Migrations run in a transaction, where foreign keys can't be turned off, so
+-- dropping sites deletes every row referring to it. They're put back after.
+CREATE TEMPORARY TABLE old_sites AS SELECT * FROM sites;
+
+CREATE TEMPORARY TABLE old_member_login_tokens AS SELECT * FROM member_login_tokens;
+
+CREATE TEMPORARY TABLE old_site_url_aliases AS SELECT * FROM site_url_aliases;
+
+CREATE TEMPORARY TABLE old_site_tags AS SELECT * FROM site_tags;
+
+DROP TABLE sites;
+
+CREATE TABLE sites (
+    id integer PRIMARY KEY,
+    ring_id integer NOT NULL DEFAULT 1,
+    root_url text CHECK (TRIM(root_url) <> '') NOT NULL,
+    email text CHECK (TRIM(email) <> '') NOT NULL,
+    email_verification_token text,
+    email_verified_at text,
+    title text,
+    description text,
+    owner_name text,
+    feed_url text,
+    button_url text,
+    approval_id integer UNIQUE,
+    denial_id integer UNIQUE,
+    UNIQUE (ring_id, root_url),
+    CHECK (
+    (approval_id IS NOT NULL AND denial_id IS NULL)
+    or (approval_id IS NULL AND denial_id IS NOT NULL)
+    or (approval_id IS NULL AND denial_id IS NULL)
+    ),
+    FOREIGN KEY (ring_id) REFERENCES rings (id),
+    FOREIGN KEY (approval_id) REFERENCES approval_records (id)
+    ON DELETE SET NULL,
+    FOREIGN KEY (denial_id) REFERENCES denial_records (id)
+    ON DELETE SET NULL
+);
+
+INSERT INTO sites (
+    id, root_url, email, email_verification_token, email_verified_at, title,
+    description, owner_name, feed_url, button_url, approval_id, denial_id
+)
+SELECT
+    id,
+    root_url,
+    email,
+    email_verification_token,
+    email_verified_at,
+    title,
+    description,
+    owner_name,
+    feed_url,
+    button_url,
+    approval_id,
+    denial_id
+FROM old_sites;
+
+INSERT INTO member_login_tokens SELECT * FROM old_member_login_tokens;
+
+INSERT INTO site_url_aliases SELECT * FROM old_site_url_aliases;
+
+INSERT INTO site_tags SELECT * FROM old_site_tags;
+
+DROP TABLE old_sites;
+
+DROP TABLE old_member_login_tokens;
+
+DROP TABLE old_site_url_aliases;
+
+DROP TABLE old_site_tags;
+
+CREATE UNIQUE INDEX sites_email_verification_token
+ON sites (email_verification_token);
+
+-- Admins with no ring manage every ring on the instance
+ALTER TABLE admins ADD COLUMN ring_id integer REFERENCES rings (id);
+
+CREATE VIEW approved_sites
+AS SELECT
+    s.id AS site_id,
+    s.ring_id,
+    s.root_url,
+    s.email AS site_email,
+    s.title,
+    s.description,
+    s.owner_name,
+    s.feed_url,
+    s.button_url,
+    (
+        SELECT GROUP_CONCAT(t.name, ' ')
+        FROM site_tags AS st
+        INNER JOIN tags AS t ON st.tag_id = t.id
+        WHERE st.site_id = s.id
+    ) AS tags,
+    ar.date_added,
+    a.id AS admin_id,
+    a.username AS admin_username,
+    a.email AS admin_email
+FROM sites AS s
+INNER JOIN approval_records AS ar ON s.approval_id = ar.id
+LEFT JOIN admins AS a ON ar.admin_id = a.id;
+
+CREATE VIEW denied_sites
+AS SELECT
+    s.id AS site_id,
+    s.ring_id,
+    s.root_url,
+    s.email AS site_email,
+    dr.date_added,
+    dr.reason,
+    a.id AS admin_id,
+    a.username AS admin_username,
+    a.email AS admin_email
+FROM sites AS s
+INNER JOIN denial_records AS dr ON s.denial_id = dr.id
+LEFT JOIN admins AS a ON dr.admin_id = a.id;
+
+CREATE VIEW unapproved_sites
+AS SELECT
+    id,
+    ring_id,
+    root_url,
+    email,
+    email_verified_at
+FROM sites
+WHERE approval_id IS null AND denial_id IS null;
@@ -0,0 +1,20 @@
+-- The key this instance signs its ring manifests with, created the first time it's needed
+CREATE TABLE instance_keys (
+    id integer PRIMARY KEY CHECK (id = 1),
+    signing_key text NOT NULL
+);
+
+CREATE TABLE peer_rings (
+    id integer PRIMARY KEY,
+    ring_id integer NOT NULL,
+    manifest_url text NOT NULL,
+    name text NOT NULL,
+    home_url text NOT NULL,
+    -- The key the peer's manifest was signed with when it was added, which it must keep using
+    public_key text NOT NULL,
+    date_added text NOT NULL,
+    last_fetched_at text NOT NULL,
+    last_error text,
+    UNIQUE (ring_id, manifest_url),
+    FOREIGN KEY (ring_id) REFERENCES rings (id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- Approved sites that have since left their ring, for the membership feed
+CREATE TABLE site_removals (
+    id integer PRIMARY KEY,
+    ring_id integer NOT NULL,
+    root_url text NOT NULL,
+    title text,
+    date_removed text NOT NULL,
+    FOREIGN KEY (ring_id) REFERENCES rings (id)
+);
+
+CREATE INDEX site_removals_ring_id ON site_removals (ring_id);
+
+-- When exactly a site was approved, so the feed can order joins among removals on the same day.
+-- Approvals from before this was recorded are taken as being at midnight
+ALTER TABLE approval_records ADD COLUMN approved_at text;
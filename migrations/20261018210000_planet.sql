@@ -0,0 +1,28 @@
+-- The state of fetching each member's feed for the planet
+CREATE TABLE planet_feeds (
+    site_id integer PRIMARY KEY,
+    -- The feed url the cached entries and validators below came from
+    feed_url text,
+    etag text,
+    last_modified text,
+    last_fetched_at text,
+    last_error text,
+    excluded integer NOT NULL DEFAULT 0,
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
+
+CREATE TABLE planet_entries (
+    id integer PRIMARY KEY,
+    site_id integer NOT NULL,
+    guid text NOT NULL,
+    title text,
+    url text NOT NULL,
+    summary text,
+    published text NOT NULL,
+    UNIQUE (site_id, guid),
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
+
+CREATE INDEX planet_entries_published ON planet_entries (published);
@@ -0,0 +1,22 @@
+-- Daily counts of visitors navigating the ring. Nothing about the visitors themselves is stored.
+-- A site id of 0 means there wasn't a site, e.g. the destination of a widget load.
+CREATE TABLE navigation_stats (
+    ring_id integer NOT NULL,
+    day text NOT NULL,
+    kind text CHECK (kind IN ('next', 'prev', 'random', 'widget')) NOT NULL,
+    source_site_id integer NOT NULL,
+    destination_site_id integer NOT NULL,
+    hits integer NOT NULL DEFAULT 0,
+    PRIMARY KEY (ring_id, day, kind, source_site_id, destination_site_id),
+    FOREIGN KEY (ring_id) REFERENCES rings (id)
+);
+
+CREATE INDEX navigation_stats_source ON navigation_stats (source_site_id, day);
+CREATE INDEX navigation_stats_destination ON navigation_stats (destination_site_id, day);
+
+-- Site ids can be reused once a site is deleted, so its stats go with it
+CREATE TRIGGER navigation_stats_site_deleted AFTER DELETE ON sites
+BEGIN
+    DELETE FROM navigation_stats
+    WHERE source_site_id = old.id OR destination_site_id = old.id;
+END;
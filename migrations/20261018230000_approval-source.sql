@@ -0,0 +1,3 @@
+-- Where an approval came from when it wasn't an admin approving an application, e.g. 'import' for
+-- sites brought over from another webring. The admin is the one who did the import.
+ALTER TABLE approval_records ADD COLUMN source text;
@@ -0,0 +1,14 @@
+-- Members' buttons, fetched by the instance so visitors never load them from members' sites
+CREATE TABLE site_buttons (
+    site_id integer PRIMARY KEY,
+    -- The button url the cached image and validators below came from
+    button_url text NOT NULL,
+    content_type text,
+    image blob,
+    etag text,
+    last_modified text,
+    last_fetched_at text NOT NULL,
+    last_error text,
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
@@ -0,0 +1,302 @@
+-- The whole schema at once, matching what the sqlite migrations have built up to. Timestamps are
+-- stored as text in the same format as on sqlite, so the same queries work on both.
+CREATE TABLE rings (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    slug text CHECK (TRIM(slug) <> '') NOT NULL UNIQUE,
+    name text CHECK (TRIM(name) <> '') NOT NULL,
+    description text
+);
+
+INSERT INTO rings (slug, name) VALUES ('default', 'WebRinger');
+
+CREATE TABLE admins (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    username text CHECK (TRIM(username) <> '') NOT NULL UNIQUE,
+    email text CHECK (TRIM(email) <> '') NOT NULL UNIQUE,
+    password_phc text CHECK (TRIM(password_phc) <> '') NOT NULL UNIQUE,
+    -- Admins with no ring manage every ring on the instance
+    ring_id bigint REFERENCES rings (id)
+);
+
+INSERT INTO admins (username, email, password_phc) VALUES (
+    'admin',
+    'admin@webringer.com',
+    '$argon2id$v=19$m=19456,t=2,'
+    || 'p=1$DT+FkPZlCUjEjpAcRhLbsQ$H9PxZNMPYrG0VRHZ2yHPkoP02aO1vSdagwV2pWGAFJc'
+);
+
+CREATE TABLE approval_records (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    date_added text NOT NULL,
+    -- When exactly the site was approved, for the membership feed
+    approved_at text,
+    admin_id bigint NOT NULL,
+    -- Where an approval came from when it wasn't an admin approving an application, e.g. 'import'
+    source text,
+    FOREIGN KEY (admin_id) REFERENCES admins (id)
+    ON DELETE RESTRICT
+);
+
+CREATE TABLE denial_records (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    date_added text NOT NULL,
+    reason text CHECK (TRIM(reason) <> '') NOT NULL UNIQUE,
+    admin_id bigint NOT NULL,
+    FOREIGN KEY (admin_id) REFERENCES admins (id)
+    ON DELETE RESTRICT
+);
+
+CREATE TABLE sites (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    ring_id bigint NOT NULL DEFAULT 1 REFERENCES rings (id),
+    root_url text CHECK (TRIM(root_url) <> '') NOT NULL,
+    email text CHECK (TRIM(email) <> '') NOT NULL,
+    email_verification_token text UNIQUE,
+    email_verified_at text,
+    title text,
+    description text,
+    owner_name text,
+    feed_url text,
+    button_url text,
+    approval_id bigint UNIQUE,
+    denial_id bigint UNIQUE,
+    UNIQUE (ring_id, root_url),
+    CHECK (approval_id IS NULL OR denial_id IS NULL),
+    FOREIGN KEY (approval_id) REFERENCES approval_records (id)
+    ON DELETE SET NULL,
+    FOREIGN KEY (denial_id) REFERENCES denial_records (id)
+    ON DELETE SET NULL
+);
+
+CREATE TABLE tags (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name text CHECK (TRIM(name) <> '') NOT NULL UNIQUE
+);
+
+CREATE TABLE site_tags (
+    site_id bigint NOT NULL,
+    tag_id bigint NOT NULL,
+    PRIMARY KEY (site_id, tag_id),
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags (id)
+    ON DELETE CASCADE
+);
+
+CREATE VIEW approved_sites
+AS SELECT
+    s.id AS site_id,
+    s.ring_id,
+    s.root_url,
+    s.email AS site_email,
+    s.title,
+    s.description,
+    s.owner_name,
+    s.feed_url,
+    s.button_url,
+    (
+        SELECT STRING_AGG(t.name, ' ')
+        FROM site_tags AS st
+        INNER JOIN tags AS t ON st.tag_id = t.id
+        WHERE st.site_id = s.id
+    ) AS tags,
+    ar.date_added,
+    a.id AS admin_id,
+    a.username AS admin_username,
+    a.email AS admin_email
+FROM sites AS s
+INNER JOIN approval_records AS ar ON s.approval_id = ar.id
+LEFT JOIN admins AS a ON ar.admin_id = a.id;
+
+CREATE VIEW denied_sites
+AS SELECT
+    s.id AS site_id,
+    s.ring_id,
+    s.root_url,
+    s.email AS site_email,
+    dr.date_added,
+    dr.reason,
+    a.id AS admin_id,
+    a.username AS admin_username,
+    a.email AS admin_email
+FROM sites AS s
+INNER JOIN denial_records AS dr ON s.denial_id = dr.id
+LEFT JOIN admins AS a ON dr.admin_id = a.id;
+
+CREATE VIEW unapproved_sites
+AS SELECT
+    id,
+    ring_id,
+    root_url,
+    email,
+    email_verified_at
+FROM sites
+WHERE approval_id IS null AND denial_id IS null;
+
+CREATE TABLE webhooks (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    url text CHECK (TRIM(url) <> '') NOT NULL UNIQUE,
+    secret text CHECK (TRIM(secret) <> '') NOT NULL,
+    date_added text NOT NULL,
+    admin_id bigint,
+    FOREIGN KEY (admin_id) REFERENCES admins (id)
+    ON DELETE SET NULL
+);
+
+CREATE TABLE webhook_deliveries (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    webhook_id bigint NOT NULL,
+    event text CHECK (TRIM(event) <> '') NOT NULL,
+    payload text NOT NULL,
+    status text CHECK (status IN ('pending', 'delivered', 'failed')) NOT NULL
+    DEFAULT 'pending',
+    attempts bigint NOT NULL DEFAULT 0,
+    date_created text NOT NULL,
+    next_attempt_at text NOT NULL,
+    last_attempt_at text,
+    last_response text,
+    FOREIGN KEY (webhook_id) REFERENCES webhooks (id)
+    ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_deliveries_due
+ON webhook_deliveries (status, next_attempt_at);
+
+-- Emails to site owners, sent by the email worker rather than while the visitor waits. Sent emails
+-- are deleted, so only ones still to be sent or given up on are kept.
+CREATE TABLE queued_emails (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    recipient text CHECK (TRIM(recipient) <> '') NOT NULL,
+    subject text NOT NULL,
+    body text NOT NULL,
+    status text CHECK (status IN ('pending', 'failed')) NOT NULL DEFAULT 'pending',
+    attempts bigint NOT NULL DEFAULT 0,
+    date_created text NOT NULL,
+    next_attempt_at text NOT NULL,
+    last_error text
+);
+
+CREATE INDEX queued_emails_due
+ON queued_emails (status, next_attempt_at);
+
+CREATE TABLE member_login_tokens (
+    token_hash text PRIMARY KEY,
+    site_id bigint NOT NULL,
+    expires_at text NOT NULL,
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
+
+CREATE TABLE site_url_aliases (
+    old_url text CHECK (TRIM(old_url) <> '') NOT NULL,
+    site_id bigint NOT NULL,
+    expires_at text NOT NULL,
+    PRIMARY KEY (old_url, site_id),
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
+
+-- The key this instance signs its ring manifests with, created the first time it's needed
+CREATE TABLE instance_keys (
+    id bigint PRIMARY KEY CHECK (id = 1),
+    signing_key text NOT NULL
+);
+
+CREATE TABLE peer_rings (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    ring_id bigint NOT NULL,
+    manifest_url text NOT NULL,
+    name text NOT NULL,
+    home_url text NOT NULL,
+    -- The key the peer's manifest was signed with when it was added, which it must keep using
+    public_key text NOT NULL,
+    date_added text NOT NULL,
+    last_fetched_at text NOT NULL,
+    last_error text,
+    UNIQUE (ring_id, manifest_url),
+    FOREIGN KEY (ring_id) REFERENCES rings (id) ON DELETE CASCADE
+);
+
+-- Approved sites that have since left their ring, for the membership feed
+CREATE TABLE site_removals (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    ring_id bigint NOT NULL,
+    root_url text NOT NULL,
+    title text,
+    date_removed text NOT NULL,
+    FOREIGN KEY (ring_id) REFERENCES rings (id)
+);
+
+CREATE INDEX site_removals_ring_id ON site_removals (ring_id);
+
+-- The state of fetching each member's feed for the planet
+CREATE TABLE planet_feeds (
+    site_id bigint PRIMARY KEY,
+    -- The feed url the cached entries and validators below came from
+    feed_url text,
+    etag text,
+    last_modified text,
+    last_fetched_at text,
+    last_error text,
+    excluded bigint NOT NULL DEFAULT 0,
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
+
+CREATE TABLE planet_entries (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    site_id bigint NOT NULL,
+    guid text NOT NULL,
+    title text,
+    url text NOT NULL,
+    summary text,
+    published text NOT NULL,
+    UNIQUE (site_id, guid),
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
+
+CREATE INDEX planet_entries_published ON planet_entries (published);
+
+-- Members' buttons, fetched by the instance so visitors never load them from members' sites
+CREATE TABLE site_buttons (
+    site_id bigint PRIMARY KEY,
+    -- The button url the cached image and validators below came from
+    button_url text NOT NULL,
+    content_type text,
+    image bytea,
+    etag text,
+    last_modified text,
+    last_fetched_at text NOT NULL,
+    last_error text,
+    FOREIGN KEY (site_id) REFERENCES sites (id)
+    ON DELETE CASCADE
+);
+
+-- Daily counts of visitors navigating the ring. Nothing about the visitors themselves is stored.
+-- A site id of 0 means there wasn't a site, e.g. the destination of a widget load.
+CREATE TABLE navigation_stats (
+    ring_id bigint NOT NULL,
+    day text NOT NULL,
+    kind text CHECK (kind IN ('next', 'prev', 'random', 'widget')) NOT NULL,
+    source_site_id bigint NOT NULL,
+    destination_site_id bigint NOT NULL,
+    hits bigint NOT NULL DEFAULT 0,
+    PRIMARY KEY (ring_id, day, kind, source_site_id, destination_site_id),
+    FOREIGN KEY (ring_id) REFERENCES rings (id)
+);
+
+CREATE INDEX navigation_stats_source ON navigation_stats (source_site_id, day);
+CREATE INDEX navigation_stats_destination ON navigation_stats (destination_site_id, day);
+
+-- Site ids aren't reused on Postgres, but the stats of a deleted site are no use to anyone
+CREATE FUNCTION delete_site_navigation_stats() RETURNS trigger AS $$
+BEGIN
+    DELETE FROM navigation_stats
+    WHERE source_site_id = old.id OR destination_site_id = old.id;
+    RETURN old;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER navigation_stats_site_deleted AFTER DELETE ON sites
+FOR EACH ROW EXECUTE FUNCTION delete_site_navigation_stats();
@@ -0,0 +1,215 @@
+//! This module handles putting the webring's pages together into a router, for serving webringer
+//! or embedding it in another axum app
+//!
+//! The router only answers requests. The queued emails and webhooks, the feeds, the buttons and the
+//! navigation counts are handled by the workers [`RingState::spawn_workers`] starts, which have to
+//! be started as well:
+//!
+//! ```rust,no_run
+//! # async fn run(state: webringer::ring::RingState) {
+//! use webringer::{app::WebringerApp, ring::workers::WorkerIntervals};
+//!
+//! state.spawn_workers(&WorkerIntervals::default());
+//! let router = WebringerApp::new(state.clone())
+//!     .nest_at("/webring")
+//!     .without_admin()
+//!     .into_router();
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await.unwrap();
+//! axum::serve(listener, router).await.unwrap();
+//! // Navigation counted since the last time the worker wrote it down
+//! state.flush_navigation().await.unwrap();
+//! # }
+//! ```
+
+use axum::{
+	Router,
+	http::{HeaderValue, StatusCode, header},
+	routing::{get, post},
+};
+use axum_login::{
+	AuthManagerLayerBuilder,
+	tower_sessions::{MemoryStore, SessionManagerLayer, SessionStore},
+};
+use axum_messages::MessagesManagerLayer;
+use tower_http::{
+	services::{ServeDir, ServeFile},
+	set_header::SetResponseHeaderLayer,
+	set_status::SetStatus,
+};
+
+use crate::{embed, ring::RingState, site};
+
+/// Builds the [`Router`] serving a webring
+///
+/// By default it serves everything `webringer serve` does from the root, keeping sessions in
+/// memory.
+#[derive(Debug, Clone)]
+pub struct WebringerApp<S = MemoryStore> {
+	state: RingState,
+	path_prefix: Option<String>,
+	ui: bool,
+	admin: bool,
+	session_store: S,
+}
+
+impl WebringerApp {
+	/// Starts building the router for the webring kept in `state`
+	#[must_use]
+	pub fn new(state: RingState) -> Self {
+		Self {
+			state,
+			path_prefix: None,
+			ui: true,
+			admin: true,
+			session_store: MemoryStore::default(),
+		}
+	}
+}
+
+impl<S> WebringerApp<S>
+where
+	S: SessionStore + Clone,
+{
+	/// Serves everything under `path_prefix`, like `/webring`, instead of the root
+	#[must_use]
+	pub fn nest_at(self, path_prefix: &str) -> Self {
+		let path_prefix = format!("/{}", path_prefix.trim_matches('/'));
+		if path_prefix == "/" {
+			return Self {
+				path_prefix: None,
+				state: self.state.with_path_prefix(""),
+				..self
+			};
+		}
+		Self {
+			state: self.state.with_path_prefix(&path_prefix),
+			path_prefix: Some(path_prefix),
+			..self
+		}
+	}
+
+	/// Leaves out the HTML pages, the admin pages, the member area and the static files, only
+	/// serving navigation, the feeds and the machine readable lists
+	#[must_use]
+	pub fn without_ui(self) -> Self {
+		Self {
+			ui: false,
+			admin: false,
+			..self
+		}
+	}
+
+	/// Leaves out the admin pages and the login page
+	#[must_use]
+	pub fn without_admin(self) -> Self {
+		Self {
+			admin: false,
+			..self
+		}
+	}
+
+	/// Keeps logged in admins' sessions in `session_store` instead of in memory
+	#[must_use]
+	pub fn with_session_store<T>(self, session_store: T) -> WebringerApp<T>
+	where
+		T: SessionStore + Clone,
+	{
+		WebringerApp {
+			state: self.state,
+			path_prefix: self.path_prefix,
+			ui: self.ui,
+			admin: self.admin,
+			session_store,
+		}
+	}
+
+	/// Builds the router
+	pub fn into_router(self) -> Router {
+		let mut router = Router::new();
+		if self.admin {
+			router = router
+				.route("/login", get(site::login::get))
+				.route("/login", post(site::login::post));
+		}
+		let session_layer = SessionManagerLayer::new(self.session_store.clone());
+		let auth_layer = AuthManagerLayerBuilder::new(self.state.clone(), session_layer).build();
+		let mut router = router
+			.with_state(self.state.clone())
+			.merge(self.ring_router())
+			.nest("/r/{ring}", self.ring_router())
+			.layer(MessagesManagerLayer)
+			.layer(auth_layer);
+		if self.ui {
+			let static_dir = self.state.ring().theme.static_dir();
+			router = match &static_dir {
+				Some(static_dir) => router.nest_service(
+					"/static",
+					ServeDir::new(static_dir)
+						.precompressed_br()
+						.precompressed_gzip()
+						.fallback(get(embed::static_handler)),
+				),
+				None => router.route("/static/{*file}", get(embed::static_handler)),
+			};
+			let fallback = get(site::not_found).with_state(self.state.clone());
+			router = match static_dir.map(|static_dir| static_dir.join("404.html")) {
+				Some(not_found) if not_found.is_file() => {
+					router.fallback_service(SetStatus::new(
+						ServeFile::new(not_found),
+						StatusCode::NOT_FOUND,
+					))
+				}
+				_ => router.fallback_service(fallback),
+			};
+			router = router.layer(SetResponseHeaderLayer::if_not_present(
+				header::CONTENT_SECURITY_POLICY,
+				HeaderValue::from_static(site::CONTENT_SECURITY_POLICY),
+			));
+		}
+		match self.path_prefix {
+			Some(path_prefix) => Router::new().nest(&path_prefix, router),
+			None => router,
+		}
+	}
+
+	/// The pages of a single ring, served from the root for the default ring and from
+	/// `/r/{ring}` for every other ring
+	fn ring_router(&self) -> Router {
+		let mut router = Router::new()
+			.route("/next", get(site::ring::next))
+			.route("/prev", get(site::ring::prev))
+			.route("/random", get(site::ring::random))
+			.route("/list.opml", get(site::directory::opml))
+			.route("/list.json", get(site::directory::json))
+			.route("/list.csv", get(site::directory::csv))
+			.route("/feed.atom", get(site::feed::atom))
+			.route("/feed.rss", get(site::feed::rss))
+			.route("/planet.atom", get(site::planet::atom))
+			.route("/ring.json", get(site::ring::manifest))
+			.route("/next-ring", get(site::ring::next_ring))
+			.route("/random-ring", get(site::ring::random_ring));
+		if self.ui {
+			router = router
+				.route("/", get(site::index))
+				.route("/join", get(site::join::get))
+				.route("/join", post(site::join::post))
+				.route("/join/verify", get(site::join::verify))
+				.route("/leave", get(site::leave::get))
+				.route("/leave", post(site::leave::post))
+				.route("/list", get(site::ring::list))
+				.route("/members/{id}", get(site::ring::member))
+				.route("/members/{id}/stats", get(site::ring::member_stats))
+				.route("/members/{id}/button", get(site::ring::button))
+				.route("/widget", get(site::ring::widget))
+				.route("/planet", get(site::planet::page));
+		}
+		let mut router = router.with_state(self.state.clone());
+		if self.admin {
+			router = router.nest("/admin", site::admin::router(self.state.clone()));
+		}
+		if self.ui {
+			router = router.nest("/member", site::member::router(self.state.clone()));
+		}
+		router
+	}
+}
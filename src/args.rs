@@ -1,30 +1,156 @@
-use std::{fmt::Debug, str::FromStr};
+use std::path::PathBuf;
 
-use clap::Parser;
-use tracing::info;
+use clap::{Parser, Subcommand};
+use webringer::ring::importer::ImportFormat;
 
 /// A server for hosting a webring!
+///
+/// Settings given here override the environment, which overrides the configuration file
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub(super) struct Args {
-	/// Address to bind to
-	#[arg(short, long, value_name = "ADDR", default_value = "0.0.0.0")]
-	pub address: String,
+	/// Address to bind to [default: 0.0.0.0]
+	#[arg(short, long, value_name = "ADDR", global = true)]
+	pub address: Option<String>,
 
-	/// Port to listen on
-	#[arg(short, long, value_name = "PORT", default_value_t = 10983)]
-	pub port: u16,
+	/// Port to listen on [default: 10983]
+	#[arg(short, long, value_name = "PORT", global = true)]
+	pub port: Option<u16>,
+
+	/// The configuration file to read [default: webringer.toml, if it exists]
+	#[arg(short, long, value_name = "FILE", global = true)]
+	pub config: Option<PathBuf>,
+
+	/// Print the configuration that would be used as TOML, then exit
+	#[arg(long, global = true)]
+	pub print_config: bool,
+
+	/// What to do, serving the site if not given
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum Command {
+	/// Serve the webring site
+	Serve,
+	/// Manage admin accounts
+	#[command(subcommand)]
+	Admin(AdminCommand),
+	/// Manage the sites in a ring
+	Site {
+		/// The short name of the ring [default: the default ring]
+		#[arg(short, long, global = true)]
+		ring: Option<String>,
+		#[command(subcommand)]
+		command: SiteCommand,
+	},
+	/// Maintain the database
+	#[command(subcommand)]
+	Db(DbCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum AdminCommand {
+	/// Add an admin
+	Create {
+		username: String,
+		email: String,
+		/// The new admin's password, which is asked for if not given
+		#[arg(long)]
+		password: Option<String>,
+		/// Only let the admin manage the ring with this short name, instead of every ring
+		#[arg(short, long)]
+		ring: Option<String>,
+	},
+	/// List every admin
+	List,
+	/// Delete an admin
+	Delete { username: String },
+	/// Set an admin's password, for when they're locked out
+	ResetPassword {
+		username: String,
+		/// The new password, which is asked for if not given
+		#[arg(long)]
+		password: Option<String>,
+	},
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum SiteCommand {
+	/// List the sites in the ring
+	List {
+		/// Only list sites that are pending, approved or denied
+		#[arg(short, long)]
+		status: Option<SiteStatus>,
+	},
+	/// Add a site, emailing the owner to verify their address, without checking they own it
+	Add {
+		url: String,
+		email: String,
+		#[arg(long)]
+		title: Option<String>,
+		#[arg(long)]
+		description: Option<String>,
+	},
+	/// Approve a pending site
+	Approve {
+		url: String,
+		/// The admin the approval is recorded as being by
+		#[arg(long)]
+		admin: String,
+	},
+	/// Deny a pending site
+	Deny {
+		url: String,
+		/// The admin the denial is recorded as being by
+		#[arg(long)]
+		admin: String,
+		/// Why the site was denied
+		#[arg(long)]
+		reason: String,
+	},
+	/// Remove a site from the ring, whatever its status
+	Remove { url: String },
+	/// Import the sites of another webring from a list of urls, JSON, CSV, or an HTML page
+	Import {
+		path: PathBuf,
+		/// The admin the import is recorded as being by, whose email is used for sites without one
+		#[arg(long)]
+		admin: String,
+		/// urls, json, csv or html, worked out from the file if not given
+		#[arg(long)]
+		format: Option<ImportFormat>,
+		/// Leave the sites waiting for approval, instead of approving them
+		#[arg(long)]
+		pending: bool,
+		/// Only show what would be imported
+		#[arg(long)]
+		dry_run: bool,
+	},
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SiteStatus {
+	Pending,
+	Approved,
+	Denied,
 }
 
-pub(super) fn read_env_var<T>(key: &str, default: T) -> T
-where
-	T: FromStr + Debug,
-{
-	dotenvy::var(key)
-		.ok()
-		.and_then(|s| s.parse().ok())
-		.unwrap_or_else(|| {
-			info!("Using default {:?} value of {:?}", key, default);
-			default
-		})
+#[derive(Subcommand, Debug)]
+pub(super) enum DbCommand {
+	/// Run any migrations that haven't been run yet
+	Migrate,
+	/// Check the database for corruption and migrations that haven't been run
+	Check,
+	/// Copy the database to a new file, which is safe to do while webringer is serving
+	Backup { path: PathBuf },
+	/// Write every ring, site, admin and webhook out as JSON
+	Export {
+		/// Write to this file instead of stdout
+		#[arg(long)]
+		output: Option<PathBuf>,
+	},
+	/// Add everything in a JSON export that isn't already in the database
+	Import { path: PathBuf },
 }
@@ -0,0 +1,402 @@
+use std::path::Path;
+
+use axum_login::AuthUser;
+use webringer::ring::{
+	RingError, RingState, SiteProfile,
+	email::Mailer,
+	export::Export,
+	importer::{ImportFormat, parse_import},
+};
+
+use crate::args::{AdminCommand, DbCommand, SiteCommand, SiteStatus};
+use crate::config::Config;
+use crate::database;
+
+/// Connects to the database, running any migrations that are needed, for commands that work
+/// through the [`RingState`] API
+pub(super) async fn ring_state(config: &Config) -> Result<RingState, String> {
+	let pool = database::connect(&config.database)
+		.await
+		.map_err(|e| format!("Could not connect to the database: {e}"))?;
+	database::migrator(&config.database.url)
+		.run(&pool)
+		.await
+		.map_err(|e| format!("Could not run migrations: {e}"))?;
+	let mailer = Mailer::new(
+		config.email.smtp_url.as_deref(),
+		&config.email.from,
+		&config.base_url(),
+	)
+	.map_err(|e| e.to_string())?;
+	Ok(RingState::new(pool).with_mailer(mailer))
+}
+
+/// Reads a password from the terminal, twice to make sure it was typed right, unless one was
+/// `given`
+fn read_password(given: Option<String>) -> Result<String, String> {
+	if let Some(password) = given {
+		return Ok(password);
+	}
+	let password = rpassword::prompt_password("Password: ")
+		.map_err(|e| format!("Could not read the password: {e}"))?;
+	let confirm_password = rpassword::prompt_password("Confirm password: ")
+		.map_err(|e| format!("Could not read the password: {e}"))?;
+	if password.is_empty() {
+		Err("The password can't be empty".to_owned())
+	} else if password != confirm_password {
+		Err("Passwords do not match".to_owned())
+	} else {
+		Ok(password)
+	}
+}
+
+pub(super) async fn run_admin(state: &RingState, command: AdminCommand) -> Result<(), String> {
+	match command {
+		AdminCommand::Create {
+			username,
+			email,
+			password,
+			ring,
+		} => {
+			let ring_id = match ring {
+				Some(slug) => Some(scoped(state, Some(slug.as_str())).await?.ring().id),
+				None => None,
+			};
+			let password = read_password(password)?;
+			match state
+				.add_admin(username.clone(), email, password, ring_id)
+				.await
+			{
+				Ok(()) => {
+					println!("Added admin {username}");
+					Ok(())
+				}
+				Err(RingError::UniqueRowAlreadyPresent(_values)) => {
+					Err("That username or email is already taken".to_owned())
+				}
+				Err(e) => Err(e.to_string()),
+			}
+		}
+		AdminCommand::List => {
+			let admins = state.get_list_admins().await.map_err(|e| e.to_string())?;
+			let rings = state.get_list_rings().await.map_err(|e| e.to_string())?;
+			for admin in admins {
+				let ring = match admin.ring_id {
+					Some(ring_id) => rings
+						.iter()
+						.find(|ring| ring.id == ring_id)
+						.map_or("?", |ring| ring.slug.as_str()),
+					None => "*",
+				};
+				println!(
+					"{}\t{}\t{}\t{}",
+					admin.id(),
+					admin.username,
+					admin.email,
+					ring
+				);
+			}
+			Ok(())
+		}
+		AdminCommand::Delete { username } => {
+			let admin = get_admin(state, &username).await?;
+			match state.delete_admin(admin.id()).await {
+				Ok(()) => {
+					println!("Deleted admin {username}");
+					Ok(())
+				}
+				Err(RingError::RowInUse(_admin)) => Err(format!(
+					"{username} has approved or denied sites, so can't be deleted"
+				)),
+				Err(e) => Err(e.to_string()),
+			}
+		}
+		AdminCommand::ResetPassword { username, password } => {
+			let admin = get_admin(state, &username).await?;
+			let password = read_password(password)?;
+			state
+				.reset_password(admin.id(), password)
+				.await
+				.map_err(|e| e.to_string())?;
+			println!("Reset the password of {username}");
+			Ok(())
+		}
+	}
+}
+
+async fn get_admin(
+	state: &RingState,
+	username: &str,
+) -> Result<webringer::ring::auth::Admin, String> {
+	match state.get_admin_by_username(username).await {
+		Ok(admin) => Ok(admin),
+		Err(RingError::RowNotFound(_query)) => Err(format!("There's no admin called {username}")),
+		Err(e) => Err(e.to_string()),
+	}
+}
+
+/// Scopes `state` to the ring with the short name `slug`, or the default ring
+async fn scoped(state: &RingState, slug: Option<&str>) -> Result<RingState, String> {
+	let scoped = match slug {
+		Some(slug) => state.for_ring(slug).await,
+		None => state.for_default_ring().await,
+	};
+	match scoped {
+		Ok(state) => Ok(state),
+		Err(RingError::RowNotFound(_query)) => Err(format!(
+			"There's no ring called {}",
+			slug.unwrap_or("default")
+		)),
+		Err(e) => Err(e.to_string()),
+	}
+}
+
+pub(super) async fn run_site(
+	state: &RingState,
+	ring: Option<&str>,
+	command: SiteCommand,
+) -> Result<(), String> {
+	let state = scoped(state, ring).await?;
+	match command {
+		SiteCommand::List { status } => {
+			let shown = |shown: SiteStatus| status.is_none_or(|status| status == shown);
+			if shown(SiteStatus::Pending) {
+				for site in state.get_list_unapproved().await.map_err(|e| e.to_string())? {
+					let verified = if site.email_verified_at.is_some() {
+						"verified"
+					} else {
+						"unverified"
+					};
+					println!(
+						"pending\t{}\t{}\t{}\t{verified}",
+						site.id, site.root_url, site.email
+					);
+				}
+			}
+			if shown(SiteStatus::Approved) {
+				for site in state
+					.get_list_approved(None)
+					.await
+					.map_err(|e| e.to_string())?
+				{
+					println!(
+						"approved\t{}\t{}\t{}\t{}",
+						site.site_id, site.root_url, site.site_email, site.admin_username
+					);
+				}
+			}
+			if shown(SiteStatus::Denied) {
+				for site in state.get_list_denied().await.map_err(|e| e.to_string())? {
+					println!(
+						"denied\t{}\t{}\t{}\t{}",
+						site.site_id, site.root_url, site.site_email, site.reason
+					);
+				}
+			}
+			Ok(())
+		}
+		SiteCommand::Add {
+			url,
+			email,
+			title,
+			description,
+		} => {
+			let profile = SiteProfile {
+				title,
+				description,
+				..SiteProfile::default()
+			}
+			.normalised();
+			match state.add_site(&url, &email, &profile).await {
+				Ok(()) => {
+					println!("Added {url}, which is waiting for approval");
+					Ok(())
+				}
+				Err(RingError::UniqueRowAlreadyPresent(_url)) => {
+					Err(format!("{url} has already been added"))
+				}
+				Err(e) => Err(e.to_string()),
+			}
+		}
+		SiteCommand::Approve { url, admin } => {
+			let admin = get_admin(&state, &admin).await?;
+			ensure_pending(&state, &url).await?;
+			state
+				.approve_site(&url, admin.id())
+				.await
+				.map_err(|e| e.to_string())?;
+			println!("Approved {url}");
+			Ok(())
+		}
+		SiteCommand::Deny { url, admin, reason } => {
+			let admin = get_admin(&state, &admin).await?;
+			ensure_pending(&state, &url).await?;
+			state
+				.deny_site(&url, &reason, admin.id())
+				.await
+				.map_err(|e| e.to_string())?;
+			println!("Denied {url}");
+			Ok(())
+		}
+		SiteCommand::Remove { url } => match state.remove_site(&url).await {
+			Ok(()) => {
+				println!("Removed {url}");
+				Ok(())
+			}
+			Err(RingError::RowNotFound(_url)) => Err(format!("{url} isn't in the ring")),
+			Err(e) => Err(e.to_string()),
+		},
+		SiteCommand::Import {
+			path,
+			admin,
+			format,
+			pending,
+			dry_run,
+		} => import_sites(&state, &path, &admin, format, pending, dry_run).await,
+	}
+}
+
+/// Previews importing the sites listed in the file at `path` into the ring, then imports them
+/// unless it's a `dry_run`
+async fn import_sites(
+	state: &RingState,
+	path: &Path,
+	admin: &str,
+	format: Option<ImportFormat>,
+	pending: bool,
+	dry_run: bool,
+) -> Result<(), String> {
+	let admin = get_admin(state, admin).await?;
+	let input = std::fs::read_to_string(path)
+		.map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+	let format = format.unwrap_or_else(|| ImportFormat::detect(&input));
+	let sites = match parse_import(format, &input) {
+		Ok(sites) => sites,
+		Err(RingError::InvalidInput(problem)) => return Err(problem),
+		Err(e) => return Err(e.to_string()),
+	};
+	let preview = state
+		.preview_import(sites.clone())
+		.await
+		.map_err(|e| e.to_string())?;
+	for entry in &preview {
+		println!(
+			"{}\t{}\t{}",
+			entry.status,
+			entry.site.url,
+			entry.site.profile.title.as_deref().unwrap_or_default()
+		);
+	}
+	if dry_run {
+		return Ok(());
+	}
+	let added = state
+		.import_sites(sites, admin.id(), &admin.email, !pending)
+		.await
+		.map_err(|e| e.to_string())?;
+	println!("Imported {added} of {} sites", preview.len());
+	Ok(())
+}
+
+/// Makes sure `url` is waiting for approval, as approving or denying anything else does nothing
+async fn ensure_pending(state: &RingState, url: &str) -> Result<(), String> {
+	let pending = state
+		.get_list_unapproved()
+		.await
+		.map_err(|e| e.to_string())?;
+	if pending.iter().any(|site| site.root_url == url) {
+		Ok(())
+	} else {
+		Err(format!("{url} isn't waiting for approval"))
+	}
+}
+
+pub(super) async fn run_db(config: &Config, command: DbCommand) -> Result<(), String> {
+	let pool = database::connect(&config.database)
+		.await
+		.map_err(|e| format!("Could not connect to the database: {e}"))?;
+	match command {
+		DbCommand::Migrate => {
+			database::migrator(&config.database.url)
+				.run(&pool)
+				.await
+				.map_err(|e| format!("Could not run migrations: {e}"))?;
+			println!("The database is up to date");
+			Ok(())
+		}
+		DbCommand::Check => {
+			let problems = database::check(&pool, &config.database.url)
+				.await
+				.map_err(|e| format!("Could not check the database: {e}"))?;
+			if problems.is_empty() {
+				println!("No problems found");
+				Ok(())
+			} else {
+				for problem in &problems {
+					println!("{problem}");
+				}
+				Err(format!("Found {} problems", problems.len()))
+			}
+		}
+		DbCommand::Backup { path } => {
+			database::backup(&config.database.url, &path).await?;
+			println!("Backed up the database to {}", path.display());
+			Ok(())
+		}
+		DbCommand::Export { output } => {
+			let export = ring_state(config)
+				.await?
+				.export()
+				.await
+				.map_err(|e| e.to_string())?;
+			let json = serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?;
+			if export.signing_key.is_some() {
+				eprintln!(
+					"The export holds the instance's signing key, anyone with it can sign manifests as \
+					this instance, so keep it secret"
+				);
+			}
+			match output {
+				Some(path) => {
+					std::fs::write(&path, json)
+						.map_err(|e| format!("Could not write {}: {e}", path.display()))?;
+					eprintln!("Exported the database to {}", path.display());
+				}
+				None => println!("{json}"),
+			}
+			Ok(())
+		}
+		DbCommand::Import { path } => {
+			let json = std::fs::read_to_string(&path)
+				.map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+			let export: Export = serde_json::from_str(&json)
+				.map_err(|e| format!("{} isn't a valid export: {e}", path.display()))?;
+			let summary = match ring_state(config).await?.import(&export).await {
+				Ok(summary) => summary,
+				Err(RingError::InvalidInput(problem)) => return Err(problem),
+				Err(RingError::UniqueRowAlreadyPresent(value)) => {
+					return Err(format!("{value} clashes with something already in the database"));
+				}
+				Err(e) => return Err(e.to_string()),
+			};
+			println!(
+				"Added {} rings, {} admins, {} sites, {} linked rings and {} webhooks",
+				summary.rings_added,
+				summary.admins_added,
+				summary.sites_added,
+				summary.peers_added,
+				summary.webhooks_added
+			);
+			if summary.signing_key_imported {
+				println!("This instance now signs its manifests with the imported signing key");
+			}
+			if summary.admins_skipped + summary.sites_skipped > 0 {
+				println!(
+					"Skipped {} admins and {} sites that were already there",
+					summary.admins_skipped, summary.sites_skipped
+				);
+			}
+			Ok(())
+		}
+	}
+}
@@ -1,41 +1,64 @@
-use std::time::Duration;
+use std::{
+	ffi::{CStr, CString},
+	path::Path,
+	ptr,
+	str::FromStr,
+	time::Duration,
+};
 
-use crate::args::read_env_var;
-use sqlx::SqlitePool;
+use crate::config::DatabaseConfig;
+use libsqlite3_sys as ffi;
+use sqlx::AnyPool;
+use sqlx::any::AnyPoolOptions;
 use sqlx::migrate::Migrator;
-use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::sqlite::SqliteConnectOptions;
 use tracing::{Instrument, error, info_span, trace};
 
-static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+pub(super) static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+pub(super) static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
 
-pub(super) async fn get_db_pool() -> SqlitePool {
-	let db_url: String = read_env_var("DATABASE_URL", "sqlite://data.db".to_string());
+/// Whether the database url is for a Postgres server rather than a sqlite file
+pub(super) fn is_postgres(db_url: &str) -> bool {
+	db_url.starts_with("postgres://") || db_url.starts_with("postgresql://")
+}
+
+/// The migrations for the kind of database the url is for
+pub(super) fn migrator(db_url: &str) -> &'static Migrator {
+	if is_postgres(db_url) {
+		&POSTGRES_MIGRATOR
+	} else {
+		&MIGRATOR
+	}
+}
+
+/// Connects to the database, creating it if it's a sqlite file that doesn't exist, without running
+/// any migrations
+///
+/// The kind of database is picked from the scheme of the url, `sqlite://` or `postgres://`.
+pub(super) async fn connect(config: &DatabaseConfig) -> Result<AnyPool, sqlx::Error> {
+	let db_url = &config.url;
+	sqlx::any::install_default_drivers();
 
 	if db_url.starts_with("sqlite://") {
 		let path = db_url.trim_start_matches("sqlite://");
 		if !std::path::Path::new(path).exists() {
-			std::fs::File::create(path).expect("Failed to create database file");
+			std::fs::File::create(path)?;
 		}
 	}
 
-	let min_connections = read_env_var("MIN_CONNECTIONS", 5);
-	let max_connections = read_env_var("MAX_CONNECTIONS", 5);
-	let acquire_timeout = Duration::from_secs(read_env_var("ACQUIRE_TIMEOUT_SECS", 10u64));
-	let idle_timeout = Some(Duration::from_secs(read_env_var(
-		"IDLE_TIMEOUT_SECS",
-		300u64,
-	)));
-
-	let pool = match SqlitePoolOptions::new()
-		.min_connections(min_connections)
-		.max_connections(max_connections)
-		.acquire_timeout(acquire_timeout) // 10 seconds
-		.idle_timeout(idle_timeout) // 5 minutes
+	AnyPoolOptions::new()
+		.min_connections(config.min_connections)
+		.max_connections(config.max_connections)
+		.acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+		.idle_timeout(Some(Duration::from_secs(config.idle_timeout_secs)))
 		.max_lifetime(None)
-		.connect(&db_url)
+		.connect(db_url)
 		.instrument(info_span!("Create Database"))
 		.await
-	{
+}
+
+pub(super) async fn get_db_pool(config: &DatabaseConfig) -> AnyPool {
+	let pool = match connect(config).await {
 		Ok(db_pool) => db_pool,
 		Err(e) => {
 			error!("Could not connect to database: {}", e);
@@ -44,7 +67,259 @@ pub(super) async fn get_db_pool() -> SqlitePool {
 	};
 
 	trace!("Running migrations");
-	MIGRATOR.run(&pool).await.expect("Could not run migrations");
+	migrator(&config.url)
+		.run(&pool)
+		.await
+		.expect("Could not run migrations");
 
 	pool
 }
+
+/// Looks for corruption, broken foreign keys, and migrations that haven't been run or have
+/// changed since they were, returning a description of each problem found
+///
+/// Postgres keeps its own foreign keys intact, so only the migrations are checked there.
+pub(super) async fn check(pool: &AnyPool, db_url: &str) -> Result<Vec<String>, sqlx::Error> {
+	let mut problems = Vec::new();
+	if !is_postgres(db_url) {
+		check_sqlite(pool, &mut problems).await?;
+	}
+
+	let migrator = migrator(db_url);
+	let applied: Vec<(i64, Vec<u8>, i64)> = match sqlx::query_as(
+		"SELECT version, checksum, CASE WHEN success THEN 1 ELSE 0 END FROM _sqlx_migrations",
+	)
+	.fetch_all(pool)
+	.await
+	{
+		Ok(applied) => applied,
+		// The database has never been migrated
+		Err(sqlx::Error::Database(ref e))
+			if e.message().contains("no such table") || e.code().as_deref() == Some("42P01") =>
+		{
+			Vec::new()
+		}
+		Err(e) => return Err(e),
+	};
+	for migration in migrator.iter() {
+		let name = format!("{} {}", migration.version, migration.description);
+		match applied.iter().find(|(version, ..)| *version == migration.version) {
+			None => problems.push(format!("Migration {name} hasn't been run")),
+			Some((_version, _checksum, 0)) => {
+				problems.push(format!("Migration {name} failed"));
+			}
+			Some((_version, checksum, _success)) if *checksum != *migration.checksum => {
+				problems.push(format!("Migration {name} has changed since it was run"));
+			}
+			Some(_) => {}
+		}
+	}
+	for (version, ..) in &applied {
+		if !migrator.iter().any(|migration| migration.version == *version) {
+			problems.push(format!(
+				"Migration {version} was run by a newer version of webringer"
+			));
+		}
+	}
+
+	Ok(problems)
+}
+
+/// Adds any corruption or broken foreign keys in a sqlite database to `problems`
+async fn check_sqlite(pool: &AnyPool, problems: &mut Vec<String>) -> Result<(), sqlx::Error> {
+	let integrity: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+		.fetch_all(pool)
+		.await?;
+	if integrity != ["ok"] {
+		problems.extend(integrity.into_iter().map(|problem| format!("Integrity: {problem}")));
+	}
+
+	let broken_keys: Vec<(String, Option<i64>, String, i64)> =
+		sqlx::query_as("PRAGMA foreign_key_check")
+			.fetch_all(pool)
+			.await?;
+	for (table, row_id, parent, _key_id) in broken_keys {
+		problems.push(format!(
+			"Row {} of {table} refers to a row of {parent} that doesn't exist",
+			row_id.map_or_else(|| "?".to_owned(), |id| id.to_string())
+		));
+	}
+
+	Ok(())
+}
+
+/// How many times a backup is retried while another connection holds a lock it needs
+const BACKUP_ATTEMPTS: u32 = 50;
+
+/// Copies the whole sqlite database to a new file at `destination` with the online backup API of
+/// sqlite
+///
+/// This only holds a read lock on the database, so it's safe to run while webringer is serving.
+pub(super) async fn backup(db_url: &str, destination: &Path) -> Result<(), String> {
+	if is_postgres(db_url) {
+		return Err("Postgres databases are backed up with pg_dump".to_owned());
+	}
+	if destination.exists() {
+		return Err(format!("{} already exists", destination.display()));
+	}
+	let source = SqliteConnectOptions::from_str(db_url)
+		.ok()
+		.and_then(|options| CString::new(options.get_filename().to_str()?).ok())
+		.ok_or_else(|| format!("{db_url} isn't a valid sqlite database url"))?;
+	let destination = destination
+		.to_str()
+		.and_then(|path| CString::new(path).ok())
+		.ok_or_else(|| format!("{} isn't a valid path", destination.display()))?;
+
+	// The backup blocks, sleeping between attempts while other connections hold locks it needs
+	tokio::task::spawn_blocking(move || backup_blocking(&source, &destination))
+		.await
+		.map_err(|e| e.to_string())?
+}
+
+fn backup_blocking(source: &CStr, destination: &CStr) -> Result<(), String> {
+	// SAFETY: both connections are only used inside this block, and are closed before it ends
+	// whatever happens, as is the backup.
+	unsafe {
+		let source_db = open(source, ffi::SQLITE_OPEN_READONLY)
+			.map_err(|e| format!("Could not open the database: {e}"))?;
+		let destination_db = match open(
+			destination,
+			ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE,
+		) {
+			Ok(destination_db) => destination_db,
+			Err(e) => {
+				ffi::sqlite3_close(source_db);
+				return Err(format!("Could not open the backup file: {e}"));
+			}
+		};
+
+		let backup = ffi::sqlite3_backup_init(
+			destination_db,
+			c"main".as_ptr(),
+			source_db,
+			c"main".as_ptr(),
+		);
+		let result = if backup.is_null() {
+			Err(format!(
+				"Could not start the backup: {}",
+				error_message(destination_db)
+			))
+		} else {
+			let mut attempts = 0;
+			let rc = loop {
+				match ffi::sqlite3_backup_step(backup, -1) {
+					ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if attempts < BACKUP_ATTEMPTS => {
+						attempts += 1;
+						trace!("The database is locked, retrying the backup");
+						ffi::sqlite3_sleep(100);
+					}
+					rc => break rc,
+				}
+			};
+			ffi::sqlite3_backup_finish(backup);
+			if rc == ffi::SQLITE_DONE {
+				Ok(())
+			} else {
+				Err(format!(
+					"The backup failed: {}",
+					error_message(destination_db)
+				))
+			}
+		};
+		ffi::sqlite3_close(destination_db);
+		ffi::sqlite3_close(source_db);
+		result
+	}
+}
+
+/// Opens a connection to the sqlite database at `path`
+///
+/// # Safety
+/// The connection returned must be closed with `sqlite3_close`
+unsafe fn open(path: &CStr, flags: i32) -> Result<*mut ffi::sqlite3, String> {
+	let mut db = ptr::null_mut();
+	// SAFETY: `path` is a valid string, and `db` is closed again if it couldn't be opened
+	unsafe {
+		if ffi::sqlite3_open_v2(path.as_ptr(), &raw mut db, flags, ptr::null()) == ffi::SQLITE_OK {
+			Ok(db)
+		} else {
+			let e = error_message(db);
+			ffi::sqlite3_close(db);
+			Err(e)
+		}
+	}
+}
+
+/// The message for the most recent error on `db`
+///
+/// # Safety
+/// `db` must be null or a connection that hasn't been closed
+unsafe fn error_message(db: *mut ffi::sqlite3) -> String {
+	// SAFETY: sqlite3_errmsg accepts null and open connections, and always returns a valid string
+	unsafe { std::ffi::CStr::from_ptr(ffi::sqlite3_errmsg(db)) }
+		.to_string_lossy()
+		.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	async fn connect_to(path: &Path) -> (String, AnyPool) {
+		let config = DatabaseConfig {
+			url: format!("sqlite://{}", path.display()),
+			..DatabaseConfig::default()
+		};
+		let pool = connect(&config).await.unwrap();
+		(config.url, pool)
+	}
+
+	#[tokio::test]
+	async fn backs_up_a_database_in_use() {
+		let dir = std::env::temp_dir().join(format!("webringer-backup-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let (url, pool) = connect_to(&dir.join("live.db")).await;
+		MIGRATOR.run(&pool).await.unwrap();
+		sqlx::query("INSERT INTO rings (slug, name) VALUES ('art', 'Art')")
+			.execute(&pool)
+			.await
+			.unwrap();
+		// Another connection reading while the backup runs
+		let mut reader = pool.begin().await.unwrap();
+		let rings: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM rings")
+			.fetch_one(&mut *reader)
+			.await
+			.unwrap();
+
+		let copy = dir.join("copy.db");
+		backup(&url, &copy).await.unwrap();
+		assert!(
+			backup(&url, &copy)
+				.await
+				.unwrap_err()
+				.contains("already exists")
+		);
+		reader.rollback().await.unwrap();
+
+		let (copy_url, copy_pool) = connect_to(&copy).await;
+		assert_eq!(
+			check(&copy_pool, &copy_url).await.unwrap(),
+			Vec::<String>::new()
+		);
+		let copied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM rings")
+			.fetch_one(&copy_pool)
+			.await
+			.unwrap();
+		assert_eq!(copied, rings);
+		let name: String = sqlx::query_scalar("SELECT name FROM rings WHERE slug = 'art'")
+			.fetch_one(&copy_pool)
+			.await
+			.unwrap();
+		assert_eq!(name, "Art");
+
+		copy_pool.close().await;
+		pool.close().await;
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
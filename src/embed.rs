@@ -1,42 +1,222 @@
-use axum::{http::{self, header::CONTENT_TYPE, StatusCode}, response::{self, IntoResponse}};
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	io::Write,
+	sync::{Arc, LazyLock, Mutex, Once, PoisonError},
+};
+
+use axum::{
+	body::Bytes,
+	http::{self, HeaderMap, HeaderValue, StatusCode, header},
+	response::{self, IntoResponse},
+};
+use chrono::DateTime;
+use flate2::{Compression, write::GzEncoder};
+use mime_guess::mime;
+
+use crate::ring::sql;
+
+/// Fonts never change without being renamed, so browsers can keep them for a year
+const FONT_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// Everything else can change when webringer is updated, so browsers check again after an hour
+const CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// The compressed versions of every file that has been compressed so far, by the hash of its
+/// contents
+static COMPRESSED: LazyLock<Mutex<HashMap<[u8; 32], Arc<Compressed>>>> =
+	LazyLock::new(Mutex::default);
+/// Set once the embedded files have started being compressed in the background
+static PRECOMPRESS: Once = Once::new();
 
 #[derive(rust_embed::Embed)]
 #[folder = "static/"]
 struct Static;
 
-struct StaticFile<T>(pub T);
+/// An embedded file, along with the headers of the request for it
+struct StaticFile {
+	path: String,
+	request_headers: HeaderMap,
+}
 
-impl<T> IntoResponse for StaticFile<T>
-where
-	T: Into<String>,
-{
+impl IntoResponse for StaticFile {
 	fn into_response(self) -> response::Response {
-		let path: String = self.0.into();
-
-		match Static::get(path.as_str()) {
-			Some(content) => {
-				let mime = mime_guess::from_path(path).first_or_octet_stream();
-				(
-					[(CONTENT_TYPE, mime.as_ref())],
-					content.data,
-				)
-					.into_response()
+		let Some(content) = Static::get(&self.path) else {
+			return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+		};
+		let mime = mime_guess::from_path(&self.path).first_or_octet_stream();
+		let hash = content.metadata.sha256_hash();
+		let mut body = match content.data {
+			Cow::Borrowed(data) => Bytes::from_static(data),
+			Cow::Owned(data) => Bytes::from(data),
+		};
+		let mut headers = HeaderMap::new();
+		let mut encoding = None;
+		if is_compressible(&mime) {
+			headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+			// Until the file has been compressed in the background, it's sent as it is
+			if let Some((name, compressed)) = Compressed::get(hash)
+				.and_then(|compressed| compressed.negotiate(&self.request_headers))
+			{
+				encoding = Some(name);
+				body = compressed;
 			}
-			None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
 		}
+
+		let etag = match encoding {
+			Some(encoding) => format!("\"{}-{encoding}\"", hex::encode(&hash[..16])),
+			None => format!("\"{}\"", hex::encode(&hash[..16])),
+		};
+		let last_modified = content
+			.metadata
+			.last_modified()
+			.and_then(|secs| DateTime::from_timestamp(i64::try_from(secs).ok()?, 0));
+		let cache_control = if self.path.starts_with("fonts/") {
+			FONT_CACHE_CONTROL
+		} else {
+			CACHE_CONTROL
+		};
+		headers.insert(
+			header::CACHE_CONTROL,
+			HeaderValue::from_static(cache_control),
+		);
+		if let Ok(etag) = HeaderValue::from_str(&etag) {
+			headers.insert(header::ETAG, etag);
+		}
+		if let Some(last_modified) = last_modified.and_then(|date| {
+			HeaderValue::from_str(&date.format(sql::HTTP_DATE_FORMAT).to_string()).ok()
+		}) {
+			headers.insert(header::LAST_MODIFIED, last_modified);
+		}
+
+		let not_modified = match self.request_headers.get(header::IF_NONE_MATCH) {
+			Some(if_none_match) => if_none_match.to_str().is_ok_and(|tags| {
+				tags.split(',')
+					.map(|tag| tag.trim().trim_start_matches("W/"))
+					.any(|tag| tag == etag || tag == "*")
+			}),
+			None => self
+				.request_headers
+				.get(header::IF_MODIFIED_SINCE)
+				.and_then(|since| DateTime::parse_from_rfc2822(since.to_str().ok()?).ok())
+				.zip(last_modified)
+				.is_some_and(|(since, modified)| since.timestamp() >= modified.timestamp()),
+		};
+		if not_modified {
+			return (StatusCode::NOT_MODIFIED, headers).into_response();
+		}
+
+		if let Ok(content_type) = HeaderValue::from_str(mime.as_ref()) {
+			headers.insert(header::CONTENT_TYPE, content_type);
+		}
+		if let Some(encoding) = encoding {
+			headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+		}
+		(headers, body).into_response()
 	}
 }
 
-pub(super) async fn static_handler(uri: http::Uri) -> impl IntoResponse {
+/// Whether a file is text, which is worth compressing
+fn is_compressible(mime: &mime::Mime) -> bool {
+	mime.type_() == mime::TEXT
+		|| [mime::SVG, mime::JSON, mime::XML, mime::JAVASCRIPT].contains(&mime.subtype())
+}
+
+/// A file compressed with brotli and gzip, each only kept if it's smaller than the file
+struct Compressed {
+	brotli: Option<Bytes>,
+	gzip: Option<Bytes>,
+}
+
+impl Compressed {
+	fn new(data: &[u8]) -> Self {
+		let mut brotli = Vec::new();
+		let brotli = brotli::BrotliCompress(
+			&mut &data[..],
+			&mut brotli,
+			&brotli::enc::BrotliEncoderParams::default(),
+		)
+		.map(|_| brotli);
+		let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+		let gzip = gzip.write_all(data).and_then(|()| gzip.finish());
+		let smaller =
+			|compressed: Vec<u8>| (compressed.len() < data.len()).then(|| compressed.into());
+		Self {
+			brotli: brotli.ok().and_then(smaller),
+			gzip: gzip.ok().and_then(smaller),
+		}
+	}
+
+	/// The compressed versions of a file, if it has been compressed yet
+	fn get(hash: [u8; 32]) -> Option<Arc<Self>> {
+		COMPRESSED
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&hash)
+			.cloned()
+	}
+
+	/// The smallest version the client accepts, with the name of its encoding
+	fn negotiate(&self, request_headers: &HeaderMap) -> Option<(&'static str, Bytes)> {
+		let accept_encoding = request_headers.get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+		let accepts = |encoding: &str| {
+			accept_encoding.split(',').any(|accepted| {
+				let mut parts = accepted.split(';').map(str::trim);
+				let name = parts.next().unwrap_or_default();
+				let refused = parts.any(|param| {
+					param
+						.strip_prefix("q=")
+						.and_then(|q| q.parse::<f32>().ok())
+						.is_some_and(|q| q <= 0.0)
+				});
+				(name.eq_ignore_ascii_case(encoding) || name == "*") && !refused
+			})
+		};
+		match (&self.brotli, &self.gzip) {
+			(Some(brotli), _) if accepts("br") => Some(("br", brotli.clone())),
+			(_, Some(gzip)) if accepts("gzip") => Some(("gzip", gzip.clone())),
+			_ => None,
+		}
+	}
+}
+
+/// Starts compressing every embedded file that's worth it on another thread, the first time any
+/// of them is asked for
+///
+/// Brotli at its best quality takes most of a second for the logo, so it's done once for every
+/// file, away from the threads serving requests.
+fn start_precompressing() {
+	PRECOMPRESS.call_once(|| {
+		std::thread::spawn(precompress);
+	});
+}
+
+fn precompress() {
+	for path in Static::iter() {
+		if let Some(content) = Static::get(&path)
+			&& is_compressible(&mime_guess::from_path(path.as_ref()).first_or_octet_stream())
+		{
+			let compressed = Arc::new(Compressed::new(&content.data));
+			COMPRESSED
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner)
+				.insert(content.metadata.sha256_hash(), compressed);
+		}
+	}
+}
+
+pub(crate) async fn static_handler(
+	uri: http::Uri,
+	request_headers: HeaderMap,
+) -> impl IntoResponse {
+	start_precompressing();
 	let mut path = uri.path().trim_start_matches('/').to_string();
 
 	if path.starts_with("static/") {
 		path = path.replace("static/", "");
 	}
 
-	StaticFile(path)
-}
-
-pub(super) async fn not_found() -> impl IntoResponse {
-	static_handler(http::Uri::from_static("/static/404.html")).await
+	StaticFile {
+		path,
+		request_headers,
+	}
 }
@@ -1,5 +1,8 @@
 #![warn(clippy::pedantic, clippy::all, clippy::cargo)]
 #![allow(clippy::module_name_repetitions, clippy::unused_async, clippy::multiple_crate_versions)]
 
+pub mod app;
+mod embed;
 pub mod ring;
 pub mod site;
+pub mod theme;
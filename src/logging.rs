@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+use axum::{body::Body, http::Request};
+use serde::{Deserialize, Serialize};
+use tower_http::request_id::RequestId;
+use tracing::{Span, info_span};
+use tracing_appender::{
+	non_blocking::WorkerGuard,
+	rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+	EnvFilter, Layer, Registry, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+use crate::config::LogConfig;
+
+/// How each log line is written
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum LogFormat {
+	/// One human readable line per event
+	Text,
+	/// Human readable events spread over several lines, for development
+	Pretty,
+	/// One JSON object per event
+	Json,
+}
+
+impl FromStr for LogFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"text" => Ok(Self::Text),
+			"pretty" => Ok(Self::Pretty),
+			"json" => Ok(Self::Json),
+			_ => Err(format!("Unknown log format {s:?}, expected text, pretty or json")),
+		}
+	}
+}
+
+/// How often a new log file is started
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum LogRotation {
+	Minutely,
+	Hourly,
+	Daily,
+	Never,
+}
+
+impl FromStr for LogRotation {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"minutely" => Ok(Self::Minutely),
+			"hourly" => Ok(Self::Hourly),
+			"daily" => Ok(Self::Daily),
+			"never" => Ok(Self::Never),
+			_ => Err(format!(
+				"Unknown log rotation {s:?}, expected minutely, hourly, daily or never"
+			)),
+		}
+	}
+}
+
+impl From<LogRotation> for Rotation {
+	fn from(rotation: LogRotation) -> Self {
+		match rotation {
+			LogRotation::Minutely => Self::MINUTELY,
+			LogRotation::Hourly => Self::HOURLY,
+			LogRotation::Daily => Self::DAILY,
+			LogRotation::Never => Self::NEVER,
+		}
+	}
+}
+
+fn format_layer<W>(
+	format: LogFormat,
+	writer: W,
+	ansi: bool,
+) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+	W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+	let layer = tracing_subscriber::fmt::layer()
+		.with_writer(writer)
+		.with_ansi(ansi);
+	match format {
+		LogFormat::Text => layer.boxed(),
+		LogFormat::Pretty => layer.pretty().boxed(),
+		LogFormat::Json => layer.json().boxed(),
+	}
+}
+
+/// Sets up logging to stdout, and to rolling files in the configured directory if there is one
+///
+/// This has to run before anything is logged. Problems with the log directory are printed to
+/// stderr as there's nowhere to log them yet. The returned guard flushes the log files when
+/// dropped, so it has to be kept until the program exits.
+pub(super) fn init(config: &LogConfig) -> Option<WorkerGuard> {
+	let filter = EnvFilter::try_new(&config.filter).unwrap_or_else(|e| {
+		eprintln!("Invalid log filter {:?}, using info: {e}", config.filter);
+		EnvFilter::new("info")
+	});
+
+	let mut layers = vec![format_layer(config.format, std::io::stdout, true)];
+	let guard = match &config.dir {
+		Some(dir) => match RollingFileAppender::builder()
+			.rotation(config.rotation.into())
+			.filename_prefix("webringer")
+			.filename_suffix("log")
+			.build(dir)
+		{
+			Ok(appender) => {
+				let (writer, guard) = tracing_appender::non_blocking(appender);
+				layers.push(format_layer(config.format, writer, false));
+				Some(guard)
+			}
+			Err(e) => {
+				eprintln!("Could not log to {}: {e}", dir.display());
+				None
+			}
+		},
+		None => None,
+	};
+
+	tracing_subscriber::registry()
+		.with(layers)
+		.with(filter)
+		.init();
+	guard
+}
+
+/// The span every request is handled in, tagged with its `x-request-id`
+///
+/// Only the path is recorded, as query strings can hold login and verification tokens.
+pub(super) fn request_span(request: &Request<Body>) -> Span {
+	let request_id = request
+		.extensions()
+		.get::<RequestId>()
+		.and_then(|id| id.header_value().to_str().ok())
+		.unwrap_or_default();
+	info_span!(
+		"request",
+		id = request_id,
+		method = %request.method(),
+		path = request.uri().path(),
+	)
+}
@@ -1,60 +1,111 @@
 #![warn(clippy::pedantic, clippy::all, clippy::cargo)]
 #![allow(clippy::module_name_repetitions, clippy::multiple_crate_versions)]
-use axum::{
-	Router,
-	routing::{get, post},
-};
-use axum_login::AuthManagerLayerBuilder;
-use axum_login::tower_sessions::{MemoryStore, SessionManagerLayer};
-use axum_messages::MessagesManagerLayer;
+use axum::Router;
+#[cfg(feature = "metrics")]
+use axum::routing::get;
 use clap::Parser;
 use tokio::signal;
+use tower_http::{
+	request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+	trace::TraceLayer,
+};
 use tracing::{Instrument, error, info, info_span, instrument, warn};
 
+use webringer::app::WebringerApp;
 use webringer::ring;
+use webringer::theme::Theme;
+#[cfg(feature = "metrics")]
 use webringer::site;
 
 mod args;
+mod cli;
+mod config;
 mod database;
-mod embed;
+mod logging;
 
 #[tokio::main(flavor = "current_thread")]
 #[instrument]
 async fn main() {
-	tracing_subscriber::fmt::init();
 	let args = args::Args::parse();
 
-	if let Err(e) = dotenvy::dotenv() {
-		warn!("No .env file found: {}", e);
+	let dotenv = dotenvy::dotenv();
+	let config = match config::Config::load(&args) {
+		Ok(config) => config,
+		Err(errors) => {
+			for e in errors {
+				eprintln!("Configuration error: {e}");
+			}
+			std::process::exit(1)
+		}
+	};
+	if args.print_config {
+		match config.to_toml() {
+			Ok(toml) => print!("{toml}"),
+			Err(e) => {
+				eprintln!("Could not print the configuration: {e}");
+				std::process::exit(1)
+			}
+		}
+		return;
+	}
+
+	let result = match args.command.unwrap_or(args::Command::Serve) {
+		args::Command::Serve => {
+			let _log_guard = logging::init(&config.log);
+			if let Err(e) = dotenv {
+				warn!("No .env file found: {}", e);
+			}
+			serve(config).await;
+			Ok(())
+		}
+		args::Command::Admin(command) => match cli::ring_state(&config).await {
+			Ok(state) => cli::run_admin(&state, command).await,
+			Err(e) => Err(e),
+		},
+		args::Command::Site { ring, command } => match cli::ring_state(&config).await {
+			Ok(state) => cli::run_site(&state, ring.as_deref(), command).await,
+			Err(e) => Err(e),
+		},
+		args::Command::Db(command) => cli::run_db(&config, command).await,
+	};
+	if let Err(e) = result {
+		eprintln!("Error: {e}");
+		std::process::exit(1)
 	}
+}
+
+/// Serves the webring site until SIGINT
+async fn serve(config: config::Config) {
+	let address = format!("{}:{}", config.address, config.port);
 
-	let address = format!("{}:{}", args.address, args.port);
+	let db_pool = database::get_db_pool(&config.database);
 
-	let db_pool = database::get_db_pool();
+	if config.email.smtp_url.is_none() {
+		warn!("No SMTP_URL set, emails won't be sent");
+	}
+	let mailer = match ring::email::Mailer::new(
+		config.email.smtp_url.as_deref(),
+		&config.email.from,
+		&config.base_url(),
+	) {
+		Ok(mailer) => mailer,
+		Err(e) => {
+			error!("Could not configure email: {}", e);
+			panic!()
+		}
+	};
+	let backend = ring::RingState::new(db_pool.await)
+		.with_mailer(mailer)
+		.with_theme(theme(&config.theme));
 
-	let session_store = MemoryStore::default();
-	let session_layer = SessionManagerLayer::new(session_store);
-	let backend = ring::RingState::new(db_pool.await);
-	let auth_layer = AuthManagerLayerBuilder::new(backend.clone(), session_layer).build();
+	backend.spawn_workers(&config.workers.intervals());
 
-	let router = Router::new()
-		.route("/", get(site::index))
-		.route("/join", get(site::join::get))
-		.route("/join", post(site::join::post))
-		.route("/leave", get(site::leave::get))
-		.route("/leave", post(site::leave::post))
-		.route("/next", get(site::ring::next))
-		.route("/prev", get(site::ring::prev))
-		.route("/random", get(site::ring::random))
-		.route("/list", get(site::ring::list))
-		.route("/login", get(site::login::get))
-		.route("/login", post(site::login::post))
-		.with_state(backend.clone())
-		.nest("/admin", site::admin::router(backend))
-		.layer(MessagesManagerLayer)
-		.layer(auth_layer)
-		.route("/static/{*file}", get(embed::static_handler))
-		.fallback_service(get(embed::not_found));
+	let router = WebringerApp::new(backend.clone())
+		.into_router()
+		.layer(PropagateRequestIdLayer::x_request_id())
+		.layer(TraceLayer::new_for_http().make_span_with(logging::request_span))
+		.layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
+	let router = with_metrics(router, backend.clone(), config.metrics.enabled);
 
 	info!("Binding to {}", address);
 	let listener = match tokio::net::TcpListener::bind(address)
@@ -79,4 +130,54 @@ async fn main() {
 	{
 		error!("Axum serving error: {}", e);
 	}
+	// Don't lose the navigation counted since the last flush
+	if let Err(e) = backend.flush_navigation().await {
+		error!("Could not write the last navigation stats: {}", e);
+	}
+}
+
+/// The theme set up in the configuration
+fn theme(config: &config::ThemeConfig) -> Theme {
+	let mut theme = Theme::default();
+	if let Some(dir) = &config.dir {
+		info!("Using the theme in {}", dir.display());
+		theme = theme.with_dir(dir);
+	}
+	if let Some(name) = &config.name {
+		theme = theme.with_name(name);
+	}
+	if let Some(logo) = &config.logo {
+		theme = theme.with_logo(logo);
+	}
+	if let Some(footer) = &config.footer {
+		theme = theme.with_footer(footer);
+	}
+	theme
+}
+
+/// Serves Prometheus metrics at `/metrics` and tracks every request, if they're `enabled`
+#[cfg(feature = "metrics")]
+fn with_metrics(router: Router, backend: ring::RingState, enabled: bool) -> Router {
+	if !enabled {
+		return router;
+	}
+	let metrics = match site::metrics::Metrics::install(backend) {
+		Ok(metrics) => metrics,
+		Err(e) => {
+			error!("Could not install the metrics recorder: {}", e);
+			panic!()
+		}
+	};
+	info!("Serving metrics at /metrics");
+	router
+		.route("/metrics", get(site::metrics::render).with_state(metrics))
+		.layer(axum::middleware::from_fn(site::metrics::track_requests))
+}
+
+#[cfg(not(feature = "metrics"))]
+fn with_metrics(router: Router, _backend: ring::RingState, enabled: bool) -> Router {
+	if enabled {
+		warn!("Metrics are enabled, but webringer was built without the metrics feature");
+	}
+	router
 }
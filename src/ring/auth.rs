@@ -2,15 +2,18 @@
 
 use argon2::{
 	Argon2,
-	password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
+	password_hash::{
+		PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+		rand_core::{OsRng, RngCore},
+	},
 };
 use axum_login::{AuthUser, AuthnBackend, UserId};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use std::fmt::Debug;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, instrument};
 
-use super::{RingError, RingState};
+use super::{RingError, RingState, rings::Ring};
 
 pub(super) async fn hash_password(password_plaintext: String) -> Result<String, RingError> {
 	match tokio::task::spawn_blocking(move || {
@@ -34,12 +37,55 @@ pub(super) async fn hash_password(password_plaintext: String) -> Result<String,
 	}
 }
 
+/// Generates a random 256 bit token, hex encoded
+pub(super) fn generate_token() -> String {
+	let mut bytes = [0u8; 32];
+	OsRng.fill_bytes(&mut bytes);
+	hex::encode(bytes)
+}
+
 #[derive(Clone, Serialize, Deserialize, FromRow)]
 pub struct Admin {
-	id: i64,
+	pub(super) id: i64,
 	pub username: String,
 	pub email: String,
-	password_phc: String,
+	#[serde(skip_serializing)]
+	pub(super) password_phc: String,
+	/// The only ring this admin can manage, or `None` if they can manage every ring
+	pub ring_id: Option<i64>,
+}
+
+impl Admin {
+	/// Creates an [`Admin`], for a [`RingStore`](super::store::RingStore) that keeps admins
+	/// somewhere other than the database
+	#[must_use]
+	pub const fn new(
+		id: i64,
+		username: String,
+		email: String,
+		password_phc: String,
+		ring_id: Option<i64>,
+	) -> Self {
+		Self {
+			id,
+			username,
+			email,
+			password_phc,
+			ring_id,
+		}
+	}
+
+	/// Whether this admin can manage every ring on the instance
+	#[must_use]
+	pub const fn is_instance_admin(&self) -> bool {
+		self.ring_id.is_none()
+	}
+
+	/// Whether this admin can manage the given ring
+	#[must_use]
+	pub fn can_manage(&self, ring: &Ring) -> bool {
+		self.ring_id.is_none_or(|ring_id| ring_id == ring.id)
+	}
 }
 
 // Manually impl so that password hash isn't shown
@@ -50,6 +96,7 @@ impl Debug for Admin {
 			.field("username", &self.username)
 			.field("email", &"redacted")
 			.field("password", &"redacted")
+			.field("ring_id", &self.ring_id)
 			.finish_non_exhaustive()
 	}
 }
@@ -92,11 +139,7 @@ impl AuthnBackend for RingState {
 		&self,
 		creds: Self::Credentials,
 	) -> Result<Option<Self::User>, Self::Error> {
-		let Some(admin) = sqlx::query_as::<_, Admin>("SELECT * FROM admins WHERE username = ?")
-			.bind(&creds.username)
-			.fetch_optional(&self.database)
-			.await?
-		else {
+		let Some(admin) = self.store.get_admin_by_username(&creds.username).await? else {
 			debug!("Couldn't find an admin with username {}", creds.username);
 			return Ok(None);
 		};
@@ -130,11 +173,7 @@ impl AuthnBackend for RingState {
 	}
 
 	async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
-		let admin = sqlx::query_as("SELECT * FROM admins WHERE id = ?")
-			.bind(user_id)
-			.fetch_optional(&self.database)
-			.await?;
-		Ok(admin)
+		self.store.get_admin(*user_id).await
 	}
 }
 
@@ -161,19 +200,52 @@ impl RingState {
 			return Err(RingError::UnauthorisedAdmin);
 		};
 		let new_password_hashed = hash_password(new_password_plaintext).await?;
-		if let Err(e) = sqlx::query!(
-			"UPDATE admins SET password_phc = ? WHERE id = ?",
-			new_password_hashed,
-			admin.id
-		)
-		.execute(&self.database)
-		.await
-		{
-			error!("Error when trying to update admin password: {e}");
-			return Err(RingError::UnrecoverableDatabaseError(e));
-		}
+		self.store.set_password(admin.id, &new_password_hashed).await
+	}
+
+	/// Sets an admin's password without needing their current one, for recovering locked out
+	/// accounts
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no admin with the given id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument(skip(new_password_plaintext))]
+	pub async fn reset_password(
+		&self,
+		admin_id: i64,
+		new_password_plaintext: String,
+	) -> Result<(), RingError> {
+		let new_password_hashed = hash_password(new_password_plaintext).await?;
+		self.store
+			.set_password(admin_id, &new_password_hashed)
+			.await?;
+		info!("Reset the password of admin {}", admin_id);
 		Ok(())
 	}
+
+	/// Gets every admin on the instance
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_list_admins(&self) -> Result<Vec<Admin>, RingError> {
+		self.store.get_list_admins().await
+	}
+
+	/// Gets the admin with the given username
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no admin with that username
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_admin_by_username(&self, username: &str) -> Result<Admin, RingError> {
+		match self.store.get_admin_by_username(username).await? {
+			Some(admin) => Ok(admin),
+			None => Err(RingError::RowNotFound(format!(
+				"Admin with username {username}"
+			))),
+		}
+	}
 }
 
 pub type AuthSession = axum_login::AuthSession<RingState>;
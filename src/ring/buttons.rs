@@ -0,0 +1,294 @@
+//! This module handles members' buttons, the 88x31 images they link their sites with
+//!
+//! Visitors would tell members' sites their IP address if pages loaded buttons from them, so a
+//! background worker fetches every approved site's button instead, see
+//! [`RingState::run_button_worker`], and the instance serves its copy. Buttons are fetched as soon
+//! as a site is approved or changes its button url, and again once a day, using conditional
+//! requests so buttons that haven't changed aren't downloaded again.
+
+use std::time::{Duration, SystemTime};
+
+use sqlx::FromRow;
+use tracing::{debug, error, instrument, warn};
+
+use super::{RingError, RingState, sql};
+
+/// The biggest button that will be downloaded, in bytes
+const MAX_BUTTON_SIZE: u64 = 256 * 1024;
+/// How long a fetched button is kept before it's fetched again
+const BUTTON_MAX_AGE: Duration = Duration::from_hours(24);
+
+/// A member's button, as fetched by the instance
+#[derive(Debug, Clone, FromRow)]
+pub struct Button {
+	pub content_type: String,
+	pub image: Vec<u8>,
+}
+
+/// A member's button as last stored, before fetching it again
+#[derive(FromRow)]
+struct StoredButton {
+	site_id: i64,
+	button_url: String,
+	/// The button url the stored validators came from
+	fetched_button_url: Option<String>,
+	etag: Option<String>,
+	last_modified: Option<String>,
+}
+
+/// The result of fetching a button
+enum FetchOutcome {
+	/// The button hasn't changed since it was last fetched
+	NotModified,
+	Fetched {
+		button: Button,
+		etag: Option<String>,
+		last_modified: Option<String>,
+	},
+}
+
+/// The type of the image in `bytes`, if it's one browsers show as a button
+///
+/// The type is read from the image itself rather than trusted from the member's server, and SVGs
+/// aren't accepted since they can hold scripts.
+fn image_type(bytes: &[u8]) -> Option<&'static str> {
+	match bytes {
+		[0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+		[b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+		[0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+		[b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+		_ => None,
+	}
+}
+
+fn fetch_button(
+	url: &str,
+	etag: Option<&str>,
+	last_modified: Option<&str>,
+) -> Result<FetchOutcome, String> {
+	let agent: ureq::Agent = ureq::Agent::config_builder()
+		.timeout_global(Some(Duration::from_secs(10)))
+		.build()
+		.into();
+	let mut request = agent.get(url).header("Accept", "image/*");
+	if let Some(etag) = etag {
+		request = request.header("If-None-Match", etag);
+	}
+	if let Some(last_modified) = last_modified {
+		request = request.header("If-Modified-Since", last_modified);
+	}
+	let response = request
+		.call()
+		.map_err(|e| format!("Could not fetch the button: {e}"))?;
+	if response.status() == 304 {
+		return Ok(FetchOutcome::NotModified);
+	}
+	let header = |name: &str| {
+		response
+			.headers()
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_owned)
+	};
+	let (etag, last_modified) = (header("ETag"), header("Last-Modified"));
+	let image = response
+		.into_body()
+		.with_config()
+		.limit(MAX_BUTTON_SIZE)
+		.read_to_vec()
+		.map_err(|e| format!("Could not read the button: {e}"))?;
+	let content_type = image_type(&image)
+		.ok_or("The button isn't a PNG, GIF, JPEG or WebP image")?
+		.to_owned();
+	Ok(FetchOutcome::Fetched {
+		button: Button {
+			content_type,
+			image,
+		},
+		etag,
+		last_modified,
+	})
+}
+
+impl RingState {
+	/// Gets the instance's copy of the button of one of the ring's approved sites, if it has one
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_button(&self, site_id: i64) -> Result<Option<Button>, RingError> {
+		// Only the button the site currently declares is served
+		match sqlx::query_as(
+			"SELECT sb.content_type, sb.image FROM site_buttons AS sb
+			INNER JOIN approved_sites AS a ON sb.site_id = a.site_id
+			WHERE sb.site_id = $1 AND a.ring_id = $2 AND sb.button_url = a.button_url
+			AND sb.image IS NOT NULL",
+		)
+		.bind(site_id)
+		.bind(self.ring.id)
+		.fetch_optional(&self.database)
+		.await
+		{
+			Ok(button) => Ok(button),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_button: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Fetches the button of every approved site on the instance that is new, has changed its
+	/// button url or was last fetched a day ago, returning how many were fetched successfully
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn refresh_buttons(&self) -> Result<usize, RingError> {
+		let buttons: Vec<StoredButton> = sqlx::query_as(
+			"SELECT a.site_id, a.button_url, sb.button_url AS fetched_button_url, sb.etag,
+			sb.last_modified
+			FROM approved_sites AS a LEFT JOIN site_buttons AS sb ON a.site_id = sb.site_id
+			WHERE a.button_url IS NOT NULL AND (sb.site_id IS NULL
+			OR sb.button_url <> a.button_url OR sb.last_fetched_at <= $1)",
+		)
+		.bind(sql::timestamp((SystemTime::now() - BUTTON_MAX_AGE).into()))
+		.fetch_all(&self.database)
+		.await?;
+		let mut refreshed = 0;
+		for button in buttons {
+			// Validators from a different button url would be meaningless
+			let same_button =
+				button.fetched_button_url.as_deref() == Some(button.button_url.as_str());
+			let (etag, last_modified) = if same_button {
+				(button.etag, button.last_modified)
+			} else {
+				(None, None)
+			};
+			let url = button.button_url.clone();
+			let outcome = tokio::task::spawn_blocking(move || {
+				fetch_button(&url, etag.as_deref(), last_modified.as_deref())
+			})
+			.await?;
+			let stored = match outcome {
+				Ok(outcome) => {
+					self.store_button(button.site_id, &button.button_url, outcome)
+						.await
+				}
+				Err(problem) => {
+					warn!(
+						"Could not refresh the button {}: {}",
+						button.button_url, problem
+					);
+					self.store_button_error(button.site_id, &button.button_url, &problem)
+						.await
+				}
+			};
+			// One button failing to be stored shouldn't stop the rest being refreshed
+			match stored {
+				Ok(true) => refreshed += 1,
+				Ok(false) => {}
+				Err(e) => error!("Error when storing the button {}: {e}", button.button_url),
+			}
+		}
+		Ok(refreshed)
+	}
+
+	async fn store_button(
+		&self,
+		site_id: i64,
+		button_url: &str,
+		outcome: FetchOutcome,
+	) -> Result<bool, RingError> {
+		let FetchOutcome::Fetched {
+			button,
+			etag,
+			last_modified,
+		} = outcome
+		else {
+			sqlx::query(
+				"UPDATE site_buttons SET last_fetched_at = $1, last_error = NULL
+				WHERE site_id = $2",
+			)
+			.bind(sql::now())
+			.bind(site_id)
+			.execute(&self.database)
+			.await?;
+			debug!("The button {} hasn't changed", button_url);
+			return Ok(true);
+		};
+		sqlx::query(
+			"INSERT INTO site_buttons (site_id, button_url, content_type, image, etag,
+			last_modified, last_fetched_at) VALUES ($1, $2, $3, $4, $5, $6, $7)
+			ON CONFLICT (site_id) DO UPDATE
+			SET button_url = excluded.button_url, content_type = excluded.content_type,
+			image = excluded.image, etag = excluded.etag, last_modified = excluded.last_modified,
+			last_fetched_at = excluded.last_fetched_at, last_error = NULL",
+		)
+		.bind(site_id)
+		.bind(button_url)
+		.bind(button.content_type)
+		.bind(button.image)
+		.bind(etag)
+		.bind(last_modified)
+		.bind(sql::now())
+		.execute(&self.database)
+		.await?;
+		debug!("Stored the button {}", button_url);
+		Ok(true)
+	}
+
+	/// Records why fetching a button failed, keeping the last copy if the url hasn't changed
+	async fn store_button_error(
+		&self,
+		site_id: i64,
+		button_url: &str,
+		problem: &str,
+	) -> Result<bool, RingError> {
+		sqlx::query(
+			"INSERT INTO site_buttons (site_id, button_url, last_fetched_at, last_error)
+			VALUES ($1, $2, $3, $4) ON CONFLICT (site_id) DO UPDATE
+			SET image = CASE WHEN site_buttons.button_url = excluded.button_url
+				THEN site_buttons.image ELSE NULL END,
+			button_url = excluded.button_url, last_fetched_at = excluded.last_fetched_at,
+			last_error = excluded.last_error",
+		)
+		.bind(site_id)
+		.bind(button_url)
+		.bind(sql::now())
+		.bind(problem)
+		.execute(&self.database)
+		.await?;
+		Ok(false)
+	}
+
+	/// Fetches the buttons that are due every `interval`, forever
+	pub async fn run_button_worker(self, interval: Duration) {
+		let mut ticker = tokio::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match self.refresh_buttons().await {
+				Ok(refreshed) => debug!("Refreshed {} buttons", refreshed),
+				Err(e) => error!("Error when refreshing the buttons: {e}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::image_type;
+
+	#[test]
+	fn reads_image_types() {
+		assert_eq!(image_type(b"\x89PNG\r\n\x1a\n"), Some("image/png"));
+		assert_eq!(image_type(b"GIF89a"), Some("image/gif"));
+		assert_eq!(image_type(b"\xFF\xD8\xFF\xE0"), Some("image/jpeg"));
+		assert_eq!(image_type(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+		assert_eq!(image_type(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+		assert_eq!(image_type(b"<html>"), None);
+		assert_eq!(image_type(b""), None);
+	}
+}
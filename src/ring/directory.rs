@@ -0,0 +1,163 @@
+//! This module handles exporting the public directory of the webring's members
+//!
+//! Only what's already shown on the member list is exported, so nothing private like the email
+//! addresses of sites or admins ever leaves through here.
+
+use serde::Serialize;
+use tracing::instrument;
+
+use super::{ApprovedSite, RingError, RingState, SiteProfile};
+
+/// The columns of the CSV export, in order
+const CSV_HEADER: [&str; 10] = [
+	"id",
+	"url",
+	"title",
+	"description",
+	"owner_name",
+	"feed_url",
+	"button_url",
+	"tags",
+	"date_added",
+	"profile_url",
+];
+
+/// A member of the ring, with only the details that are public
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicSite {
+	pub id: i64,
+	pub url: String,
+	#[serde(flatten)]
+	pub profile: SiteProfile,
+	pub tags: Vec<String>,
+	pub date_added: String,
+	/// The full url of the site's page on the ring
+	pub profile_url: String,
+}
+
+/// The whole public directory of a ring
+#[derive(Debug, Clone, Serialize)]
+pub struct Directory {
+	pub name: String,
+	pub description: Option<String>,
+	pub home_url: String,
+	pub sites: Vec<PublicSite>,
+}
+
+impl Directory {
+	/// The directory as CSV, with a header row
+	#[must_use]
+	pub fn to_csv(&self) -> String {
+		let mut csv = CSV_HEADER.join(",");
+		csv.push_str("\r\n");
+		for site in &self.sites {
+			let row = [
+				site.id.to_string(),
+				site.url.clone(),
+				site.profile.title.clone().unwrap_or_default(),
+				site.profile.description.clone().unwrap_or_default(),
+				site.profile.owner_name.clone().unwrap_or_default(),
+				site.profile.feed_url.clone().unwrap_or_default(),
+				site.profile.button_url.clone().unwrap_or_default(),
+				site.tags.join(" "),
+				site.date_added.clone(),
+				site.profile_url.clone(),
+			];
+			csv.push_str(
+				&row.iter()
+					.map(|field| csv_field(field))
+					.collect::<Vec<_>>()
+					.join(","),
+			);
+			csv.push_str("\r\n");
+		}
+		csv
+	}
+}
+
+/// Quotes a CSV field if it needs to be, as RFC 4180 describes
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\r', '\n']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_owned()
+	}
+}
+
+impl RingState {
+	/// Gets the public directory of the ring's members, optionally only those with the given tag,
+	/// in the order they are in the ring
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_directory(&self, tag: Option<&str>) -> Result<Directory, RingError> {
+		let sites = match self.get_list_approved(tag).await {
+			Ok(sites) => sites,
+			Err(RingError::RowNotFound(_query)) => Vec::new(),
+			Err(e) => return Err(e),
+		};
+		Ok(Directory {
+			name: self.ring.name.clone(),
+			description: self.ring.description.clone(),
+			home_url: self.home_url(),
+			sites: sites
+				.into_iter()
+				.map(|site| self.public_site(site))
+				.collect(),
+		})
+	}
+
+	fn public_site(&self, site: ApprovedSite) -> PublicSite {
+		PublicSite {
+			tags: site.tag_list().into_iter().map(str::to_owned).collect(),
+			profile_url: self.url(&format!("/members/{}", site.site_id)),
+			id: site.site_id,
+			url: site.root_url,
+			profile: site.profile,
+			date_added: site.date_added,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Directory, PublicSite, csv_field};
+	use crate::ring::SiteProfile;
+
+	#[test]
+	fn quotes_csv_fields_that_need_it() {
+		assert_eq!(csv_field("plain"), "plain");
+		assert_eq!(csv_field("a, b"), "\"a, b\"");
+		assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+		assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+	}
+
+	#[test]
+	fn exports_csv() {
+		let directory = Directory {
+			name: "Ring".to_owned(),
+			description: None,
+			home_url: "https://ring.example/".to_owned(),
+			sites: vec![PublicSite {
+				id: 1,
+				url: "https://a.example".to_owned(),
+				profile: SiteProfile {
+					title: Some("A, \"the\" site".to_owned()),
+					..SiteProfile::default()
+				},
+				tags: vec!["art".to_owned(), "music".to_owned()],
+				date_added: "2025-05-09".to_owned(),
+				profile_url: "https://ring.example/members/1".to_owned(),
+			}],
+		};
+		let csv = directory.to_csv();
+		let rows: Vec<&str> = csv.split_terminator("\r\n").collect();
+		assert_eq!(rows.len(), 2);
+		assert!(rows[0].starts_with("id,url,title,"));
+		assert_eq!(
+			rows[1],
+			"1,https://a.example,\"A, \"\"the\"\" site\",,,,,art music,2025-05-09,https://ring.example/members/1"
+		);
+	}
+}
@@ -0,0 +1,657 @@
+//! This module handles moving a whole instance between databases as JSON
+//!
+//! An [`Export`] holds every ring with its sites, their approvals and denials, and its linked
+//! rings, along with the admins, webhooks and the key the instance signs its ring manifests with.
+//! Admins keep their password hashes, so nobody has to reset their password after a move, but
+//! plaintext passwords are never stored anywhere to be exported. The signing key goes along so
+//! linked rings that have pinned it keep trusting the ring once it's moved, which means anyone
+//! holding an export can sign manifests as the instance. Visitor stats, the webhook queue, planet
+//! feeds and members' buttons are left out, use a database backup to keep those.
+//!
+//! Importing merges into whatever is already in the database, so it's safe to import into a fresh
+//! instance that has already created its default ring and admin.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Any, FromRow, Transaction};
+use tracing::{error, info, instrument, warn};
+
+use super::{RingError, RingState, SiteProfile, sql, tags::parse_tags};
+
+/// The version of the export format, bumped whenever an older webringer couldn't read it
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Everything needed to recreate an instance
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Export {
+	pub version: u32,
+	pub exported_at: String,
+	pub admins: Vec<ExportedAdmin>,
+	pub rings: Vec<ExportedRing>,
+	pub webhooks: Vec<ExportedWebhook>,
+	/// The secret key the instance signs its ring manifests with, in hex, if it has made one
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub signing_key: Option<String>,
+}
+
+impl std::fmt::Debug for Export {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Export")
+			.field("version", &self.version)
+			.field("exported_at", &self.exported_at)
+			.field("admins", &self.admins)
+			.field("rings", &self.rings)
+			.field("webhooks", &self.webhooks)
+			.field(
+				"signing_key",
+				&self.signing_key.as_ref().map(|_key| "redacted"),
+			)
+			.finish()
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct ExportedAdmin {
+	pub username: String,
+	pub email: String,
+	pub password_phc: String,
+	/// The slug of the only ring the admin can manage, or none for an instance admin
+	pub ring: Option<String>,
+}
+
+impl std::fmt::Debug for ExportedAdmin {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ExportedAdmin")
+			.field("username", &self.username)
+			.field("email", &"redacted")
+			.field("password_phc", &"redacted")
+			.field("ring", &self.ring)
+			.finish()
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedRing {
+	pub slug: String,
+	pub name: String,
+	pub description: Option<String>,
+	/// The ring's sites, in the order they are linked in
+	pub sites: Vec<ExportedSite>,
+	pub peers: Vec<ExportedPeer>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExportedSite {
+	pub root_url: String,
+	pub email: String,
+	pub email_verified_at: Option<String>,
+	#[serde(flatten)]
+	pub profile: SiteProfile,
+	pub tags: Vec<String>,
+	/// Whether the site was approved or denied, or none if it's still waiting
+	pub decision: Option<Decision>,
+}
+
+impl std::fmt::Debug for ExportedSite {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ExportedSite")
+			.field("root_url", &self.root_url)
+			.field("email", &"redacted")
+			.field("email_verified_at", &self.email_verified_at)
+			.field("profile", &self.profile)
+			.field("tags", &self.tags)
+			.field("decision", &self.decision)
+			.finish()
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum Decision {
+	Approved {
+		date_added: String,
+		/// When the site was approved, which exports from before it was recorded don't have
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		approved_at: Option<String>,
+		/// The username of the admin who approved the site
+		admin: String,
+		/// Where the approval came from, if it wasn't the admin approving an application
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		source: Option<String>,
+	},
+	Denied {
+		date_added: String,
+		/// The username of the admin who denied the site
+		admin: String,
+		reason: String,
+	},
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExportedPeer {
+	pub manifest_url: String,
+	pub name: String,
+	pub home_url: String,
+	pub public_key: String,
+	pub date_added: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct ExportedWebhook {
+	pub url: String,
+	pub secret: String,
+	pub date_added: String,
+	/// The username of the admin who added the webhook, if they still exist
+	pub admin: Option<String>,
+}
+
+impl std::fmt::Debug for ExportedWebhook {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ExportedWebhook")
+			.field("url", &self.url)
+			.field("secret", &"redacted")
+			.field("date_added", &self.date_added)
+			.field("admin", &self.admin)
+			.finish()
+	}
+}
+
+/// How much of an [`Export`] was added by [`RingState::import`], and how much was already there
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ImportSummary {
+	pub rings_added: usize,
+	pub admins_added: usize,
+	pub admins_skipped: usize,
+	pub sites_added: usize,
+	pub sites_skipped: usize,
+	pub peers_added: usize,
+	pub webhooks_added: usize,
+	/// Whether the instance now signs its manifests with the key from the export
+	pub signing_key_imported: bool,
+}
+
+/// A site as it's read for exporting, with its decision spread over nullable columns
+#[derive(FromRow)]
+struct SiteRow {
+	root_url: String,
+	email: String,
+	email_verified_at: Option<String>,
+	#[sqlx(flatten)]
+	profile: SiteProfile,
+	tags: Option<String>,
+	approval_date: Option<String>,
+	approved_at: Option<String>,
+	approved_by: Option<String>,
+	approval_source: Option<String>,
+	denied_at: Option<String>,
+	denied_by: Option<String>,
+	denial_reason: Option<String>,
+}
+
+impl From<SiteRow> for ExportedSite {
+	fn from(row: SiteRow) -> Self {
+		let decision = match row {
+			SiteRow {
+				approval_date: Some(ref date_added),
+				..
+			} => Some(Decision::Approved {
+				date_added: date_added.clone(),
+				approved_at: row.approved_at.clone(),
+				admin: row.approved_by.clone().unwrap_or_default(),
+				source: row.approval_source.clone(),
+			}),
+			SiteRow {
+				denied_at: Some(ref date_added),
+				denial_reason: Some(ref reason),
+				..
+			} => Some(Decision::Denied {
+				date_added: date_added.clone(),
+				admin: row.denied_by.clone().unwrap_or_default(),
+				reason: reason.clone(),
+			}),
+			_ => None,
+		};
+		Self {
+			root_url: row.root_url,
+			email: row.email,
+			email_verified_at: row.email_verified_at,
+			profile: row.profile,
+			tags: row
+				.tags
+				.map(|tags| tags.split(' ').map(str::to_owned).collect())
+				.unwrap_or_default(),
+			decision,
+		}
+	}
+}
+
+impl RingState {
+	/// Backups are read and written straight from the database, so they'd miss or go around sites
+	/// kept in any other [`RingStore`](super::store::RingStore)
+	fn check_backups_supported(&self) -> Result<(), RingError> {
+		if self.store_is_database {
+			Ok(())
+		} else {
+			Err(RingError::InvalidInput(
+				"Backups can only be made of rings kept in the database".to_owned(),
+			))
+		}
+	}
+
+	/// Exports every ring of the instance, whichever ring this is scoped to
+	///
+	/// # Errors
+	/// [`RingError::InvalidInput`] if the rings aren't kept in the database
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn export(&self) -> Result<Export, RingError> {
+		self.check_backups_supported()?;
+		match self.export_inner().await {
+			Ok(export) => Ok(export),
+			Err(e) => {
+				error!("There was an unrecoverable database error in export: {}", e);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn export_inner(&self) -> Result<Export, sqlx::Error> {
+		// Read everything in one transaction so the export is consistent
+		let mut tx = self.database.begin().await?;
+
+		let exported_at = sql::now();
+		let admins = sqlx::query_as(
+			"SELECT a.username, a.email, a.password_phc, r.slug AS ring
+			FROM admins AS a LEFT JOIN rings AS r ON a.ring_id = r.id
+			ORDER BY a.id",
+		)
+		.fetch_all(&mut *tx)
+		.await?;
+		let webhooks = sqlx::query_as(
+			"SELECT w.url, w.secret, w.date_added, a.username AS admin
+			FROM webhooks AS w LEFT JOIN admins AS a ON w.admin_id = a.id
+			ORDER BY w.id",
+		)
+		.fetch_all(&mut *tx)
+		.await?;
+
+		let mut rings = Vec::new();
+		let ring_rows: Vec<super::rings::Ring> = sqlx::query_as("SELECT * FROM rings ORDER BY id")
+			.fetch_all(&mut *tx)
+			.await?;
+		for ring in ring_rows {
+			let sites: Vec<SiteRow> = sqlx::query_as(
+				"SELECT s.root_url, s.email, s.email_verified_at, s.title, s.description,
+					s.owner_name, s.feed_url, s.button_url,
+					(
+						SELECT string_agg(t.name, ' ')
+						FROM site_tags AS st
+						INNER JOIN tags AS t ON st.tag_id = t.id
+						WHERE st.site_id = s.id
+					) AS tags,
+					ar.date_added AS approval_date, ar.approved_at, aa.username AS approved_by,
+					ar.source AS approval_source,
+					dr.date_added AS denied_at, da.username AS denied_by,
+					dr.reason AS denial_reason
+				FROM sites AS s
+				LEFT JOIN approval_records AS ar ON s.approval_id = ar.id
+				LEFT JOIN admins AS aa ON ar.admin_id = aa.id
+				LEFT JOIN denial_records AS dr ON s.denial_id = dr.id
+				LEFT JOIN admins AS da ON dr.admin_id = da.id
+				WHERE s.ring_id = $1
+				ORDER BY s.id",
+			)
+			.bind(ring.id)
+			.fetch_all(&mut *tx)
+			.await?;
+			let peers = sqlx::query_as(
+				"SELECT manifest_url, name, home_url, public_key, date_added
+				FROM peer_rings WHERE ring_id = $1 ORDER BY id",
+			)
+			.bind(ring.id)
+			.fetch_all(&mut *tx)
+			.await?;
+			rings.push(ExportedRing {
+				slug: ring.slug,
+				name: ring.name,
+				description: ring.description,
+				sites: sites.into_iter().map(ExportedSite::from).collect(),
+				peers,
+			});
+		}
+
+		let signing_key = sqlx::query_scalar("SELECT signing_key FROM instance_keys WHERE id = 1")
+			.fetch_optional(&mut *tx)
+			.await?;
+
+		tx.commit().await?;
+		info!("Exported {} rings", rings.len());
+		Ok(Export {
+			version: EXPORT_VERSION,
+			exported_at,
+			admins,
+			rings,
+			webhooks,
+			signing_key,
+		})
+	}
+
+	/// Adds everything in `export` that isn't already in the database, in one transaction
+	///
+	/// Rings are matched by slug, and have their name and description updated. Admins are matched
+	/// by username and sites by ring and url, and are left alone if they already exist. Sites are
+	/// added in the order they were exported in, after any sites already in their ring. The signing
+	/// key in the export replaces the instance's own, so linked rings keep trusting the moved
+	/// rings.
+	///
+	/// # Errors
+	/// [`RingError::InvalidInput`] if the export is from a newer version of webringer, or
+	/// doesn't make sense, or if the rings aren't kept in the database
+	/// [`RingError::UniqueRowAlreadyPresent`] if an admin's email is taken by a different admin, or
+	/// a denial reason is already used by a site in the database
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument(skip(export))]
+	pub async fn import(&self, export: &Export) -> Result<ImportSummary, RingError> {
+		self.check_backups_supported()?;
+		if export.version != EXPORT_VERSION {
+			return Err(RingError::InvalidInput(format!(
+				"This export is version {}, but only version {EXPORT_VERSION} can be imported",
+				export.version
+			)));
+		}
+		let mut summary = ImportSummary::default();
+		let mut tx = self.database.begin().await?;
+
+		let ring_ids = import_rings(&mut tx, &export.rings, &mut summary).await?;
+		let admin_ids = import_admins(&mut tx, &export.admins, &ring_ids, &mut summary).await?;
+
+		for ring in &export.rings {
+			let ring_id = ring_ids[ring.slug.as_str()];
+			for site in &ring.sites {
+				if import_site(&mut tx, ring_id, site, &admin_ids).await? {
+					summary.sites_added += 1;
+				} else {
+					summary.sites_skipped += 1;
+				}
+			}
+			for peer in &ring.peers {
+				// The peer is fetched again by the refresh worker, which fills in anything stale
+				summary.peers_added += usize::try_from(
+					sqlx::query(
+						"INSERT INTO peer_rings (ring_id, manifest_url, name, home_url,
+							public_key, date_added, last_fetched_at)
+						VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING",
+					)
+					.bind(ring_id)
+					.bind(&peer.manifest_url)
+					.bind(&peer.name)
+					.bind(&peer.home_url)
+					.bind(&peer.public_key)
+					.bind(&peer.date_added)
+					.bind(&peer.date_added)
+					.execute(&mut *tx)
+					.await?
+					.rows_affected(),
+				)
+				.unwrap_or_default();
+			}
+		}
+
+		for webhook in &export.webhooks {
+			let admin_id = webhook
+				.admin
+				.as_deref()
+				.and_then(|username| admin_ids.get(username));
+			summary.webhooks_added += usize::try_from(
+				sqlx::query(
+					"INSERT INTO webhooks (url, secret, date_added, admin_id)
+					VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+				)
+				.bind(&webhook.url)
+				.bind(&webhook.secret)
+				.bind(&webhook.date_added)
+				.bind(admin_id)
+				.execute(&mut *tx)
+				.await?
+				.rows_affected(),
+			)
+			.unwrap_or_default();
+		}
+
+		if let Some(signing_key) = &export.signing_key {
+			summary.signing_key_imported = import_signing_key(&mut tx, signing_key).await?;
+		}
+
+		tx.commit().await?;
+		self.topology.invalidate_all();
+		info!("Imported {:?}", summary);
+		Ok(summary)
+	}
+}
+
+/// Makes `signing_key` the instance's signing key, returning false if it already was
+async fn import_signing_key(
+	tx: &mut Transaction<'static, Any>,
+	signing_key: &str,
+) -> Result<bool, RingError> {
+	if hex::decode(signing_key).map_or(true, |bytes| bytes.len() != 32) {
+		return Err(RingError::InvalidInput(
+			"The signing key in the export isn't a 32 byte hex key".to_owned(),
+		));
+	}
+	let existing: Option<String> =
+		sqlx::query_scalar("SELECT signing_key FROM instance_keys WHERE id = 1")
+			.fetch_optional(&mut **tx)
+			.await?;
+	match existing {
+		Some(existing) if existing == signing_key => return Ok(false),
+		Some(_existing) => warn!(
+			"Replacing the instance's signing key with the imported one, linked rings that pinned \
+			the old key will stop trusting this instance's manifests"
+		),
+		None => {}
+	}
+	sqlx::query(
+		"INSERT INTO instance_keys (id, signing_key) VALUES (1, $1)
+		ON CONFLICT (id) DO UPDATE SET signing_key = excluded.signing_key",
+	)
+	.bind(signing_key)
+	.execute(&mut **tx)
+	.await?;
+	Ok(true)
+}
+
+/// Adds or updates every exported ring, returning the id of each by slug
+async fn import_rings<'a>(
+	tx: &mut Transaction<'static, Any>,
+	rings: &'a [ExportedRing],
+	summary: &mut ImportSummary,
+) -> Result<HashMap<&'a str, i64>, RingError> {
+	let mut ring_ids = HashMap::new();
+	for ring in rings {
+		let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM rings WHERE slug = $1")
+			.bind(&ring.slug)
+			.fetch_optional(&mut **tx)
+			.await?;
+		let ring_id = if let Some(ring_id) = existing {
+			sqlx::query("UPDATE rings SET name = $1, description = $2 WHERE id = $3")
+				.bind(&ring.name)
+				.bind(&ring.description)
+				.bind(ring_id)
+				.execute(&mut **tx)
+				.await?;
+			ring_id
+		} else {
+			summary.rings_added += 1;
+			sqlx::query_scalar(
+				"INSERT INTO rings (slug, name, description) VALUES ($1, $2, $3) RETURNING id",
+			)
+			.bind(&ring.slug)
+			.bind(&ring.name)
+			.bind(&ring.description)
+			.fetch_one(&mut **tx)
+			.await?
+		};
+		ring_ids.insert(ring.slug.as_str(), ring_id);
+	}
+	Ok(ring_ids)
+}
+
+/// Adds every exported admin that isn't already there, returning the id of each by username
+async fn import_admins<'a>(
+	tx: &mut Transaction<'static, Any>,
+	admins: &'a [ExportedAdmin],
+	ring_ids: &HashMap<&str, i64>,
+	summary: &mut ImportSummary,
+) -> Result<HashMap<&'a str, i64>, RingError> {
+	let mut admin_ids = HashMap::new();
+	for admin in admins {
+		let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM admins WHERE username = $1")
+			.bind(&admin.username)
+			.fetch_optional(&mut **tx)
+			.await?;
+		let admin_id = if let Some(admin_id) = existing {
+			summary.admins_skipped += 1;
+			admin_id
+		} else {
+			let ring_id = match &admin.ring {
+				Some(slug) => Some(ring_ids.get(slug.as_str()).copied().ok_or_else(|| {
+					RingError::InvalidInput(format!(
+						"{} is an admin of {slug}, which isn't in the export",
+						admin.username
+					))
+				})?),
+				None => None,
+			};
+			summary.admins_added += 1;
+			match sqlx::query_scalar(
+				"INSERT INTO admins (username, email, password_phc, ring_id) VALUES ($1, $2, $3, $4)
+				RETURNING id",
+			)
+			.bind(&admin.username)
+			.bind(&admin.email)
+			.bind(&admin.password_phc)
+			.bind(ring_id)
+			.fetch_one(&mut **tx)
+			.await
+			{
+				Ok(admin_id) => admin_id,
+				Err(ref e) if sql::is_unique_violation(e) => {
+					return Err(RingError::UniqueRowAlreadyPresent(admin.username.clone()));
+				}
+				Err(e) => return Err(e.into()),
+			}
+		};
+		admin_ids.insert(admin.username.as_str(), admin_id);
+	}
+	Ok(admin_ids)
+}
+
+/// Adds a single exported site to `ring_id`, returning false if its url is already taken in that
+/// ring
+async fn import_site(
+	tx: &mut Transaction<'static, Any>,
+	ring_id: i64,
+	site: &ExportedSite,
+	admin_ids: &HashMap<&str, i64>,
+) -> Result<bool, RingError> {
+	if sqlx::query_scalar::<_, i64>("SELECT id FROM sites WHERE root_url = $1 AND ring_id = $2")
+		.bind(&site.root_url)
+		.bind(ring_id)
+		.fetch_optional(&mut **tx)
+		.await?
+		.is_some()
+	{
+		return Ok(false);
+	}
+	let admin_id = |username: &str| {
+		admin_ids.get(username).copied().ok_or_else(|| {
+			RingError::InvalidInput(format!(
+				"{} was decided on by {username}, who isn't in the export",
+				site.root_url
+			))
+		})
+	};
+
+	let (approval_id, denial_id): (Option<i64>, Option<i64>) = match &site.decision {
+		Some(Decision::Approved {
+			date_added,
+			approved_at,
+			admin,
+			source,
+		}) => {
+			let admin_id = admin_id(admin)?;
+			let approval_id: i64 = sqlx::query_scalar(
+				"INSERT INTO approval_records (date_added, approved_at, admin_id, source)
+				VALUES ($1, $2, $3, $4) RETURNING id",
+			)
+			.bind(date_added)
+			.bind(approved_at)
+			.bind(admin_id)
+			.bind(source)
+			.fetch_one(&mut **tx)
+			.await?;
+			(Some(approval_id), None)
+		}
+		Some(Decision::Denied {
+			date_added,
+			admin,
+			reason,
+		}) => {
+			let admin_id = admin_id(admin)?;
+			match sqlx::query_scalar(
+				"INSERT INTO denial_records (date_added, reason, admin_id) VALUES ($1, $2, $3)
+				RETURNING id",
+			)
+			.bind(date_added)
+			.bind(reason)
+			.bind(admin_id)
+			.fetch_one(&mut **tx)
+			.await
+			{
+				Ok(denial_id) => (None, Some(denial_id)),
+				// Every denial needs its own reason
+				Err(ref e) if sql::is_unique_violation(e) => {
+					return Err(RingError::UniqueRowAlreadyPresent(reason.clone()));
+				}
+				Err(e) => return Err(e.into()),
+			}
+		}
+		None => (None, None),
+	};
+
+	let site_id: i64 = sqlx::query_scalar(
+		"INSERT INTO sites (root_url, email, email_verified_at, title, description,
+			owner_name, feed_url, button_url, approval_id, denial_id, ring_id)
+		VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING id",
+	)
+	.bind(&site.root_url)
+	.bind(&site.email)
+	.bind(&site.email_verified_at)
+	.bind(&site.profile.title)
+	.bind(&site.profile.description)
+	.bind(&site.profile.owner_name)
+	.bind(&site.profile.feed_url)
+	.bind(&site.profile.button_url)
+	.bind(approval_id)
+	.bind(denial_id)
+	.bind(ring_id)
+	.fetch_one(&mut **tx)
+	.await?;
+
+	for tag in parse_tags(&site.tags.join(" "))? {
+		sqlx::query("INSERT INTO tags (name) VALUES ($1) ON CONFLICT DO NOTHING")
+			.bind(&tag)
+			.execute(&mut **tx)
+			.await?;
+		sqlx::query(
+			"INSERT INTO site_tags (site_id, tag_id) SELECT $1, id FROM tags WHERE name = $2",
+		)
+		.bind(site_id)
+		.bind(tag)
+		.execute(&mut **tx)
+		.await?;
+	}
+	Ok(true)
+}
@@ -0,0 +1,576 @@
+//! This module handles linking rings on different webringer instances into a ring of rings
+//!
+//! Every ring publishes a [`RingManifest`] at `<ring>/ring.json`, signed with the instance's
+//! Ed25519 key. The signature is sent in the `X-Webringer-Signature` header as
+//! `ed25519=<hex signature>`, and covers the exact bytes of the body. Admins subscribe their ring
+//! to a peer ring by the url of its manifest, and the key the peer used then is pinned, so a peer
+//! that later changes its key stops being trusted.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{debug, error, info, instrument, warn};
+
+use super::{RingError, RingState, sql};
+
+/// The version of the manifest format, bumped whenever it changes incompatibly
+pub const MANIFEST_VERSION: u32 = 1;
+/// The header the manifest's signature is sent in
+pub const SIGNATURE_HEADER: &str = "X-Webringer-Signature";
+
+/// A machine readable description of a ring, for other instances to link to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RingManifest {
+	pub version: u32,
+	pub name: String,
+	pub description: Option<String>,
+	pub home_url: String,
+	pub manifest_url: String,
+	pub navigation: ManifestNavigation,
+	pub members: Vec<ManifestMember>,
+	/// The manifest urls of the rings this ring links to
+	pub peers: Vec<String>,
+	/// The hex encoded Ed25519 key the manifest is signed with
+	pub public_key: String,
+	/// When the manifest was generated, in seconds since the unix epoch
+	pub generated_at: u64,
+}
+
+/// The urls members of a ring link to, which take a `current` query parameter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestNavigation {
+	pub next: String,
+	pub prev: String,
+	pub random: String,
+	pub next_ring: String,
+	pub random_ring: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ManifestMember {
+	pub url: String,
+	pub title: Option<String>,
+}
+
+/// A ring on another instance that a local ring links to
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct PeerRing {
+	pub id: i64,
+	pub manifest_url: String,
+	pub name: String,
+	pub home_url: String,
+	pub public_key: String,
+	pub date_added: String,
+	pub last_fetched_at: String,
+	/// Why the last refresh of the peer's manifest failed, if it did
+	pub last_error: Option<String>,
+}
+
+/// Generates a new random key to sign manifests with
+#[must_use]
+pub fn generate_signing_key() -> SigningKey {
+	let mut bytes = [0u8; 32];
+	OsRng.fill_bytes(&mut bytes);
+	SigningKey::from_bytes(&bytes)
+}
+
+/// Signs a manifest body, returning the value of the [`SIGNATURE_HEADER`]
+#[must_use]
+pub fn sign_manifest(key: &SigningKey, body: &str) -> String {
+	format!("ed25519={}", hex::encode(key.sign(body.as_bytes()).to_bytes()))
+}
+
+/// Checks that a manifest body fetched from `url` was signed by the key it names, and by
+/// `pinned_key` if given
+///
+/// # Errors
+/// [`RingError::Federation`] describing the problem if the manifest is malformed, isn't published
+/// at `url`, links to something other than a web page, the signature doesn't match or the key
+/// isn't the pinned one
+pub fn verify_manifest(
+	url: &str,
+	body: &str,
+	signature: &str,
+	pinned_key: Option<&str>,
+) -> Result<RingManifest, RingError> {
+	let invalid = |problem: &str| RingError::Federation(problem.to_owned());
+	let manifest: RingManifest = serde_json::from_str(body)
+		.map_err(|e| RingError::Federation(format!("The manifest isn't valid: {e}")))?;
+	if manifest.version != MANIFEST_VERSION {
+		return Err(RingError::Federation(format!(
+			"The manifest is version {}, but only version {MANIFEST_VERSION} is supported",
+			manifest.version
+		)));
+	}
+	if !same_url(&manifest.manifest_url, url) {
+		return Err(invalid(
+			"The manifest is for a ring published at a different url",
+		));
+	}
+	// The home url is redirected to and linked to, so it mustn't be e.g. a javascript: url
+	if !(manifest.home_url.starts_with("https://") || manifest.home_url.starts_with("http://")) {
+		return Err(invalid("The manifest's home url isn't a http(s) url"));
+	}
+	if pinned_key.is_some_and(|pinned_key| pinned_key != manifest.public_key) {
+		return Err(invalid(
+			"The manifest is signed with a different key to when the ring was added",
+		));
+	}
+	let key_bytes: [u8; 32] = hex::decode(&manifest.public_key)
+		.ok()
+		.and_then(|bytes| bytes.try_into().ok())
+		.ok_or_else(|| invalid("The manifest's public key isn't valid"))?;
+	let key = VerifyingKey::from_bytes(&key_bytes)
+		.map_err(|_| invalid("The manifest's public key isn't valid"))?;
+	let signature_bytes: [u8; 64] = signature
+		.strip_prefix("ed25519=")
+		.and_then(|signature| hex::decode(signature).ok())
+		.and_then(|bytes| bytes.try_into().ok())
+		.ok_or_else(|| invalid("The manifest's signature isn't valid"))?;
+	key.verify(body.as_bytes(), &Signature::from_bytes(&signature_bytes))
+		.map_err(|_| invalid("The manifest's signature doesn't match"))?;
+	Ok(manifest)
+}
+
+/// Fetches a manifest, returning its body and signature
+fn fetch_manifest(url: &str) -> Result<(String, String), String> {
+	let agent: ureq::Agent = ureq::Agent::config_builder()
+		.timeout_global(Some(Duration::from_secs(10)))
+		.build()
+		.into();
+	let response = agent
+		.get(url)
+		.call()
+		.map_err(|e| format!("Could not fetch the manifest: {e}"))?;
+	let signature = response
+		.headers()
+		.get(SIGNATURE_HEADER)
+		.and_then(|signature| signature.to_str().ok())
+		.ok_or_else(|| "The manifest isn't signed".to_owned())?
+		.to_owned();
+	let body = response
+		.into_body()
+		.read_to_string()
+		.map_err(|e| format!("Could not read the manifest: {e}"))?;
+	Ok((body, signature))
+}
+
+/// Fetches and verifies a manifest, see [`verify_manifest`]
+async fn fetch_verified_manifest(
+	url: &str,
+	pinned_key: Option<String>,
+) -> Result<RingManifest, RingError> {
+	let url = url.to_owned();
+	tokio::task::spawn_blocking(move || {
+		let (body, signature) = fetch_manifest(&url).map_err(RingError::Federation)?;
+		verify_manifest(&url, &body, &signature, pinned_key.as_deref())
+	})
+	.await?
+}
+
+/// Whether two urls are the same, ignoring a trailing slash
+fn same_url(a: &str, b: &str) -> bool {
+	a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+impl RingState {
+	/// Gets the instance's signing key, creating it if there isn't one yet
+	async fn signing_key(&self) -> Result<SigningKey, RingError> {
+		let new_key = hex::encode(generate_signing_key().to_bytes());
+		sqlx::query(
+			"INSERT INTO instance_keys (id, signing_key) VALUES (1, $1) ON CONFLICT DO NOTHING",
+		)
+		.bind(new_key)
+		.execute(&self.database)
+		.await?;
+		let key: String = sqlx::query_scalar("SELECT signing_key FROM instance_keys WHERE id = 1")
+			.fetch_one(&self.database)
+			.await?;
+		let key_bytes: [u8; 32] = hex::decode(&key)
+			.ok()
+			.and_then(|bytes| bytes.try_into().ok())
+			.ok_or_else(|| {
+				error!("The stored instance signing key is corrupt");
+				RingError::Federation("The instance signing key is corrupt".to_owned())
+			})?;
+		Ok(SigningKey::from_bytes(&key_bytes))
+	}
+
+	/// The full url the ring's manifest is published at
+	#[must_use]
+	pub fn manifest_url(&self) -> String {
+		self.url("/ring.json")
+	}
+
+	/// Gets the signed manifest of the ring, returning the body and the value of the
+	/// [`SIGNATURE_HEADER`]
+	///
+	/// # Errors
+	/// [`RingError::Federation`] if the instance's signing key is corrupt
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_ring_manifest(&self) -> Result<(String, String), RingError> {
+		let key = self.signing_key().await?;
+		let members = self
+			.store
+			.get_list_approved(self.ring.id, None)
+			.await?
+			.into_iter()
+			.map(|site| ManifestMember {
+				url: site.root_url,
+				title: site.profile.title,
+			})
+			.collect();
+		let peers = sqlx::query_scalar(
+			"SELECT manifest_url FROM peer_rings WHERE ring_id = $1 AND last_error IS NULL
+			ORDER BY id",
+		)
+		.bind(self.ring.id)
+		.fetch_all(&self.database)
+		.await?;
+
+		let manifest = RingManifest {
+			version: MANIFEST_VERSION,
+			name: self.ring.name.clone(),
+			description: self.ring.description.clone(),
+			home_url: self.home_url(),
+			manifest_url: self.manifest_url(),
+			navigation: ManifestNavigation {
+				next: self.url("/next"),
+				prev: self.url("/prev"),
+				random: self.url("/random"),
+				next_ring: self.url("/next-ring"),
+				random_ring: self.url("/random-ring"),
+			},
+			members,
+			peers,
+			public_key: hex::encode(key.verifying_key().to_bytes()),
+			generated_at: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |duration| duration.as_secs()),
+		};
+		let body = serde_json::to_string(&manifest)
+			.map_err(|e| RingError::Federation(format!("Could not serialize the manifest: {e}")))?;
+		let signature = sign_manifest(&key, &body);
+		Ok((body, signature))
+	}
+
+	/// Links the ring to a ring on another instance, by the url of its manifest
+	///
+	/// # Errors
+	/// [`RingError::Federation`] if the manifest couldn't be fetched or verified
+	/// [`RingError::UniqueRowAlreadyPresent`] if the ring already links to that peer
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn add_peer_ring(&self, manifest_url: &str) -> Result<(), RingError> {
+		let manifest = fetch_verified_manifest(manifest_url, None).await?;
+		match sqlx::query(
+			"INSERT INTO peer_rings (ring_id, manifest_url, name, home_url, public_key, date_added,
+			last_fetched_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+		)
+		.bind(self.ring.id)
+		.bind(manifest_url)
+		.bind(manifest.name)
+		.bind(manifest.home_url)
+		.bind(manifest.public_key)
+		.bind(sql::today())
+		.bind(sql::now())
+		.execute(&self.database)
+		.await
+		{
+			Ok(_query_outcome) => {
+				info!(
+					"Ring {} now links to peer ring {}",
+					self.ring.slug, manifest_url
+				);
+				Ok(())
+			}
+			Err(ref e) if sql::is_unique_violation(e) => {
+				info!(
+					"Someone tried to add peer ring {} to ring {} but it was already there",
+					manifest_url, self.ring.slug
+				);
+				Err(RingError::UniqueRowAlreadyPresent(manifest_url.to_owned()))
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in add_peer_ring: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Stops the ring linking to a peer ring
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if the ring doesn't link to a peer with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn remove_peer_ring(&self, peer_id: i64) -> Result<(), RingError> {
+		match sqlx::query("DELETE FROM peer_rings WHERE id = $1 AND ring_id = $2")
+			.bind(peer_id)
+			.bind(self.ring.id)
+			.execute(&self.database)
+			.await
+		{
+			Ok(query_outcome) if query_outcome.rows_affected() == 0 => Err(
+				RingError::RowNotFound(format!("Peer ring with id {peer_id}")),
+			),
+			Ok(_query_outcome) => {
+				info!("Removed peer ring {}", peer_id);
+				Ok(())
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in remove_peer_ring: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets every peer ring the ring links to
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_list_peer_rings(&self) -> Result<Vec<PeerRing>, RingError> {
+		match sqlx::query_as("SELECT * FROM peer_rings WHERE ring_id = $1 ORDER BY id")
+			.bind(self.ring.id)
+			.fetch_all(&self.database)
+			.await
+		{
+			Ok(peers) => Ok(peers),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_peer_rings: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Fetches the manifest of a peer ring again, updating its details or recording why it failed
+	///
+	/// # Errors
+	/// [`RingError::Federation`] if the manifest couldn't be fetched or verified
+	/// [`RingError::RowNotFound`] if the ring doesn't link to a peer with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn refresh_peer_ring(&self, peer_id: i64) -> Result<(), RingError> {
+		let Some((manifest_url, public_key)): Option<(String, String)> = sqlx::query_as(
+			"SELECT manifest_url, public_key FROM peer_rings WHERE id = $1 AND ring_id = $2",
+		)
+		.bind(peer_id)
+		.bind(self.ring.id)
+		.fetch_optional(&self.database)
+		.await?
+		else {
+			return Err(RingError::RowNotFound(format!("Peer ring with id {peer_id}")));
+		};
+		self.refresh_peer(peer_id, &manifest_url, public_key).await
+	}
+
+	async fn refresh_peer(
+		&self,
+		peer_id: i64,
+		manifest_url: &str,
+		public_key: String,
+	) -> Result<(), RingError> {
+		match fetch_verified_manifest(manifest_url, Some(public_key)).await {
+			Ok(manifest) => {
+				sqlx::query(
+					"UPDATE peer_rings SET name = $1, home_url = $2, last_fetched_at = $3,
+					last_error = NULL WHERE id = $4",
+				)
+				.bind(manifest.name)
+				.bind(manifest.home_url)
+				.bind(sql::now())
+				.bind(peer_id)
+				.execute(&self.database)
+				.await?;
+				debug!("Refreshed peer ring {}", manifest_url);
+				Ok(())
+			}
+			Err(RingError::Federation(problem)) => {
+				warn!("Could not refresh peer ring {}: {}", manifest_url, problem);
+				sqlx::query(
+					"UPDATE peer_rings SET last_fetched_at = $1, last_error = $2 WHERE id = $3",
+				)
+				.bind(sql::now())
+				.bind(&problem)
+				.bind(peer_id)
+				.execute(&self.database)
+				.await?;
+				Err(RingError::Federation(problem))
+			}
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Refreshes every peer ring of every ring on the instance, returning how many were refreshed
+	/// successfully
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn refresh_all_peer_rings(&self) -> Result<usize, RingError> {
+		let peers: Vec<(i64, String, String)> =
+			sqlx::query_as("SELECT id, manifest_url, public_key FROM peer_rings")
+				.fetch_all(&self.database)
+				.await?;
+		let mut refreshed = 0;
+		for (peer_id, manifest_url, public_key) in peers {
+			match self.refresh_peer(peer_id, &manifest_url, public_key).await {
+				Ok(()) => refreshed += 1,
+				Err(RingError::Federation(_problem)) => {}
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(refreshed)
+	}
+
+	/// Refreshes every peer ring every `interval`, forever
+	pub async fn run_peer_refresh_worker(self, interval: Duration) {
+		let mut ticker = tokio::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match self.refresh_all_peer_rings().await {
+				Ok(refreshed) => debug!("Refreshed {} peer rings", refreshed),
+				Err(e) => error!("Error when refreshing peer rings: {e}"),
+			}
+		}
+	}
+
+	/// Gets the home url of the ring after `current` in the ring of rings
+	///
+	/// The ring of rings is this ring followed by its peers, so the ring after the last peer is
+	/// this ring again. If `current` isn't one of the peers, the first peer is next.
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if the ring doesn't link to any working peers
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_next_ring(&self, current: Option<&str>) -> Result<String, RingError> {
+		let peers: Vec<String> = sqlx::query_scalar(
+			"SELECT home_url FROM peer_rings WHERE ring_id = $1 AND last_error IS NULL ORDER BY id",
+		)
+		.bind(self.ring.id)
+		.fetch_all(&self.database)
+		.await?;
+		let Some(first) = peers.first() else {
+			return Err(RingError::RowNotFound(format!(
+				"Peer rings of ring {}",
+				self.ring.slug
+			)));
+		};
+		let position = current.and_then(|current| peers.iter().position(|url| same_url(url, current)));
+		Ok(match position {
+			Some(position) if position + 1 == peers.len() => self.home_url(),
+			Some(position) => peers[position + 1].clone(),
+			None => first.clone(),
+		})
+	}
+
+	/// Gets the home url of a random peer ring
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if the ring doesn't link to any working peers
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_random_ring(&self) -> Result<String, RingError> {
+		match sqlx::query_scalar(
+			"SELECT home_url FROM peer_rings WHERE ring_id = $1 AND last_error IS NULL
+			ORDER BY random() LIMIT 1",
+		)
+		.bind(self.ring.id)
+		.fetch_optional(&self.database)
+		.await
+		{
+			Ok(Some(url)) => Ok(url),
+			Ok(None) => Err(RingError::RowNotFound(format!(
+				"Peer rings of ring {}",
+				self.ring.slug
+			))),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_random_ring: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		ManifestNavigation, RingManifest, generate_signing_key, sign_manifest, verify_manifest,
+	};
+
+	const URL: &str = "https://ring.example/ring.json";
+
+	fn manifest(home_url: &str) -> RingManifest {
+		let url = |path: &str| format!("https://ring.example{path}");
+		RingManifest {
+			version: super::MANIFEST_VERSION,
+			name: "Example".to_owned(),
+			description: None,
+			home_url: home_url.to_owned(),
+			manifest_url: URL.to_owned(),
+			navigation: ManifestNavigation {
+				next: url("/next"),
+				prev: url("/prev"),
+				random: url("/random"),
+				next_ring: url("/next-ring"),
+				random_ring: url("/random-ring"),
+			},
+			members: Vec::new(),
+			peers: Vec::new(),
+			public_key: String::new(),
+			generated_at: 0,
+		}
+	}
+
+	/// Signs the manifest, returning the body and signature
+	fn signed(mut manifest: RingManifest) -> (String, String) {
+		let key = generate_signing_key();
+		manifest.public_key = hex::encode(key.verifying_key().to_bytes());
+		let body = serde_json::to_string(&manifest).unwrap();
+		let signature = sign_manifest(&key, &body);
+		(body, signature)
+	}
+
+	#[test]
+	fn verifies_signed_manifests() {
+		let (body, signature) = signed(manifest("https://ring.example/"));
+		let manifest = verify_manifest(URL, &body, &signature, None).unwrap();
+		assert!(verify_manifest(URL, &body, &signature, Some(&manifest.public_key)).is_ok());
+		assert!(verify_manifest(URL, &body, &signature, Some("00")).is_err());
+		assert!(
+			verify_manifest(URL, &body.replace("Example", "Exemple"), &signature, None).is_err()
+		);
+	}
+
+	#[test]
+	fn rejects_manifests_for_other_urls() {
+		let (body, signature) = signed(manifest("https://ring.example/"));
+		assert!(
+			verify_manifest("https://other.example/ring.json", &body, &signature, None).is_err()
+		);
+	}
+
+	#[test]
+	fn rejects_home_urls_that_arent_web_pages() {
+		let (body, signature) = signed(manifest("javascript:alert(1)"));
+		assert!(verify_manifest(URL, &body, &signature, None).is_err());
+	}
+}
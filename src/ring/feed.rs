@@ -0,0 +1,123 @@
+//! This module handles the feed of sites joining and leaving the webring
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{error, instrument};
+
+use super::{
+	RingError, RingState,
+	sql::{self, Flag},
+};
+
+/// The timestamp used as the feed's last update when nothing has happened yet
+pub const FEED_EPOCH: &str = "1970-01-01 00:00:00";
+
+/// A site joining or leaving the webring
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FeedEntry {
+	/// Whether the site joined the ring, rather than left it
+	#[sqlx(try_from = "Flag")]
+	pub joined: bool,
+	/// The site's id if it joined, or the id of the removal if it left
+	pub id: i64,
+	pub root_url: String,
+	pub title: Option<String>,
+	pub description: Option<String>,
+	pub owner_name: Option<String>,
+	/// When it happened, as `YYYY-MM-DD HH:MM:SS` in UTC
+	pub date: String,
+}
+
+impl FeedEntry {
+	/// A one line summary of what happened, e.g. `Example joined the ring`
+	#[must_use]
+	pub fn headline(&self) -> String {
+		let name = self.title.as_deref().unwrap_or(&self.root_url);
+		if self.joined {
+			format!("{name} joined the ring")
+		} else {
+			format!("{name} left the ring")
+		}
+	}
+
+	/// When it happened, formatted for Atom feeds
+	#[must_use]
+	pub fn updated(&self) -> String {
+		rfc3339(&self.date)
+	}
+
+	/// When it happened, formatted for RSS feeds
+	#[must_use]
+	pub fn pub_date(&self) -> String {
+		http_date(&self.date)
+	}
+}
+
+/// Formats a `YYYY-MM-DD HH:MM:SS` timestamp from the database as RFC 3339, e.g.
+/// `2025-05-09T12:21:47Z`
+#[must_use]
+pub fn rfc3339(timestamp: &str) -> String {
+	format!("{}Z", timestamp.replacen(' ', "T", 1))
+}
+
+/// Formats a `YYYY-MM-DD HH:MM:SS` timestamp from the database the way HTTP headers and RSS feeds
+/// expect, e.g. `Fri, 09 May 2025 12:21:47 GMT`
+#[must_use]
+pub fn http_date(timestamp: &str) -> String {
+	sql::parse_timestamp(timestamp)
+		.unwrap_or_default()
+		.format(sql::HTTP_DATE_FORMAT)
+		.to_string()
+}
+
+impl RingState {
+	/// Gets the most recent sites to join or leave the ring, newest first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_membership_feed(&self, limit: i64) -> Result<Vec<FeedEntry>, RingError> {
+		match sqlx::query_as(
+			"SELECT * FROM (
+				SELECT 1 AS joined, s.id, s.root_url, s.title, s.description, s.owner_name,
+				COALESCE(ar.approved_at, ar.date_added || ' 00:00:00') AS date FROM sites AS s
+				INNER JOIN approval_records AS ar ON s.approval_id = ar.id WHERE s.ring_id = $1
+				UNION ALL
+				SELECT 0 AS joined, id, root_url, title, NULL, NULL, date_removed AS date
+				FROM site_removals WHERE ring_id = $1
+			) AS entries ORDER BY date DESC, id DESC LIMIT $2",
+		)
+		.bind(self.ring.id)
+		.bind(limit)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(entries) => Ok(entries),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_membership_feed: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{http_date, rfc3339};
+
+	#[test]
+	fn formats_dates() {
+		assert_eq!(
+			http_date("2025-05-09 12:21:47"),
+			"Fri, 09 May 2025 12:21:47 GMT"
+		);
+		assert_eq!(
+			http_date("2024-02-29 00:00:00"),
+			"Thu, 29 Feb 2024 00:00:00 GMT"
+		);
+		assert_eq!(rfc3339("2025-05-09 12:21:47"), "2025-05-09T12:21:47Z");
+	}
+}
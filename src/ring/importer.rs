@@ -0,0 +1,677 @@
+//! This module handles bringing members over from a webring that was kept some other way
+//!
+//! Sites can be read from a plain list of urls, JSON, CSV, or the links on an existing ring's HTML
+//! page, see [`parse_import`]. [`RingState::preview_import`] checks what was read against every
+//! site already in the ring, and [`RingState::import_sites`] adds the new ones, either approved
+//! or waiting for approval. The admin doing the import vouches for the sites, so their owners don't
+//! have to prove they own them, and nobody is emailed.
+
+use std::{collections::HashSet, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use super::{RingError, RingState, SiteProfile, tags::parse_tags};
+
+/// What the approval record of an imported site says it came from
+pub const IMPORT_SOURCE: &str = "import";
+
+/// How a list of sites to import is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+	/// One url per line, optionally followed by the site's title
+	Urls,
+	/// An array of urls or of objects with a `url`, or an object with such an array as `sites`
+	Json,
+	/// Comma separated values, with a header row naming the columns if there's more than a url
+	Csv,
+	/// Every absolute link on an HTML page
+	Html,
+}
+
+impl ImportFormat {
+	/// Guesses the format of `input` from how it starts
+	#[must_use]
+	pub fn detect(input: &str) -> Self {
+		let input = input.trim_start();
+		if input.starts_with('[') || input.starts_with('{') {
+			Self::Json
+		} else if input.starts_with('<') {
+			Self::Html
+		} else if input.lines().next().is_some_and(|line| line.contains(',')) {
+			Self::Csv
+		} else {
+			Self::Urls
+		}
+	}
+}
+
+impl FromStr for ImportFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"urls" => Ok(Self::Urls),
+			"json" => Ok(Self::Json),
+			"csv" => Ok(Self::Csv),
+			"html" => Ok(Self::Html),
+			_ => Err(format!("Unknown import format {s:?}, expected urls, json, csv or html")),
+		}
+	}
+}
+
+impl Display for ImportFormat {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Urls => "urls",
+			Self::Json => "json",
+			Self::Csv => "csv",
+			Self::Html => "html",
+		})
+	}
+}
+
+/// A site read from another webring's list
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ImportedSite {
+	pub url: String,
+	/// The owner's email, if the list has one. The importing admin's is used if it doesn't.
+	pub email: Option<String>,
+	#[serde(flatten)]
+	pub profile: SiteProfile,
+	pub tags: Vec<String>,
+}
+
+impl std::fmt::Debug for ImportedSite {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ImportedSite")
+			.field("url", &self.url)
+			.field("email", &self.email.as_ref().map(|_email| "redacted"))
+			.field("profile", &self.profile)
+			.field("tags", &self.tags)
+			.finish()
+	}
+}
+
+impl ImportedSite {
+	fn new(url: &str, title: Option<&str>) -> Self {
+		Self {
+			url: url.trim().to_owned(),
+			profile: SiteProfile {
+				title: title.map(str::to_owned),
+				..SiteProfile::default()
+			}
+			.normalised(),
+			..Self::default()
+		}
+	}
+
+	/// Finds why the site can't be added, if there's a reason
+	fn check(&self) -> Result<(), String> {
+		let Some(rest) = self
+			.url
+			.strip_prefix("https://")
+			.or_else(|| self.url.strip_prefix("http://"))
+		else {
+			return Err("The url must start with http:// or https://".to_owned());
+		};
+		if rest.is_empty() || self.url.contains(char::is_whitespace) {
+			return Err("The url isn't valid".to_owned());
+		}
+		if let Some(email) = &self.email
+			&& let Err(e) = email.parse::<lettre::Address>()
+		{
+			return Err(format!("The email is invalid: {e}"));
+		}
+		match self.profile.validate().and_then(|()| parse_tags(&self.tags.join(" "))) {
+			Ok(_tags) => Ok(()),
+			Err(RingError::InvalidInput(problem)) => Err(problem),
+			Err(e) => Err(e.to_string()),
+		}
+	}
+}
+
+/// Whether an imported site can be added
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ImportStatus {
+	/// The site will be added
+	New,
+	/// A site with the same url is already in this ring
+	Duplicate,
+	/// The site is earlier in the list too
+	Repeated,
+	/// The site can't be added, for the given reason
+	Invalid(String),
+}
+
+impl Display for ImportStatus {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::New => f.write_str("new"),
+			Self::Duplicate => f.write_str("already in this ring"),
+			Self::Repeated => f.write_str("repeated in the list"),
+			Self::Invalid(problem) => write!(f, "invalid: {problem}"),
+		}
+	}
+}
+
+/// A site read from another webring's list, and whether it can be added
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportPreview {
+	pub site: ImportedSite,
+	pub status: ImportStatus,
+}
+
+/// Reads the sites in `input`, without checking any of them
+///
+/// # Errors
+/// [`RingError::InvalidInput`] if the input is JSON that isn't a list of sites
+pub fn parse_import(format: ImportFormat, input: &str) -> Result<Vec<ImportedSite>, RingError> {
+	match format {
+		ImportFormat::Urls => Ok(parse_urls(input)),
+		ImportFormat::Json => parse_json(input),
+		ImportFormat::Csv => Ok(parse_csv(input)),
+		ImportFormat::Html => Ok(parse_html(input)),
+	}
+}
+
+fn parse_urls(input: &str) -> Vec<ImportedSite> {
+	input
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(|line| match line.split_once(char::is_whitespace) {
+			Some((url, title)) => ImportedSite::new(url, Some(title)),
+			None => ImportedSite::new(line, None),
+		})
+		.collect()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonList {
+	Sites(Vec<JsonSite>),
+	/// Like the ring's own `/list.json`
+	Directory { sites: Vec<JsonSite> },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonSite {
+	Url(String),
+	Details(JsonSiteDetails),
+}
+
+#[derive(Deserialize)]
+struct JsonSiteDetails {
+	#[serde(alias = "root_url", alias = "href", alias = "link")]
+	url: String,
+	email: Option<String>,
+	#[serde(alias = "name")]
+	title: Option<String>,
+	description: Option<String>,
+	#[serde(alias = "owner")]
+	owner_name: Option<String>,
+	#[serde(alias = "feed")]
+	feed_url: Option<String>,
+	#[serde(alias = "button")]
+	button_url: Option<String>,
+	#[serde(default)]
+	tags: JsonTags,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonTags {
+	List(Vec<String>),
+	/// Separated by spaces or commas
+	Text(String),
+}
+
+impl Default for JsonTags {
+	fn default() -> Self {
+		Self::List(Vec::new())
+	}
+}
+
+fn parse_json(input: &str) -> Result<Vec<ImportedSite>, RingError> {
+	let sites = match serde_json::from_str(input) {
+		Ok(JsonList::Sites(sites) | JsonList::Directory { sites }) => sites,
+		Err(e) => {
+			return Err(RingError::InvalidInput(format!(
+				"The JSON isn't a list of sites: {e}"
+			)));
+		}
+	};
+	Ok(sites
+		.into_iter()
+		.map(|site| match site {
+			JsonSite::Url(url) => ImportedSite::new(&url, None),
+			JsonSite::Details(details) => ImportedSite {
+				url: details.url.trim().to_owned(),
+				email: details.email.map(|email| email.trim().to_owned()),
+				profile: SiteProfile {
+					title: details.title,
+					description: details.description,
+					owner_name: details.owner_name,
+					feed_url: details.feed_url,
+					button_url: details.button_url,
+				}
+				.normalised(),
+				tags: match details.tags {
+					JsonTags::List(tags) => tags,
+					JsonTags::Text(tags) => vec![tags],
+				},
+			},
+		})
+		.collect())
+}
+
+/// The names a column holding each field of a site can have in a CSV header
+const CSV_URL_COLUMNS: [&str; 5] = ["url", "root_url", "link", "href", "website"];
+
+fn parse_csv(input: &str) -> Vec<ImportedSite> {
+	let mut records = csv_records(input).into_iter();
+	let Some(first) = records.next() else {
+		return Vec::new();
+	};
+	let header: Vec<String> = first
+		.iter()
+		.map(|name| name.trim().to_lowercase())
+		.collect();
+	let column = |names: &[&str]| header.iter().position(|name| names.contains(&name.as_str()));
+	let Some(url_column) = column(&CSV_URL_COLUMNS) else {
+		// Without a header, the first column is the url and the second the title
+		return std::iter::once(first)
+			.chain(records)
+			.map(|record| {
+				ImportedSite::new(
+					record.first().map_or("", String::as_str),
+					record.get(1).map(String::as_str),
+				)
+			})
+			.collect();
+	};
+	let columns = [
+		column(&["email"]),
+		column(&["title", "name"]),
+		column(&["description"]),
+		column(&["owner_name", "owner"]),
+		column(&["feed_url", "feed"]),
+		column(&["button_url", "button"]),
+		column(&["tags"]),
+	];
+
+	records
+		.map(|record| {
+			let field = |index: Option<usize>| {
+				index
+					.and_then(|index| record.get(index))
+					.map(|field| field.trim().to_owned())
+					.filter(|field| !field.is_empty())
+			};
+			let [email, title, description, owner_name, feed_url, button_url, tags] =
+				columns.map(field);
+			ImportedSite {
+				url: field(Some(url_column)).unwrap_or_default(),
+				email,
+				profile: SiteProfile {
+					title,
+					description,
+					owner_name,
+					feed_url,
+					button_url,
+				}
+				.normalised(),
+				tags: tags.into_iter().collect(),
+			}
+		})
+		.collect()
+}
+
+/// Splits CSV into records of fields, as RFC 4180 describes, skipping blank lines
+fn csv_records(input: &str) -> Vec<Vec<String>> {
+	let mut records = Vec::new();
+	let mut record = Vec::new();
+	let mut field = String::new();
+	let mut quoted = false;
+	let mut chars = input.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' if quoted => {
+				if chars.next_if_eq(&'"').is_some() {
+					field.push('"');
+				} else {
+					quoted = false;
+				}
+			}
+			'"' if field.is_empty() => quoted = true,
+			',' if !quoted => record.push(std::mem::take(&mut field)),
+			'\r' if !quoted => {}
+			'\n' if !quoted => {
+				record.push(std::mem::take(&mut field));
+				records.push(std::mem::take(&mut record));
+			}
+			c => field.push(c),
+		}
+	}
+	if !field.is_empty() || !record.is_empty() {
+		record.push(field);
+		records.push(record);
+	}
+	records.retain(|record| record.iter().any(|field| !field.trim().is_empty()));
+	records
+}
+
+/// Finds every absolute link on the page, using the link's text as the site's title
+///
+/// This doesn't parse the HTML properly, but ring pages are usually simple lists of links. Links
+/// back to the ring itself are read too, so the preview is worth checking.
+fn parse_html(input: &str) -> Vec<ImportedSite> {
+	// Only ascii letters are lowercased, so every position in this is the same in the input
+	let lower = input.to_ascii_lowercase();
+	let mut sites = Vec::new();
+	let mut position = 0;
+	while let Some(found) = lower[position..].find("<a") {
+		let attributes_start = position + found + 2;
+		let Some(tag_length) = lower[attributes_start..].find('>') else {
+			break;
+		};
+		let tag_end = attributes_start + tag_length;
+		position = tag_end + 1;
+		// Other tags start with an a too, like <abbr> and <article>
+		if !lower[attributes_start..].starts_with(|c: char| c.is_ascii_whitespace()) {
+			continue;
+		}
+		let Some(href) = attribute(&input[attributes_start..tag_end], "href") else {
+			continue;
+		};
+		let href = decode_entities(&href);
+		if !(href.starts_with("http://") || href.starts_with("https://")) {
+			continue;
+		}
+		let text_end = lower[position..]
+			.find("</a")
+			.map_or(input.len(), |length| position + length);
+		let title = decode_entities(&strip_tags(&input[position..text_end]));
+		sites.push(ImportedSite::new(&href, Some(&title)));
+	}
+	sites
+}
+
+/// Gets the value of the attribute `name` from the inside of a tag, after its name
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+	let lower = attributes.to_ascii_lowercase();
+	let mut position = 0;
+	while let Some(found) = lower[position..].find(name) {
+		let start = position + found;
+		position = start + name.len();
+		// This could be the end of another attribute's name, like data-href
+		if !lower[..start].ends_with(|c: char| c.is_ascii_whitespace()) {
+			continue;
+		}
+		let Some(value) = attributes[position..].trim_start().strip_prefix('=') else {
+			continue;
+		};
+		let value = value.trim_start();
+		let value = match value.chars().next() {
+			Some(quote @ ('"' | '\'')) => value[1..].split(quote).next(),
+			_ => value.split(|c: char| c.is_ascii_whitespace()).next(),
+		};
+		return value.map(str::to_owned);
+	}
+	None
+}
+
+fn strip_tags(html: &str) -> String {
+	let mut text = String::new();
+	let mut in_tag = false;
+	for c in html.chars() {
+		match c {
+			'<' => in_tag = true,
+			'>' => in_tag = false,
+			c if !in_tag => text.push(c),
+			_ => {}
+		}
+	}
+	text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Decodes the few entities that turn up in links and their text
+fn decode_entities(text: &str) -> String {
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&#39;", "'")
+		.replace("&apos;", "'")
+		.replace("&nbsp;", " ")
+		.replace("&amp;", "&")
+}
+
+/// What a url is compared by, so `https://example.com/` and `https://Example.com` are the same
+fn url_key(url: &str) -> String {
+	url.trim_end_matches('/').to_lowercase()
+}
+
+/// Checks every site in `sites`, against each other and the urls of the sites already in the
+/// ring
+fn preview(sites: Vec<ImportedSite>, existing: &[String]) -> Vec<ImportPreview> {
+	let existing: HashSet<String> = existing.iter().map(|url| url_key(url)).collect();
+	let mut seen = HashSet::new();
+	sites
+		.into_iter()
+		.map(|site| {
+			let status = if let Err(problem) = site.check() {
+				ImportStatus::Invalid(problem)
+			} else if existing.contains(&url_key(&site.url)) {
+				ImportStatus::Duplicate
+			} else if !seen.insert(url_key(&site.url)) {
+				ImportStatus::Repeated
+			} else {
+				ImportStatus::New
+			};
+			ImportPreview { site, status }
+		})
+		.collect()
+}
+
+impl RingState {
+	/// Checks which of `sites` can be imported, and why the others can't
+	///
+	/// Sites are compared with every site in this ring, including the ones waiting for a decision
+	/// and the denied ones. A site in another ring can still be imported into this one.
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument(skip(sites))]
+	pub async fn preview_import(
+		&self,
+		sites: Vec<ImportedSite>,
+	) -> Result<Vec<ImportPreview>, RingError> {
+		let existing = self.store.get_list_site_urls(self.ring.id).await?;
+		Ok(preview(sites, &existing))
+	}
+
+	/// Adds every site in `sites` that [`RingState::preview_import`] says is new to the ring, in
+	/// the order they were listed, returning how many were added
+	///
+	/// If `approve` is set, each site gets an approval record by `admin_id` marked as an import,
+	/// otherwise they wait for approval like any other application. Sites without an email get
+	/// `fallback_email`. No emails are sent and no webhooks are fired, as an import would send a
+	/// flood of them.
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument(skip(sites, fallback_email))]
+	pub async fn import_sites(
+		&self,
+		sites: Vec<ImportedSite>,
+		admin_id: i64,
+		fallback_email: &str,
+		approve: bool,
+	) -> Result<usize, RingError> {
+		let existing = self.store.get_list_site_urls(self.ring.id).await?;
+		let mut new_sites = Vec::new();
+		for ImportPreview { mut site, status } in preview(sites, &existing) {
+			if status == ImportStatus::New {
+				site.tags = parse_tags(&site.tags.join(" "))?;
+				new_sites.push(site);
+			}
+		}
+
+		self.store
+			.import_sites(
+				self.ring.id,
+				&new_sites,
+				fallback_email,
+				approve.then_some(admin_id),
+			)
+			.await?;
+		let added = new_sites.len();
+		self.topology.invalidate(self.ring.id);
+		info!("Imported {} sites into ring {}", added, self.ring.slug);
+		Ok(added)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ImportFormat, ImportStatus, ImportedSite, parse_import, preview, url_key};
+
+	fn parse(format: ImportFormat, input: &str) -> Vec<ImportedSite> {
+		parse_import(format, input).unwrap()
+	}
+
+	fn title(site: &ImportedSite) -> Option<&str> {
+		site.profile.title.as_deref()
+	}
+
+	#[test]
+	fn detects_formats() {
+		assert_eq!(
+			ImportFormat::detect("  [\"https://a.example\"]"),
+			ImportFormat::Json
+		);
+		assert_eq!(ImportFormat::detect("<ul></ul>"), ImportFormat::Html);
+		assert_eq!(ImportFormat::detect("url,title\n"), ImportFormat::Csv);
+		assert_eq!(
+			ImportFormat::detect("https://a.example A"),
+			ImportFormat::Urls
+		);
+	}
+
+	#[test]
+	fn parses_urls() {
+		let sites = parse(
+			ImportFormat::Urls,
+			"# Our members\nhttps://a.example  A site\n\n  https://b.example\n",
+		);
+		assert_eq!(sites.len(), 2);
+		assert_eq!(sites[0].url, "https://a.example");
+		assert_eq!(title(&sites[0]), Some("A site"));
+		assert_eq!(sites[1].url, "https://b.example");
+		assert_eq!(title(&sites[1]), None);
+	}
+
+	#[test]
+	fn parses_json() {
+		let sites = parse(
+			ImportFormat::Json,
+			r#"["https://a.example", {"root_url": " https://b.example ", "name": "B",
+			"email": "b@example.com", "tags": "art, music"}]"#,
+		);
+		assert_eq!(sites.len(), 2);
+		assert_eq!(sites[0].url, "https://a.example");
+		assert_eq!(sites[1].url, "https://b.example");
+		assert_eq!(title(&sites[1]), Some("B"));
+		assert_eq!(sites[1].email.as_deref(), Some("b@example.com"));
+		assert_eq!(sites[1].tags, ["art, music"]);
+
+		// The ring's own /list.json
+		let sites = parse(
+			ImportFormat::Json,
+			r#"{"name": "Ring", "sites": [{"url": "https://c.example", "tags": ["art"]}]}"#,
+		);
+		assert_eq!(sites[0].url, "https://c.example");
+		assert_eq!(sites[0].tags, ["art"]);
+
+		assert!(parse_import(ImportFormat::Json, r#"{"url": "https://a.example"}"#).is_err());
+	}
+
+	#[test]
+	fn parses_csv() {
+		let sites = parse(
+			ImportFormat::Csv,
+			"Name,Website,Tags\r\n\"Tom \"\"the\"\" cat, esq\",https://a.example,art\r\n\r\n\
+			,https://b.example,\n",
+		);
+		assert_eq!(sites.len(), 2);
+		assert_eq!(sites[0].url, "https://a.example");
+		assert_eq!(title(&sites[0]), Some("Tom \"the\" cat, esq"));
+		assert_eq!(sites[0].tags, ["art"]);
+		assert_eq!(title(&sites[1]), None);
+		assert!(sites[1].tags.is_empty());
+
+		// Without a header, the url comes first
+		let sites = parse(
+			ImportFormat::Csv,
+			"https://a.example,A\nhttps://b.example\n",
+		);
+		assert_eq!(sites.len(), 2);
+		assert_eq!(title(&sites[0]), Some("A"));
+		assert_eq!(sites[1].url, "https://b.example");
+	}
+
+	#[test]
+	fn parses_html() {
+		let sites = parse(
+			ImportFormat::Html,
+			r#"<ul>
+				<li><a class="site" href="https://a.example/?a=1&amp;b=2"><b>Tom &amp; Jerry</b></a></li>
+				<li><A HREF='https://b.example'>B</A></li>
+				<li><a href="/join">Join</a></li>
+				<li><a data-href="https://c.example">C</a></li>
+				<li><abbr title="https://d.example">D</abbr></li>
+			</ul>"#,
+		);
+		assert_eq!(sites.len(), 2);
+		assert_eq!(sites[0].url, "https://a.example/?a=1&b=2");
+		assert_eq!(title(&sites[0]), Some("Tom & Jerry"));
+		assert_eq!(sites[1].url, "https://b.example");
+		assert_eq!(title(&sites[1]), Some("B"));
+	}
+
+	#[test]
+	fn compares_urls_loosely() {
+		assert_eq!(
+			url_key("https://Example.com/"),
+			url_key("https://example.com")
+		);
+		assert_ne!(
+			url_key("https://example.com/a"),
+			url_key("https://example.com")
+		);
+	}
+
+	#[test]
+	fn previews_imports() {
+		let sites = parse(
+			ImportFormat::Urls,
+			"https://new.example\nhttps://Existing.example/\nhttps://new.example/\nftp://bad.example",
+		);
+		let statuses: Vec<ImportStatus> = preview(sites, &["https://existing.example".to_owned()])
+			.into_iter()
+			.map(|preview| preview.status)
+			.collect();
+		assert_eq!(
+			statuses[..3],
+			[
+				ImportStatus::New,
+				ImportStatus::Duplicate,
+				ImportStatus::Repeated
+			]
+		);
+		assert!(matches!(statuses[3], ImportStatus::Invalid(_)));
+	}
+}
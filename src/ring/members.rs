@@ -0,0 +1,157 @@
+//! This module handles site owners managing their own membership of the webring
+//!
+//! Owners log in with a single use link sent to the email stored for their site, rather than with
+//! a password.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use tracing::{error, info, instrument};
+
+use super::{RingError, RingState, SiteProfile, auth::generate_token, sql};
+
+/// How long a login link stays valid for, in seconds
+const LOGIN_TOKEN_LIFETIME: u64 = 60 * 60;
+
+/// A site as seen by its owner, whatever state its application is in
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct MemberSite {
+	pub id: i64,
+	pub root_url: String,
+	pub email: String,
+	pub email_verified_at: Option<String>,
+	#[sqlx(flatten)]
+	pub profile: SiteProfile,
+	pub approval_date: Option<String>,
+	pub denial_date: Option<String>,
+	pub denial_reason: Option<String>,
+}
+
+impl MemberSite {
+	#[must_use]
+	pub const fn is_approved(&self) -> bool {
+		self.approval_date.is_some()
+	}
+
+	#[must_use]
+	pub const fn is_denied(&self) -> bool {
+		self.denial_date.is_some()
+	}
+}
+
+/// Login tokens are stored hashed, so a leaked database can't be used to log in
+fn hash_token(token: &str) -> String {
+	hex::encode(Sha256::digest(token))
+}
+
+impl RingState {
+	/// Queues an email to the owner of a site with a link that logs them in to manage it
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that url
+	/// [`RingError::Email`] if there is no SMTP server and the email could not be logged
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn send_member_login_link(&self, root_url: &str) -> Result<(), RingError> {
+		let site_id = match self.store.get_site_id(self.ring.id, root_url).await {
+			Ok(site_id) => site_id,
+			Err(RingError::RowNotFound(url)) => {
+				info!("Someone tried to log in as unregistered site {}", root_url);
+				return Err(RingError::RowNotFound(url));
+			}
+			Err(e) => return Err(e),
+		};
+		let site_email = self.store.get_member_site(self.ring.id, site_id).await?.email;
+
+		let token = generate_token();
+		self.store
+			.add_member_login_token(
+				site_id,
+				&hash_token(&token),
+				&sql::seconds_from_now(LOGIN_TOKEN_LIFETIME),
+			)
+			.await?;
+
+		let link = format!(
+			"{}{}/member/login/verify?token={}",
+			self.mailer.base_url(),
+			self.ring.base_path(),
+			token
+		);
+		let ring_name = &self.ring.name;
+		self.queue_email(
+			&site_email,
+			&format!("Log in to manage your {ring_name} membership"),
+			format!(
+				"Someone (hopefully you) asked to log in to manage {root_url} on {ring_name}.\n\n\
+				Log in by visiting {link}\n\n\
+				This link can only be used once and expires in an hour. If this wasn't you, you can ignore this email."
+			),
+		)
+		.await
+	}
+
+	/// Uses up a login token, returning the id of the site it logs in to
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if the token is unknown, used, expired or for another ring
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument(skip(token))]
+	pub async fn redeem_member_login_token(&self, token: &str) -> Result<i64, RingError> {
+		self.store
+			.redeem_member_login_token(self.ring.id, &hash_token(token))
+			.await
+	}
+
+	/// Gets a site along with the outcome of its application
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_member_site(&self, site_id: i64) -> Result<MemberSite, RingError> {
+		self.store.get_member_site(self.ring.id, site_id).await
+	}
+
+	/// Replaces the profile of a site
+	///
+	/// # Errors
+	/// [`RingError::InvalidInput`] if the profile is invalid
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn update_site_profile(
+		&self,
+		site_id: i64,
+		profile: &SiteProfile,
+	) -> Result<(), RingError> {
+		profile.validate()?;
+		self.store
+			.update_site_profile(self.ring.id, site_id, profile)
+			.await?;
+		info!("Updated profile of site {}", site_id);
+		Ok(())
+	}
+
+	/// Changes the email of a site, which then has to be verified again
+	///
+	/// A failure to queue the verification email is logged rather than returned, as the email has
+	/// still been changed
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument(skip(email))]
+	pub async fn change_site_email(&self, site_id: i64, email: &str) -> Result<(), RingError> {
+		let token = generate_token();
+		let root_url = self
+			.store
+			.change_site_email(self.ring.id, site_id, email, &token)
+			.await?;
+		info!("Changed email of site {}", root_url);
+		if let Err(e) = self.send_verification_email(&root_url, email, &token).await {
+			error!("Could not send verification email for {}: {}", root_url, e);
+		}
+		Ok(())
+	}
+}
@@ -1,19 +1,109 @@
 //! This module handles the actual webring capabilities
 
+use std::sync::Arc;
+
 use argon2::password_hash;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
+use sqlx::{AnyPool, FromRow};
 use thiserror::Error;
 use tokio::task;
 use tracing::{debug, error, info, instrument};
 
 pub mod auth;
+pub mod buttons;
+pub mod directory;
+pub mod email;
+pub mod export;
+pub mod federation;
+pub mod feed;
+pub mod importer;
+pub mod members;
+pub mod ownership;
+pub mod planet;
+pub mod rings;
+pub mod sql;
+pub mod stats;
+pub mod store;
+pub mod tags;
+mod topology;
+pub mod webhooks;
+pub mod workers;
+
+use email::Mailer;
+use rings::Ring;
+use stats::NavigationCounts;
+use store::{DatabaseStore, RingStore};
+use topology::{Topology, TopologyCache};
+use webhooks::RingEvent;
+
+/// The optional, public details a site owner can give about their site
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+pub struct SiteProfile {
+	pub title: Option<String>,
+	pub description: Option<String>,
+	pub owner_name: Option<String>,
+	/// The url of the site's RSS or Atom feed
+	pub feed_url: Option<String>,
+	/// The url of an 88x31 button image for the site
+	pub button_url: Option<String>,
+}
+
+impl SiteProfile {
+	const MAX_TITLE_LENGTH: usize = 100;
+	const MAX_DESCRIPTION_LENGTH: usize = 500;
+	const MAX_OWNER_NAME_LENGTH: usize = 100;
+
+	/// Trims every field, treating blank ones as not given
+	#[must_use]
+	pub fn normalised(self) -> Self {
+		let normalise = |field: Option<String>| {
+			field
+				.map(|value| value.trim().to_owned())
+				.filter(|value| !value.is_empty())
+		};
+		Self {
+			title: normalise(self.title),
+			description: normalise(self.description),
+			owner_name: normalise(self.owner_name),
+			feed_url: normalise(self.feed_url),
+			button_url: normalise(self.button_url),
+		}
+	}
+
+	/// Checks that the fields are of a reasonable length and the urls are http(s)
+	///
+	/// # Errors
+	/// [`RingError::InvalidInput`] describing the first invalid field
+	pub fn validate(&self) -> Result<(), RingError> {
+		let check_length = |name: &str, field: &Option<String>, max: usize| match field {
+			Some(value) if value.chars().count() > max => Err(RingError::InvalidInput(format!(
+				"The {name} must be at most {max} characters"
+			))),
+			_ => Ok(()),
+		};
+		let check_url = |name: &str, field: &Option<String>| match field {
+			Some(url) if !(url.starts_with("https://") || url.starts_with("http://")) => Err(
+				RingError::InvalidInput(format!("The {name} must start with http:// or https://")),
+			),
+			_ => Ok(()),
+		};
+		check_length("title", &self.title, Self::MAX_TITLE_LENGTH)?;
+		check_length("description", &self.description, Self::MAX_DESCRIPTION_LENGTH)?;
+		check_length("owner name", &self.owner_name, Self::MAX_OWNER_NAME_LENGTH)?;
+		check_url("feed url", &self.feed_url)?;
+		check_url("button url", &self.button_url)
+	}
+}
 
 #[derive(Clone, Serialize, Deserialize, FromRow)]
 pub struct ApprovedSite {
 	pub site_id: i64,
 	pub root_url: String,
 	pub site_email: String,
+	#[sqlx(flatten)]
+	pub profile: SiteProfile,
+	/// The site's tags, separated by spaces
+	pub tags: Option<String>,
 	pub date_added: String,
 	pub admin_id: i64,
 	pub admin_username: String,
@@ -25,6 +115,7 @@ pub struct UnapprovedSite {
 	pub id: i64,
 	pub root_url: String,
 	pub email: String,
+	pub email_verified_at: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, FromRow)]
@@ -39,9 +130,26 @@ pub struct DeniedSite {
 	pub admin_email: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct RingState {
-	database: SqlitePool,
+	database: AnyPool,
+	/// Where the sites, the decisions about them and the admins are kept
+	store: Arc<dyn RingStore>,
+	/// Whether `store` is the database, so backups see the same sites as everything else
+	store_is_database: bool,
+	/// The order of each ring, shared by the states scoped to every ring
+	topology: Arc<TopologyCache>,
+	navigation: Arc<NavigationCounts>,
+	mailer: Mailer,
+	ring: Arc<Ring>,
+}
+
+impl std::fmt::Debug for RingState {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("RingState")
+			.field("ring", &self.ring.slug)
+			.finish_non_exhaustive()
+	}
 }
 
 #[derive(Debug, Error)]
@@ -50,6 +158,8 @@ pub enum RingError {
 	RowNotFound(String),
 	#[error("The row {0} is already present in the database")]
 	UniqueRowAlreadyPresent(String),
+	#[error("The row {0} is still referred to by other rows")]
+	RowInUse(String),
 	#[error("The site {0} is not approved")]
 	SiteNotApproved(String),
 	#[error(transparent)]
@@ -60,49 +170,115 @@ pub enum RingError {
 	PasswordVerification(password_hash::Error),
 	#[error("An admin method was called outside of an authorised session")]
 	UnauthorisedAdmin,
+	#[error("Email error: {0}")]
+	Email(String),
+	#[error("Invalid input: {0}")]
+	InvalidInput(String),
+	#[error("Could not verify ownership of the site: {0}")]
+	OwnershipNotVerified(String),
+	#[error("Could not federate with the peer ring: {0}")]
+	Federation(String),
+}
+
+impl RingError {
+	/// The name of the variant, for labelling errors without their details
+	#[must_use]
+	pub const fn name(&self) -> &'static str {
+		match self {
+			Self::RowNotFound(_) => "row_not_found",
+			Self::UniqueRowAlreadyPresent(_) => "unique_row_already_present",
+			Self::RowInUse(_) => "row_in_use",
+			Self::SiteNotApproved(_) => "site_not_approved",
+			Self::UnrecoverableDatabaseError(_) => "unrecoverable_database_error",
+			Self::TaskJoin(_) => "task_join",
+			Self::PasswordVerification(_) => "password_verification",
+			Self::UnauthorisedAdmin => "unauthorised_admin",
+			Self::Email(_) => "email",
+			Self::InvalidInput(_) => "invalid_input",
+			Self::OwnershipNotVerified(_) => "ownership_not_verified",
+			Self::Federation(_) => "federation",
+		}
+	}
 }
 
 impl RingState {
+	/// Creates a new [`RingState`] scoped to the default ring, that keeps everything in the
+	/// database and logs emails instead of sending them
 	#[must_use]
-	pub const fn new(database: SqlitePool) -> Self {
-		Self { database }
+	pub fn new(database: AnyPool) -> Self {
+		Self {
+			store: Arc::new(DatabaseStore::new(database.clone())),
+			store_is_database: true,
+			topology: Arc::default(),
+			navigation: Arc::default(),
+			database,
+			mailer: Mailer::default(),
+			ring: Arc::default(),
+		}
 	}
 
-	/// Add a site to the webring
+	/// Sets the [`RingStore`] the rings, their sites, the decisions about them and the admins are
+	/// kept in
+	///
+	/// Backups, see [`RingState::export`], only work with the database, so they're refused after
+	/// this.
+	#[must_use]
+	pub fn with_store(mut self, store: impl RingStore + 'static) -> Self {
+		self.store = Arc::new(store);
+		self.store_is_database = false;
+		self.topology = Arc::default();
+		self
+	}
+
+	/// Sets the [`Mailer`] used to email site owners
+	#[must_use]
+	pub fn with_mailer(mut self, mailer: Mailer) -> Self {
+		self.mailer = mailer;
+		self
+	}
+
+	/// Add a site to the webring, and email the owner a link to verify their email address
+	///
+	/// A failure to queue the email is logged rather than returned, as the site has still been
+	/// added
 	///
 	/// # Errors
+	/// Returns [`RingError::InvalidInput`] if the profile is invalid
 	/// Returns [`RingError::SiteAlreadyPresent`] if the site has already been registered
 	/// Otherwise, [`RingError::UnrecoverableDatabaseError`]
-	#[instrument]
-	pub async fn add_site(&self, root_url: &str, email: &str) -> Result<(), RingError> {
-		match sqlx::query!(
-			"INSERT INTO sites (root_url, email) values (?, ?)",
-			root_url,
-			email
-		)
-		.bind(root_url)
-		.bind(email)
-		.execute(&self.database)
-		.await
+	#[instrument(skip(email))]
+	pub async fn add_site(
+		&self,
+		root_url: &str,
+		email: &str,
+		profile: &SiteProfile,
+	) -> Result<(), RingError> {
+		profile.validate()?;
+		let token = auth::generate_token();
+		match self
+			.store
+			.add_site(self.ring.id, root_url, email, &token, profile)
+			.await
 		{
-			Ok(_query_outcome) => {
+			Ok(()) => {
 				info!("Unapproved site {} added to database", root_url);
+				if let Err(e) = self.send_verification_email(root_url, email, &token).await {
+					error!("Could not send verification email for {}: {}", root_url, e);
+				}
+				self.enqueue_event(RingEvent::SiteApplied {
+					root_url: root_url.to_owned(),
+				})
+				.await;
 				Ok(())
 			}
-			Err(sqlx::Error::Database(ref e)) if e.code().as_deref() == Some("2067") => {
+			Err(RingError::UniqueRowAlreadyPresent(url)) => {
 				info!(
 					"Someone tried to register their site {} but it was already registered",
 					root_url
 				);
-				Err(RingError::UniqueRowAlreadyPresent(root_url.to_owned()))
-			}
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in add_site: {}",
-					e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
+				Err(RingError::UniqueRowAlreadyPresent(url))
 			}
+			Err(e) => Err(e),
 		}
 	}
 
@@ -113,81 +289,88 @@ impl RingState {
 	/// Otherwise, [`RingError::UnrecoverableDatabaseError`]
 	#[instrument]
 	pub async fn remove_site(&self, root_url: &str) -> Result<(), RingError> {
-		match sqlx::query!("DELETE FROM sites WHERE root_url = ?", root_url)
-			.bind(root_url)
-			.execute(&self.database)
-			.await
-		{
-			Ok(query_outcome) => {
-				if query_outcome.rows_affected() == 0 {
-					info!(
-						"Someone tried to remove their site {} but it was already not there",
-						root_url
-					);
-					Err(RingError::RowNotFound(root_url.to_owned()))
-				} else {
-					info!("Site {} removed from webring", root_url);
-					Ok(())
-				}
+		match self.store.remove_site(self.ring.id, root_url).await {
+			Ok(()) => {
+				self.topology.invalidate(self.ring.id);
+				info!("Site {} removed from webring", root_url);
+				self.enqueue_event(RingEvent::SiteRemoved {
+					root_url: root_url.to_owned(),
+				})
+				.await;
+				Ok(())
 			}
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in remove_site: {}",
-					e
+			Err(RingError::RowNotFound(url)) => {
+				info!(
+					"Someone tried to remove their site {} but it was already not there",
+					root_url
 				);
-				Err(RingError::UnrecoverableDatabaseError(e))
+				Err(RingError::RowNotFound(url))
 			}
+			Err(e) => Err(e),
 		}
 	}
 
-	/// Approves a site for the webring
+	/// Moves a site to a new url, once the owner has proven they control it
+	///
+	/// The site keeps its id, and so its place in the webring and its approval. If
+	/// `grace_period_days` is given, navigating from the old url keeps working for that many days.
 	///
 	/// # Errors
+	/// [`RingError::OwnershipNotVerified`] if the new url isn't hosting its auth hash
+	/// [`RingError::UniqueRowAlreadyPresent`] if another site already has the new url
+	/// [`RingError::RowNotFound`] if there is no site with that id
 	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
 	#[instrument]
-	pub async fn approve_site(&self, root_url: &str, admin_id: i64) -> Result<(), RingError> {
-		let mut tx = match self.database.begin().await {
-			Ok(tx) => tx,
-			Err(e) => return Err(RingError::UnrecoverableDatabaseError(e)),
-		};
-
-		let approval_id = match sqlx::query!(
-			"INSERT INTO approval_records (date_added, admin_id) VALUES (date('now'), ?)",
-			admin_id
-		)
-		.execute(&mut *tx)
-		.await
-		{
-			Ok(query_outcome) => query_outcome.last_insert_rowid(),
-			Err(e) => {
-				error!("There was an error when adding an approval record");
-				return Err(RingError::UnrecoverableDatabaseError(e));
-			}
-		};
-
-		if let Err(e) = sqlx::query!(
-			"UPDATE sites SET approval_id = ? WHERE root_url = ?",
-			approval_id,
-			root_url
-		)
-		.execute(&mut *tx)
-		.await
-		{
-			// TODO: Distinguish for the type of error you get when there is a constraint error
-			// (e.g. there is already a denial_id set or vice versa)
-			return Err(RingError::UnrecoverableDatabaseError(e));
-		}
+	pub async fn change_site_url(
+		&self,
+		site_id: i64,
+		new_url: &str,
+		grace_period_days: Option<u32>,
+	) -> Result<(), RingError> {
+		ownership::verify_ownership(new_url).await?;
+		let old_url = self
+			.store
+			.change_site_url(
+				self.ring.id,
+				site_id,
+				new_url,
+				grace_period_days.map(sql::days_from_now),
+			)
+			.await?;
+		self.topology.invalidate(self.ring.id);
 
-		if let Err(e) = tx.commit().await {
-			return Err(RingError::UnrecoverableDatabaseError(e));
-		}
+		info!("Site {} moved to {}", old_url, new_url);
+		self.enqueue_event(RingEvent::SiteMoved {
+			old_url,
+			new_url: new_url.to_owned(),
+		})
+		.await;
+		Ok(())
+	}
 
+	/// Approves a site for the webring
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that url in the ring
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn approve_site(&self, root_url: &str, admin_id: i64) -> Result<(), RingError> {
+		self.store
+			.approve_site(self.ring.id, root_url, admin_id)
+			.await?;
+		self.topology.invalidate(self.ring.id);
+		self.enqueue_event(RingEvent::SiteApproved {
+			root_url: root_url.to_owned(),
+			admin_id,
+		})
+		.await;
 		Ok(())
 	}
 
 	/// Denies a site for the webring
 	///
 	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that url in the ring
 	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
 	#[instrument]
 	pub async fn deny_site(
@@ -196,89 +379,52 @@ impl RingState {
 		reason: &str,
 		admin_id: i64,
 	) -> Result<(), RingError> {
-		let mut tx = match self.database.begin().await {
-			Ok(tx) => tx,
-			Err(e) => return Err(RingError::UnrecoverableDatabaseError(e)),
-		};
-
-		let denial_id = match sqlx::query!(
-			"INSERT INTO denial_records (date_added, admin_id, reason) VALUES (date('now'), ?, ?)",
+		self.store
+			.deny_site(self.ring.id, root_url, reason, admin_id)
+			.await?;
+		self.enqueue_event(RingEvent::SiteDenied {
+			root_url: root_url.to_owned(),
+			reason: reason.to_owned(),
 			admin_id,
-			reason
-		)
-		.execute(&mut *tx)
-		.await
-		{
-			Ok(query_outcome) => query_outcome.last_insert_rowid(),
-			Err(e) => {
-				error!("There was an error when adding a denial record");
-				return Err(RingError::UnrecoverableDatabaseError(e));
-			}
-		};
-
-		if let Err(e) = sqlx::query!(
-			"UPDATE sites SET denial_id = ? WHERE root_url = ?",
-			denial_id,
-			root_url
-		)
-		.execute(&mut *tx)
-		.await
-		{
-			return Err(RingError::UnrecoverableDatabaseError(e));
-		}
-
-		if let Err(e) = tx.commit().await {
-			return Err(RingError::UnrecoverableDatabaseError(e));
-		}
-
+		})
+		.await;
 		Ok(())
 	}
 
-	/// Gets the webring site after the current one
+	/// Gets the webring site after the current one, optionally only counting sites with the given
+	/// tag
 	///
 	/// # Errors
 	/// Returns [`RingError::SiteNotApproved`] if the current site is not part of the webring
 	/// Returns [`RingError::RowNotFound`] if the current site is last in the webring
 	/// Otherwise, [`RingError::UnrecoverableDatabaseError`]
 	#[instrument]
-	pub async fn get_next(&self, current_url: &str) -> Result<String, RingError> {
+	pub async fn get_next(&self, current_url: &str, tag: Option<&str>) -> Result<String, RingError> {
 		let id = self.get_approved_site_id(current_url).await?;
-		match sqlx::query!(
-            "SELECT root_url FROM approved_sites WHERE site_id > ? ORDER BY site_id ASC LIMIT 1",
-            id
-        )
-        .fetch_one(&self.database)
-        .await
-        {
-            Ok(record) => Ok(record.root_url),
-            Err(sqlx::Error::RowNotFound) => Err(RingError::RowNotFound("SELECT root_url FROM verified_sites WHERE site_id > ? ORDER BY site_id ASC LIMIT 1".to_owned())),
-            Err(e) => Err(RingError::UnrecoverableDatabaseError(e)),
-        }
+		match self.topology().await?.next(id, tag) {
+			Some(root_url) => Ok(root_url.to_owned()),
+			None => Err(RingError::RowNotFound(format!(
+				"Approved site after site with id {id}"
+			))),
+		}
 	}
 
-	/// Gets the webring site before the current one
+	/// Gets the webring site before the current one, optionally only counting sites with the given
+	/// tag
 	///
 	/// # Errors
 	/// Returns [`RingError::SiteNotApproved`] if the current site is not part of the webring
-	/// Returns [`RingError::RowNotFound`] if the current site is last in the webring
+	/// Returns [`RingError::RowNotFound`] if the current site is first in the webring
 	/// Otherwise, [`RingError::UnrecoverableDatabaseError`]
 	#[instrument]
-	pub async fn get_prev(&self, current_url: &str) -> Result<String, RingError> {
+	pub async fn get_prev(&self, current_url: &str, tag: Option<&str>) -> Result<String, RingError> {
 		let id = self.get_approved_site_id(current_url).await?;
-		match sqlx::query!(
-            "SELECT root_url FROM approved_sites WHERE site_id > ? ORDER BY site_id ASC LIMIT 1",
-            id
-        )
-        .fetch_one(&self.database)
-        .await
-        {
-            Ok(record) => Ok(record.root_url),
-            Err(sqlx::Error::RowNotFound) => Err(RingError::RowNotFound("SELECT root_url FROM verified_sites WHERE site_id < ? ORDER BY site_id ASC LIMIT 1".to_owned())),
-            Err(e) => {
-                error!("There was an unrecoverable database error in get_prev: {}", e);
-                Err(RingError::UnrecoverableDatabaseError(e))
-            }
-        }
+		match self.topology().await?.prev(id, tag) {
+			Some(root_url) => Ok(root_url.to_owned()),
+			None => Err(RingError::RowNotFound(format!(
+				"Approved site before site with id {id}"
+			))),
+		}
 	}
 
 	/// Gets the id of an approved site with the given url
@@ -288,78 +434,68 @@ impl RingState {
 	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
 	#[instrument]
 	async fn get_approved_site_id(&self, root_url: &str) -> Result<i64, RingError> {
-		match sqlx::query!(
-			"SELECT site_id FROM approved_sites WHERE root_url=?",
-			root_url
-		)
-		.fetch_one(&self.database)
-		.await
-		{
-			Ok(record) => Ok(record
-				.site_id
-				.ok_or(RingError::SiteNotApproved(root_url.to_owned()))?),
-			Err(sqlx::Error::RowNotFound) => {
+		if let Some(site_id) = self.topology().await?.site_id(root_url) {
+			return Ok(site_id);
+		}
+		// Sites that have moved can still be navigated from their old url for a while
+		match self.store.get_approved_site_id(self.ring.id, root_url).await {
+			Err(RingError::SiteNotApproved(url)) => {
 				info!("The unapproved site {root_url} tried to be a part of the webring");
-				return Err(RingError::SiteNotApproved(root_url.to_owned()));
-			}
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in get_verified_id: {}",
-					e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
+				Err(RingError::SiteNotApproved(url))
 			}
+			result => result,
+		}
+	}
+
+	/// Gets the order of the ring this state is scoped to, loading it if there isn't an up to date
+	/// copy in memory
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn topology(&self) -> Result<Arc<Topology>, RingError> {
+		if let Some(topology) = self.topology.get(self.ring.id) {
+			return Ok(topology);
 		}
+		let generation = self.topology.generation();
+		let approved = self.store.get_list_approved(self.ring.id, None).await?;
+		debug!("Loaded the order of ring {}", self.ring.slug);
+		Ok(self
+			.topology
+			.insert(self.ring.id, generation, Topology::new(approved)))
 	}
 
-	/// Gets a random site from the webring
+	/// Gets a random site from the webring, optionally only from sites with the given tag
 	///
 	/// # Errors
 	/// Returns [`RingError::RowNotFound`] if there are no approved sites
 	/// Otherwise, [`RingError::UnrecoverableDatabaseError`]
 	#[instrument]
-	pub async fn get_random_site(&self) -> Result<String, RingError> {
-		match sqlx::query!("SELECT root_url FROM approved_sites ORDER BY random() LIMIT 1")
-			.fetch_one(&self.database)
-			.await
-		{
-			Ok(record) => Ok(record.root_url),
-			Err(sqlx::Error::RowNotFound) => Err(RingError::RowNotFound(
-				"SELECT root_url FROM verified_sites ORDER BY random() LIMIT 1".to_owned(),
-			)),
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in get_random_site: {}",
-					e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
-			}
+	pub async fn get_random_site(&self, tag: Option<&str>) -> Result<String, RingError> {
+		match self.topology().await?.random(tag) {
+			Some(root_url) => Ok(root_url.to_owned()),
+			None => Err(RingError::RowNotFound("Any approved site".to_owned())),
 		}
 	}
 
-	/// Gets a list of all approved webring sites
+	/// Gets a list of all approved webring sites, optionally only those with the given tag, in the
+	/// order they are in the ring
 	///
 	/// # Errors
 	/// Returns [`RingError::RowNotFound`] if there are no verified sites
 	/// Otherwise, [`RingError::UnrecoverableDatabaseError`]
 	#[instrument]
-	pub async fn get_list_approved(&self) -> Result<Vec<ApprovedSite>, RingError> {
-		match sqlx::query_as("SELECT * FROM approved_sites ORDER BY random()")
-			.fetch_all(&self.database)
-			.await
-		{
-			Ok(sites) => Ok(sites),
-			Err(sqlx::Error::RowNotFound) => Err(RingError::RowNotFound(
-				"SELECT root_url FROM verified_sites ORDER BY random()".to_owned(),
-			)),
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in get_list_approved: {}",
-					e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
-			}
-		}
+	pub async fn get_list_approved(&self, tag: Option<&str>) -> Result<Vec<ApprovedSite>, RingError> {
+		self.store.get_list_approved(self.ring.id, tag).await
+	}
+
+	/// Gets a single approved site
+	///
+	/// # Errors
+	/// [`RingError::SiteNotApproved`] if there is no approved site with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_approved_site(&self, site_id: i64) -> Result<ApprovedSite, RingError> {
+		self.store.get_approved_site(self.ring.id, site_id).await
 	}
 
 	/// Gets all the denied sites
@@ -369,22 +505,7 @@ impl RingState {
 	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
 	#[instrument]
 	pub async fn get_list_denied(&self) -> Result<Vec<DeniedSite>, RingError> {
-		match sqlx::query_as("SELECT * FROM denied_sites")
-			.fetch_all(&self.database)
-			.await
-		{
-			Ok(sites) => Ok(sites),
-			Err(sqlx::Error::RowNotFound) => Err(RingError::RowNotFound(
-				"SELECT * FROM denied_sites".to_owned(),
-			)),
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in get_list_denied: {}",
-					e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
-			}
-		}
+		self.store.get_list_denied(self.ring.id).await
 	}
 
 	/// Gets a list of all unapproved webring sites
@@ -394,68 +515,41 @@ impl RingState {
 	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
 	#[instrument]
 	pub async fn get_list_unapproved(&self) -> Result<Vec<UnapprovedSite>, RingError> {
-		match sqlx::query_as("SELECT * FROM unapproved_sites ORDER BY id")
-			.fetch_all(&self.database)
-			.await
-		{
-			Ok(sites) => Ok(sites),
-			Err(sqlx::Error::RowNotFound) => Err(RingError::RowNotFound(
-				"SELECT * FROM unapproved_sites ORDER BY id".to_owned(),
-			)),
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in get_list_unapproved: {}",
-					e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
-			}
-		}
+		self.store.get_list_unapproved(self.ring.id).await
 	}
 
-	/// Gets a list of all unapproved webring sites
+	/// Adds an admin, who can manage every ring if `ring_id` is `None`, or only the ring with that
+	/// id otherwise
 	///
 	/// # Errors
 	/// [`RingError::UniqueRowAlreadyPresent`] if the new admins email or username are already in
 	/// use
 	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
-	#[instrument]
+	#[instrument(skip(email, password_plaintext))]
 	pub async fn add_admin(
 		&self,
 		username: String,
 		email: String,
 		password_plaintext: String,
+		ring_id: Option<i64>,
 	) -> Result<(), RingError> {
 		debug!("Add admin function running");
 		let password_hashed = auth::hash_password(password_plaintext).await?;
-		match sqlx::query!(
-			"INSERT INTO admins (username, email, password_phc) values (?, ?, ?)",
-			username,
-			email,
-			password_hashed
-		)
-		.execute(&self.database)
-		.await
+		match self
+			.store
+			.add_admin(&username, &email, &password_hashed, ring_id)
+			.await
 		{
-			Ok(_query_result) => {
-				info!("Added admin to database: {} {}", username, email);
+			Ok(()) => {
+				info!("Added admin to database: {}", username);
+				self.enqueue_event(RingEvent::AdminAdded { username }).await;
 				Ok(())
 			}
-			Err(sqlx::Error::Database(ref e)) if e.code().as_deref() == Some("2067") => {
-				info!(
-					"Admin username {} or email {} already taken",
-					username, email
-				);
-				Err(RingError::UniqueRowAlreadyPresent(format!(
-					"{username} {email}"
-				)))
-			}
-			Err(e) => {
-				error!(
-					"There was an unrecoverable database error in add_admin: {}",
-					e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
+			Err(RingError::UniqueRowAlreadyPresent(values)) => {
+				info!("Admin username {} or their email already taken", username);
+				Err(RingError::UniqueRowAlreadyPresent(values))
 			}
+			Err(e) => Err(e),
 		}
 	}
 
@@ -463,34 +557,21 @@ impl RingState {
 	///
 	/// # Errors
 	/// [`RingError::RowNotFound`] if there is no admin with the given id
+	/// [`RingError::RowInUse`] if the admin has approved or denied sites
 	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
 	#[instrument]
 	pub async fn delete_admin(&self, admin_id: i64) -> Result<(), RingError> {
-		match sqlx::query("DELETE FROM admins WHERE id = ?")
-			.bind(admin_id)
-			.execute(&self.database)
-			.await
-		{
-			Ok(query) if query.rows_affected() == 0 => {
-				error!("No admin found to delete. {:?}", query);
-				Err(RingError::RowNotFound(format!(
-					"Admin with admin id {admin_id:?}"
-				)))
-			}
-			Ok(query) => {
-				info!(
-					"Successfully deleted admin account with id {:?}: {:?}",
-					admin_id, query
-				);
+		match self.store.delete_admin(admin_id).await {
+			Ok(()) => {
+				info!("Successfully deleted admin account with id {:?}", admin_id);
+				self.enqueue_event(RingEvent::AdminDeleted { admin_id }).await;
 				Ok(())
 			}
-			Err(e) => {
-				error!(
-					"There was a database error when trying to delete admin with id {}: {}",
-					admin_id, e
-				);
-				Err(RingError::UnrecoverableDatabaseError(e))
+			Err(RingError::RowNotFound(admin)) => {
+				error!("No admin found to delete with id {:?}", admin_id);
+				Err(RingError::RowNotFound(admin))
 			}
+			Err(e) => Err(e),
 		}
 	}
 }
@@ -0,0 +1,56 @@
+//! This module handles checking that someone owns the site they're adding to the webring
+//!
+//! Owners prove that they control a site by hosting [`auth_hash`] of the site's url as plain text
+//! at `<url>/webringer/auth`.
+
+use axum::http::StatusCode;
+use sha2::{Digest, Sha256};
+use tracing::{debug, instrument};
+
+use super::RingError;
+
+/// The string a site must host at `/webringer/auth` to prove ownership, the hex encoded SHA-256
+/// of its url
+#[must_use]
+pub fn auth_hash(url: &str) -> String {
+	let mut hasher = Sha256::new();
+	hasher.update(url);
+	hex::encode(hasher.finalize())
+}
+
+/// Checks that the site at `url` is hosting its [`auth_hash`] at `/webringer/auth`
+///
+/// # Errors
+/// [`RingError::OwnershipNotVerified`] describing what went wrong if the hash couldn't be fetched
+/// or didn't match
+/// [`RingError::TaskJoin`] if the task fetching the hash panics
+#[instrument]
+pub async fn verify_ownership(url: &str) -> Result<(), RingError> {
+	let expected = auth_hash(url);
+	let auth_url = format!("{url}/webringer/auth");
+	let response = tokio::task::spawn_blocking(move || match ureq::get(&auth_url).call() {
+		Ok(response) if response.status() == StatusCode::NOT_FOUND => Err(format!(
+			"Got a 404 error when trying to get {auth_url}"
+		)),
+		Ok(response) => match response.into_body().read_to_string() {
+			Ok(text) => Ok(text.trim().to_owned()),
+			Err(e) => Err(format!(
+				"Could not read the verification string from your site: {e}"
+			)),
+		},
+		Err(e) => Err(format!(
+			"There was an error when getting the verification string from your site: {e}"
+		)),
+	})
+	.await?
+	.map_err(RingError::OwnershipNotVerified)?;
+
+	if response == expected {
+		Ok(())
+	} else {
+		debug!("Response: {} Url hash: {}", response, expected);
+		Err(RingError::OwnershipNotVerified(format!(
+			"Url hash found but did not match: {response} != {expected}"
+		)))
+	}
+}
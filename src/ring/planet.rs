@@ -0,0 +1,704 @@
+//! This module handles the planet, a combined feed of posts from members' own feeds
+//!
+//! Members declare a feed url in their profile, which can be RSS, Atom or JSON Feed. A background
+//! worker polls every feed, see [`RingState::run_planet_worker`], using conditional requests so
+//! feeds that haven't changed aren't downloaded again. Admins can exclude a site's feed from the
+//! planet without touching the site's profile.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{debug, error, info, instrument, warn};
+use url::Url;
+
+use super::{
+	RingError, RingState,
+	feed::rfc3339,
+	sql::{self, Flag},
+};
+
+/// How many of each feed's newest entries are kept
+const MAX_ENTRIES_PER_FEED: u32 = 20;
+/// The biggest feed that will be downloaded, in bytes
+const MAX_FEED_SIZE: u64 = 2 * 1024 * 1024;
+const MAX_SUMMARY_LENGTH: usize = 300;
+
+/// A post from a member's feed
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PlanetEntry {
+	pub site_id: i64,
+	pub root_url: String,
+	/// The title of the site the post is from
+	pub site_title: Option<String>,
+	pub title: Option<String>,
+	pub url: String,
+	/// The start of the post, as plain text
+	pub summary: Option<String>,
+	/// When the post was published, as `YYYY-MM-DD HH:MM:SS` in UTC
+	pub published: String,
+}
+
+impl PlanetEntry {
+	/// The name of the site the post is from
+	#[must_use]
+	pub fn site_name(&self) -> &str {
+		self.site_title.as_deref().unwrap_or(&self.root_url)
+	}
+
+	/// When the post was published, formatted for Atom feeds
+	#[must_use]
+	pub fn updated(&self) -> String {
+		rfc3339(&self.published)
+	}
+}
+
+/// A member's feed, as shown to admins
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PlanetFeed {
+	pub site_id: i64,
+	pub root_url: String,
+	pub feed_url: String,
+	/// Whether an admin has left the feed out of the planet
+	#[sqlx(try_from = "Flag")]
+	pub excluded: bool,
+	pub last_fetched_at: Option<String>,
+	/// Why the last fetch of the feed failed, if it did
+	pub last_error: Option<String>,
+	pub entry_count: i64,
+}
+
+/// A post read from a feed, before it's stored
+#[derive(Debug)]
+struct ParsedEntry {
+	guid: String,
+	title: Option<String>,
+	url: String,
+	summary: Option<String>,
+	/// As `YYYY-MM-DD HH:MM:SS` in UTC, if the feed gave a date that could be read
+	published: Option<String>,
+}
+
+/// A member's feed as last stored, before fetching it again
+#[derive(FromRow)]
+struct StoredFeed {
+	site_id: i64,
+	feed_url: String,
+	/// The feed url the stored validators came from
+	fetched_feed_url: Option<String>,
+	etag: Option<String>,
+	last_modified: Option<String>,
+}
+
+/// The result of fetching a feed
+enum FetchOutcome {
+	/// The feed hasn't changed since it was last fetched
+	NotModified,
+	Fetched {
+		body: String,
+		etag: Option<String>,
+		last_modified: Option<String>,
+	},
+}
+
+fn fetch_feed(
+	url: &str,
+	etag: Option<&str>,
+	last_modified: Option<&str>,
+) -> Result<FetchOutcome, String> {
+	let agent: ureq::Agent = ureq::Agent::config_builder()
+		.timeout_global(Some(Duration::from_secs(10)))
+		.build()
+		.into();
+	let mut request = agent.get(url).header(
+		"Accept",
+		"application/atom+xml, application/rss+xml, application/feed+json, application/xml;q=0.9, */*;q=0.8",
+	);
+	if let Some(etag) = etag {
+		request = request.header("If-None-Match", etag);
+	}
+	if let Some(last_modified) = last_modified {
+		request = request.header("If-Modified-Since", last_modified);
+	}
+	let response = request
+		.call()
+		.map_err(|e| format!("Could not fetch the feed: {e}"))?;
+	if response.status() == 304 {
+		return Ok(FetchOutcome::NotModified);
+	}
+	let header = |name: &str| {
+		response
+			.headers()
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_owned)
+	};
+	let (etag, last_modified) = (header("ETag"), header("Last-Modified"));
+	let body = response
+		.into_body()
+		.with_config()
+		.limit(MAX_FEED_SIZE)
+		.read_to_string()
+		.map_err(|e| format!("Could not read the feed: {e}"))?;
+	Ok(FetchOutcome::Fetched {
+		body,
+		etag,
+		last_modified,
+	})
+}
+
+/// Reads the entries of an RSS, Atom or JSON feed fetched from `feed_url`, newest first
+fn parse_feed(feed_url: &str, body: &str) -> Result<Vec<ParsedEntry>, String> {
+	let base = Url::parse(feed_url).map_err(|e| format!("The feed url isn't valid: {e}"))?;
+	let mut entries = if body.trim_start().starts_with('{') {
+		parse_json_feed(body)?
+	} else {
+		parse_xml_feed(body)?
+	};
+	// Links can be relative to the feed, and are shown to visitors so mustn't be e.g. javascript:
+	entries.retain_mut(|entry| match base.join(&entry.url) {
+		Ok(url) if matches!(url.scheme(), "http" | "https") => {
+			entry.url = url.into();
+			true
+		}
+		_ => false,
+	});
+	entries.sort_by(|a, b| b.published.cmp(&a.published));
+	entries.truncate(MAX_ENTRIES_PER_FEED as usize);
+	Ok(entries)
+}
+
+fn parse_xml_feed(body: &str) -> Result<Vec<ParsedEntry>, String> {
+	let document = roxmltree::Document::parse(body)
+		.map_err(|e| format!("The feed isn't valid XML: {e}"))?;
+	let root = document.root_element();
+	match root.tag_name().name() {
+		// RSS 2.0 puts items in the channel, RSS 1.0 puts them next to it
+		"rss" | "RDF" => Ok(root
+			.descendants()
+			.filter(|node| node.tag_name().name() == "item")
+			.filter_map(|item| {
+				let url = child_text(item, "link");
+				let guid = child_text(item, "guid").or_else(|| url.clone())?;
+				Some(ParsedEntry {
+					url: url.unwrap_or_else(|| guid.clone()),
+					guid,
+					title: child_text(item, "title"),
+					summary: child_text(item, "description").map(|summary| summarise(&summary)),
+					published: child_text(item, "pubDate")
+						.or_else(|| child_text(item, "date"))
+						.and_then(|date| parse_date(&date)),
+				})
+			})
+			.collect()),
+		"feed" => Ok(root
+			.children()
+			.filter(|node| node.tag_name().name() == "entry")
+			.filter_map(|entry| {
+				let url = entry
+					.children()
+					.filter(|node| node.tag_name().name() == "link")
+					.find(|link| link.attribute("rel").is_none_or(|rel| rel == "alternate"))
+					.and_then(|link| link.attribute("href"))
+					.map(str::to_owned);
+				let guid = child_text(entry, "id").or_else(|| url.clone())?;
+				Some(ParsedEntry {
+					url: url.unwrap_or_else(|| guid.clone()),
+					guid,
+					title: child_text(entry, "title"),
+					summary: child_text(entry, "summary")
+						.or_else(|| child_text(entry, "content"))
+						.map(|summary| summarise(&summary)),
+					published: child_text(entry, "published")
+						.or_else(|| child_text(entry, "updated"))
+						.and_then(|date| parse_date(&date)),
+				})
+			})
+			.collect()),
+		other => Err(format!("The feed has an unknown root element <{other}>")),
+	}
+}
+
+/// The text of the first child element with the given local name, ignoring its namespace
+fn child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+	let child = node
+		.children()
+		.find(|child| child.is_element() && child.tag_name().name() == name)?;
+	let text: String = child
+		.descendants()
+		.filter(roxmltree::Node::is_text)
+		.filter_map(|descendant| descendant.text())
+		.collect();
+	let text = text.trim();
+	(!text.is_empty()).then(|| text.to_owned())
+}
+
+#[derive(Deserialize)]
+struct JsonFeed {
+	items: Vec<JsonFeedItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedItem {
+	#[serde(default)]
+	id: serde_json::Value,
+	url: Option<String>,
+	title: Option<String>,
+	summary: Option<String>,
+	content_text: Option<String>,
+	content_html: Option<String>,
+	date_published: Option<String>,
+	date_modified: Option<String>,
+}
+
+fn parse_json_feed(body: &str) -> Result<Vec<ParsedEntry>, String> {
+	let feed: JsonFeed =
+		serde_json::from_str(body).map_err(|e| format!("The feed isn't a valid JSON Feed: {e}"))?;
+	Ok(feed
+		.items
+		.into_iter()
+		.filter_map(|item| {
+			// Ids should be strings, but some feeds use numbers
+			let guid = match item.id {
+				serde_json::Value::String(id) => id,
+				serde_json::Value::Null => item.url.clone()?,
+				id => id.to_string(),
+			};
+			Some(ParsedEntry {
+				url: item.url.unwrap_or_else(|| guid.clone()),
+				guid,
+				title: item.title,
+				summary: item
+					.summary
+					.or(item.content_text)
+					.or(item.content_html)
+					.map(|summary| summarise(&summary)),
+				published: item
+					.date_published
+					.or(item.date_modified)
+					.and_then(|date| parse_date(&date)),
+			})
+		})
+		.collect())
+}
+
+/// Reads an RFC 3339 or RFC 2822 date, the formats Atom, JSON Feed and RSS use
+fn parse_date(date: &str) -> Option<String> {
+	let date = date.trim();
+	DateTime::parse_from_rfc3339(date)
+		.or_else(|_| DateTime::parse_from_rfc2822(date))
+		.ok()
+		.map(|date| sql::timestamp(date.with_timezone(&Utc)))
+}
+
+/// Turns the html of a post into a short plain text summary
+fn summarise(html: &str) -> String {
+	let mut text = String::with_capacity(html.len());
+	let mut in_tag = false;
+	for c in html.chars() {
+		match c {
+			'<' => in_tag = true,
+			'>' if in_tag => {
+				in_tag = false;
+				text.push(' ');
+			}
+			c if !in_tag => text.push(c),
+			_ => {}
+		}
+	}
+	let text = text
+		.replace("&nbsp;", " ")
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&#39;", "'")
+		.replace("&amp;", "&");
+	let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+	if text.chars().count() > MAX_SUMMARY_LENGTH {
+		let mut summary: String = text.chars().take(MAX_SUMMARY_LENGTH).collect();
+		summary.push('…');
+		summary
+	} else {
+		text
+	}
+}
+
+impl RingState {
+	/// Gets the newest posts from the feeds of the ring's members, newest first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_planet_entries(&self, limit: i64) -> Result<Vec<PlanetEntry>, RingError> {
+		// Only entries from the feed the site currently declares are shown
+		match sqlx::query_as(
+			"SELECT pe.site_id, a.root_url, a.title AS site_title, pe.title, pe.url, pe.summary,
+			pe.published FROM planet_entries AS pe
+			INNER JOIN approved_sites AS a ON pe.site_id = a.site_id
+			INNER JOIN planet_feeds AS pf ON pe.site_id = pf.site_id
+			WHERE a.ring_id = $1 AND pf.excluded = 0 AND pf.feed_url = a.feed_url
+			ORDER BY pe.published DESC, pe.id DESC LIMIT $2",
+		)
+		.bind(self.ring.id)
+		.bind(limit)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(entries) => Ok(entries),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_planet_entries: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets every feed declared by the ring's members, and how fetching them is going
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_list_planet_feeds(&self) -> Result<Vec<PlanetFeed>, RingError> {
+		match sqlx::query_as(
+			"SELECT a.site_id, a.root_url, a.feed_url, COALESCE(pf.excluded, 0) AS excluded,
+			pf.last_fetched_at, pf.last_error,
+			(SELECT COUNT(*) FROM planet_entries AS pe WHERE pe.site_id = a.site_id) AS entry_count
+			FROM approved_sites AS a LEFT JOIN planet_feeds AS pf ON a.site_id = pf.site_id
+			WHERE a.ring_id = $1 AND a.feed_url IS NOT NULL ORDER BY a.root_url",
+		)
+		.bind(self.ring.id)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(feeds) => Ok(feeds),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_planet_feeds: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Leaves a site's feed out of the planet, or puts it back in
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if the ring has no approved site with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn set_planet_feed_excluded(
+		&self,
+		site_id: i64,
+		excluded: bool,
+	) -> Result<(), RingError> {
+		match sqlx::query(
+			"INSERT INTO planet_feeds (site_id, excluded)
+			SELECT site_id, $1 FROM approved_sites WHERE site_id = $2 AND ring_id = $3
+			ON CONFLICT (site_id) DO UPDATE SET excluded = excluded.excluded",
+		)
+		.bind(i64::from(excluded))
+		.bind(site_id)
+		.bind(self.ring.id)
+		.execute(&self.database)
+		.await
+		{
+			Ok(query_outcome) if query_outcome.rows_affected() == 0 => {
+				Err(RingError::RowNotFound(format!("Approved site with id {site_id}")))
+			}
+			Ok(_query_outcome) => {
+				info!(
+					"Site {} {} the planet",
+					site_id,
+					if excluded { "excluded from" } else { "included in" }
+				);
+				Ok(())
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in set_planet_feed_excluded: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Fetches the feed of every approved site on the instance that has one, returning how many
+	/// were fetched successfully
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn refresh_planet(&self) -> Result<usize, RingError> {
+		let feeds: Vec<StoredFeed> = sqlx::query_as(
+			"SELECT a.site_id, a.feed_url, pf.feed_url AS fetched_feed_url, pf.etag,
+			pf.last_modified
+			FROM approved_sites AS a LEFT JOIN planet_feeds AS pf ON a.site_id = pf.site_id
+			WHERE a.feed_url IS NOT NULL AND COALESCE(pf.excluded, 0) = 0",
+		)
+		.fetch_all(&self.database)
+		.await?;
+		let mut refreshed = 0;
+		for feed in feeds {
+			// Validators from a different feed url would be meaningless
+			let same_feed = feed.fetched_feed_url.as_deref() == Some(feed.feed_url.as_str());
+			let (etag, last_modified) = if same_feed {
+				(feed.etag, feed.last_modified)
+			} else {
+				(None, None)
+			};
+			let url = feed.feed_url.clone();
+			let outcome = tokio::task::spawn_blocking(move || -> Result<_, String> {
+				let outcome = fetch_feed(&url, etag.as_deref(), last_modified.as_deref())?;
+				match outcome {
+					FetchOutcome::NotModified => Ok((outcome, Vec::new())),
+					FetchOutcome::Fetched { ref body, .. } => {
+						let entries = parse_feed(&url, body)?;
+						Ok((outcome, entries))
+					}
+				}
+			})
+			.await?;
+			match outcome {
+				Ok((outcome, entries)) => {
+					// One feed failing to be stored shouldn't stop the rest being refreshed
+					match self
+						.store_planet_feed(
+							feed.site_id,
+							&feed.feed_url,
+							same_feed,
+							outcome,
+							entries,
+						)
+						.await
+					{
+						Ok(()) => refreshed += 1,
+						Err(e) => error!("Error when storing the feed {}: {e}", feed.feed_url),
+					}
+				}
+				Err(problem) => {
+					warn!("Could not refresh the feed {}: {}", feed.feed_url, problem);
+					if let Err(e) = sqlx::query(
+						"INSERT INTO planet_feeds (site_id, last_fetched_at, last_error)
+						VALUES ($1, $2, $3) ON CONFLICT (site_id) DO UPDATE
+						SET last_fetched_at = excluded.last_fetched_at, last_error = excluded.last_error",
+					)
+					.bind(feed.site_id)
+					.bind(sql::now())
+					.bind(problem)
+					.execute(&self.database)
+					.await
+					{
+						error!(
+							"Error when storing why the feed {} failed: {e}",
+							feed.feed_url
+						);
+					}
+				}
+			}
+		}
+		Ok(refreshed)
+	}
+
+	async fn store_planet_feed(
+		&self,
+		site_id: i64,
+		feed_url: &str,
+		same_feed: bool,
+		outcome: FetchOutcome,
+		entries: Vec<ParsedEntry>,
+	) -> Result<(), RingError> {
+		let max_entries = i64::from(MAX_ENTRIES_PER_FEED);
+		let mut tx = self.database.begin().await?;
+		let (etag, last_modified) = match outcome {
+			FetchOutcome::NotModified => {
+				sqlx::query(
+					"UPDATE planet_feeds SET last_fetched_at = $1, last_error = NULL
+					WHERE site_id = $2",
+				)
+				.bind(sql::now())
+				.bind(site_id)
+				.execute(&mut *tx)
+				.await?;
+				tx.commit().await?;
+				debug!("The feed {} hasn't changed", feed_url);
+				return Ok(());
+			}
+			FetchOutcome::Fetched {
+				etag,
+				last_modified,
+				..
+			} => (etag, last_modified),
+		};
+		if !same_feed {
+			sqlx::query("DELETE FROM planet_entries WHERE site_id = $1")
+				.bind(site_id)
+				.execute(&mut *tx)
+				.await?;
+		}
+		sqlx::query(
+			"INSERT INTO planet_feeds (site_id, feed_url, etag, last_modified, last_fetched_at)
+			VALUES ($1, $2, $3, $4, $5) ON CONFLICT (site_id) DO UPDATE
+			SET feed_url = excluded.feed_url, etag = excluded.etag,
+			last_modified = excluded.last_modified, last_fetched_at = excluded.last_fetched_at,
+			last_error = NULL",
+		)
+		.bind(site_id)
+		.bind(feed_url)
+		.bind(etag)
+		.bind(last_modified)
+		.bind(sql::now())
+		.execute(&mut *tx)
+		.await?;
+		let now = sql::now();
+		for entry in entries {
+			// Posts without a date are dated when they're first seen, and posts from the future
+			// are brought back to now so they can't stay at the top of the planet
+			let published = match entry.published {
+				Some(published) if published < now => published,
+				_ => now.clone(),
+			};
+			sqlx::query(
+				"INSERT INTO planet_entries (site_id, guid, title, url, summary, published)
+				VALUES ($1, $2, $3, $4, $5, $6)
+				ON CONFLICT (site_id, guid) DO UPDATE
+				SET title = excluded.title, url = excluded.url, summary = excluded.summary",
+			)
+			.bind(site_id)
+			.bind(entry.guid)
+			.bind(entry.title)
+			.bind(entry.url)
+			.bind(entry.summary)
+			.bind(published)
+			.execute(&mut *tx)
+			.await?;
+		}
+		sqlx::query(
+			"DELETE FROM planet_entries WHERE site_id = $1 AND id NOT IN (
+				SELECT id FROM planet_entries WHERE site_id = $1 ORDER BY published DESC LIMIT $2
+			)",
+		)
+		.bind(site_id)
+		.bind(max_entries)
+		.execute(&mut *tx)
+		.await?;
+		tx.commit().await?;
+		debug!("Stored the feed {}", feed_url);
+		Ok(())
+	}
+
+	/// Fetches every member's feed every `interval`, forever
+	pub async fn run_planet_worker(self, interval: Duration) {
+		let mut ticker = tokio::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match self.refresh_planet().await {
+				Ok(refreshed) => debug!("Refreshed {} planet feeds", refreshed),
+				Err(e) => error!("Error when refreshing the planet: {e}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::parse_feed;
+
+	const FEED_URL: &str = "https://blog.example/feed.xml";
+
+	#[test]
+	fn parses_atom() {
+		let entries = parse_feed(
+			FEED_URL,
+			r#"<?xml version="1.0" encoding="utf-8"?>
+			<feed xmlns="http://www.w3.org/2005/Atom">
+				<entry>
+					<id>tag:blog.example,2025:1</id>
+					<title>First</title>
+					<link rel="edit" href="/edit/1"/>
+					<link href="/posts/1"/>
+					<published>2025-05-09T12:21:47+01:00</published>
+					<summary type="html">&lt;p&gt;Hello &amp;amp; welcome&lt;/p&gt;</summary>
+				</entry>
+				<entry>
+					<id>tag:blog.example,2025:2</id>
+					<title>Second</title>
+					<link href="https://blog.example/posts/2"/>
+					<updated>2025-06-01T08:00:00Z</updated>
+				</entry>
+			</feed>"#,
+		)
+		.unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].title.as_deref(), Some("Second"));
+		let first = &entries[1];
+		assert_eq!(first.guid, "tag:blog.example,2025:1");
+		assert_eq!(first.url, "https://blog.example/posts/1");
+		assert_eq!(first.published.as_deref(), Some("2025-05-09 11:21:47"));
+		assert_eq!(first.summary.as_deref(), Some("Hello & welcome"));
+	}
+
+	#[test]
+	fn parses_rss() {
+		let entries = parse_feed(
+			FEED_URL,
+			r#"<?xml version="1.0"?>
+			<rss version="2.0">
+				<channel>
+					<title>Blog</title>
+					<item>
+						<title>Relative</title>
+						<link>posts/relative</link>
+						<pubDate>Fri, 09 May 2025 12:21:47 GMT</pubDate>
+					</item>
+					<item>
+						<title>Script</title>
+						<link>javascript:alert(1)</link>
+					</item>
+					<item>
+						<guid>urn:uuid:1b4e28ba-2fa1-11d2-883f-0016d3cca427</guid>
+						<title>No link</title>
+					</item>
+				</channel>
+			</rss>"#,
+		)
+		.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].url, "https://blog.example/posts/relative");
+		assert_eq!(entries[0].guid, "posts/relative");
+		assert_eq!(entries[0].published.as_deref(), Some("2025-05-09 12:21:47"));
+	}
+
+	#[test]
+	fn parses_json_feed() {
+		let entries = parse_feed(
+			"https://blog.example/feed.json",
+			r#"{
+				"version": "https://jsonfeed.org/version/1.1",
+				"items": [
+					{"id": 1, "url": "/posts/1", "content_text": "Some text"},
+					{"id": "2", "url": "data:text/html,hi", "title": "Data"},
+					{"url": "https://elsewhere.example/post", "date_published": "not a date"}
+				]
+			}"#,
+		)
+		.unwrap();
+		assert_eq!(entries.len(), 2);
+		let numbered = entries.iter().find(|entry| entry.guid == "1").unwrap();
+		assert_eq!(numbered.url, "https://blog.example/posts/1");
+		assert_eq!(numbered.summary.as_deref(), Some("Some text"));
+		let undated = entries.iter().find(|entry| entry.guid != "1").unwrap();
+		assert_eq!(undated.guid, "https://elsewhere.example/post");
+		assert_eq!(undated.published, None);
+	}
+
+	#[test]
+	fn rejects_unknown_feeds() {
+		assert!(parse_feed(FEED_URL, "<html></html>").is_err());
+		assert!(parse_feed(FEED_URL, "{\"items\": 1}").is_err());
+	}
+}
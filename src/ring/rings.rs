@@ -0,0 +1,307 @@
+//! This module handles hosting several independent webrings from one instance
+//!
+//! Every site belongs to exactly one ring, though the same url can be a site in several rings, each
+//! with its own approval. A [`RingState`] is scoped to a single ring, see
+//! [`RingState::for_ring`], and everything it does only sees that ring's sites. The ring with id
+//! [`DEFAULT_RING_ID`] is served from the root of the instance, every other ring is served from
+//! `/r/<slug>`.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{info, instrument};
+
+use super::{RingError, RingState};
+use crate::theme::Theme;
+
+/// The ring that existed before an instance could host several, served from the root
+pub const DEFAULT_RING_ID: i64 = 1;
+const MAX_SLUG_LENGTH: usize = 32;
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_DESCRIPTION_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Ring {
+	pub id: i64,
+	/// The short name used in the ring's urls
+	pub slug: String,
+	pub name: String,
+	pub description: Option<String>,
+	/// The path the whole instance is served under, empty when it's served from the root
+	#[sqlx(skip)]
+	#[serde(skip)]
+	pub(crate) path_prefix: String,
+	/// How the whole instance looks
+	#[sqlx(skip)]
+	#[serde(skip)]
+	pub(crate) theme: Arc<Theme>,
+}
+
+impl Default for Ring {
+	fn default() -> Self {
+		Self {
+			id: DEFAULT_RING_ID,
+			slug: "default".to_owned(),
+			name: "WebRinger".to_owned(),
+			description: None,
+			path_prefix: String::new(),
+			theme: Arc::default(),
+		}
+	}
+}
+
+impl Ring {
+	#[must_use]
+	pub const fn is_default(&self) -> bool {
+		self.id == DEFAULT_RING_ID
+	}
+
+	/// The path every page of the ring is under, empty for the default ring
+	#[must_use]
+	pub fn base_path(&self) -> String {
+		if self.is_default() {
+			self.path_prefix.clone()
+		} else {
+			format!("{}/r/{}", self.path_prefix, self.slug)
+		}
+	}
+
+	/// The path of a page of the ring, e.g. `/list`
+	#[must_use]
+	pub fn path(&self, path: &str) -> String {
+		format!("{}{path}", self.base_path())
+	}
+
+	/// The path of the ring's home page
+	#[must_use]
+	pub fn home_path(&self) -> String {
+		let base_path = self.base_path();
+		if base_path.is_empty() {
+			"/".to_owned()
+		} else {
+			base_path
+		}
+	}
+
+	/// The path of a page that isn't part of any one ring, e.g. `/login`
+	#[must_use]
+	pub fn instance_path(&self, path: &str) -> String {
+		format!("{}{path}", self.path_prefix)
+	}
+
+	/// The name of the whole instance, which every ring is part of
+	#[must_use]
+	pub fn instance_name(&self) -> &str {
+		self.theme.name()
+	}
+
+	/// The url of the logo shown on every page
+	#[must_use]
+	pub fn logo_path(&self) -> String {
+		match self.theme.logo() {
+			Some(logo) if logo.starts_with('/') => self.instance_path(logo),
+			Some(logo) => logo.to_owned(),
+			None => self.instance_path("/static/logo.svg"),
+		}
+	}
+
+	/// The text shown in every page's footer, if any is set
+	#[must_use]
+	pub fn footer(&self) -> Option<&str> {
+		self.theme.footer()
+	}
+}
+
+/// Checks a ring's slug, name and description, trimming them and treating a blank description as
+/// not given
+fn validate_ring(
+	slug: &str,
+	name: &str,
+	description: Option<&str>,
+) -> Result<(String, String, Option<String>), RingError> {
+	let slug = slug.trim().to_lowercase();
+	let name = name.trim().to_owned();
+	let description = description
+		.map(str::trim)
+		.filter(|description| !description.is_empty())
+		.map(str::to_owned);
+	if slug.is_empty() || slug.chars().count() > MAX_SLUG_LENGTH {
+		return Err(RingError::InvalidInput(format!(
+			"The ring's short name must be between 1 and {MAX_SLUG_LENGTH} characters"
+		)));
+	}
+	if !slug
+		.chars()
+		.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+	{
+		return Err(RingError::InvalidInput(
+			"The ring's short name can only contain letters, numbers and dashes".to_owned(),
+		));
+	}
+	if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+		return Err(RingError::InvalidInput(format!(
+			"The ring's name must be between 1 and {MAX_NAME_LENGTH} characters"
+		)));
+	}
+	if description
+		.as_ref()
+		.is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_LENGTH)
+	{
+		return Err(RingError::InvalidInput(format!(
+			"The ring's description must be at most {MAX_DESCRIPTION_LENGTH} characters"
+		)));
+	}
+	Ok((slug, name, description))
+}
+
+impl RingState {
+	/// The ring this state is scoped to
+	#[must_use]
+	pub fn ring(&self) -> &Ring {
+		&self.ring
+	}
+
+	/// The full url of a page of the ring this state is scoped to, e.g. `/list`
+	#[must_use]
+	pub fn url(&self, path: &str) -> String {
+		format!("{}{}", self.mailer.base_url(), self.ring.path(path))
+	}
+
+	/// The full url of the home page of the ring this state is scoped to
+	#[must_use]
+	pub fn home_url(&self) -> String {
+		format!("{}{}", self.mailer.base_url(), self.ring.home_path())
+	}
+
+	/// Sets the path the whole instance is served under, like `/webring`, for the links it makes
+	#[must_use]
+	pub fn with_path_prefix(self, path_prefix: &str) -> Self {
+		let ring = Ring {
+			path_prefix: path_prefix.trim_end_matches('/').to_owned(),
+			..(*self.ring).clone()
+		};
+		Self {
+			ring: Arc::new(ring),
+			..self
+		}
+	}
+
+	/// Sets how the whole instance looks
+	#[must_use]
+	pub fn with_theme(self, theme: Theme) -> Self {
+		let ring = Ring {
+			theme: Arc::new(theme),
+			..(*self.ring).clone()
+		};
+		Self {
+			ring: Arc::new(ring),
+			..self
+		}
+	}
+
+	/// A copy of this state scoped to `ring`
+	fn scoped_to(&self, ring: Ring) -> Self {
+		Self {
+			ring: Arc::new(Ring {
+				path_prefix: self.ring.path_prefix.clone(),
+				theme: Arc::clone(&self.ring.theme),
+				..ring
+			}),
+			..self.clone()
+		}
+	}
+
+	/// Gets a copy of this state scoped to the ring with the given slug
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no ring with that slug
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	pub async fn for_ring(&self, slug: &str) -> Result<Self, RingError> {
+		match self.store.get_ring_by_slug(slug).await? {
+			Some(ring) => Ok(self.scoped_to(ring)),
+			None => Err(RingError::RowNotFound(format!("Ring {slug}"))),
+		}
+	}
+
+	/// Gets a copy of this state scoped to the default ring
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if the default ring has been removed from the store
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	pub async fn for_default_ring(&self) -> Result<Self, RingError> {
+		match self.store.get_ring(DEFAULT_RING_ID).await? {
+			Some(ring) => Ok(self.scoped_to(ring)),
+			None => Err(RingError::RowNotFound(format!("Ring {DEFAULT_RING_ID}"))),
+		}
+	}
+
+	/// Gets every ring hosted by this instance, the default ring first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_list_rings(&self) -> Result<Vec<Ring>, RingError> {
+		Ok(self
+			.store
+			.get_list_rings()
+			.await?
+			.into_iter()
+			.map(|ring| Ring {
+				path_prefix: self.ring.path_prefix.clone(),
+				theme: Arc::clone(&self.ring.theme),
+				..ring
+			})
+			.collect())
+	}
+
+	/// Creates a new, empty ring
+	///
+	/// # Errors
+	/// [`RingError::InvalidInput`] if the slug, name or description are invalid
+	/// [`RingError::UniqueRowAlreadyPresent`] if a ring with that slug already exists
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn add_ring(
+		&self,
+		slug: &str,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError> {
+		let (slug, name, description) = validate_ring(slug, name, description)?;
+		match self
+			.store
+			.add_ring(&slug, &name, description.as_deref())
+			.await
+		{
+			Ok(()) => {
+				info!("Added ring {}", slug);
+				Ok(())
+			}
+			Err(RingError::UniqueRowAlreadyPresent(_)) => {
+				info!("Someone tried to add ring {} but it already exists", slug);
+				Err(RingError::UniqueRowAlreadyPresent(slug))
+			}
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Changes the name and description of the ring this state is scoped to
+	///
+	/// # Errors
+	/// [`RingError::InvalidInput`] if the name or description are invalid
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn update_ring(
+		&self,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError> {
+		let (_slug, name, description) = validate_ring(&self.ring.slug, name, description)?;
+		self.store
+			.update_ring(self.ring.id, &name, description.as_deref())
+			.await?;
+		info!("Updated ring {}", self.ring.slug);
+		Ok(())
+	}
+}
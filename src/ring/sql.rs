@@ -0,0 +1,121 @@
+//! What the ring needs to run the same queries on every database it supports
+//!
+//! Timestamps are stored as text in the format sqlite's `datetime()` and `date()` produce, and are
+//! worked out here rather than in the queries, as each database has its own date functions.
+
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{
+	Any, Decode, Type,
+	any::{AnyTypeInfo, AnyValueRef},
+	error::BoxDynError,
+};
+
+/// The format of stored timestamps, e.g. `2025-05-09 12:21:47`
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The format of stored dates, e.g. `2025-05-09`
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The format of timestamps in HTTP headers and RSS feeds, e.g. `Fri, 09 May 2025 12:21:47 GMT`
+pub const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A time, as stored in the database
+#[must_use]
+pub fn timestamp(time: DateTime<Utc>) -> String {
+	time.format(DATETIME_FORMAT).to_string()
+}
+
+/// Reads a timestamp stored in the database
+#[must_use]
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+	NaiveDateTime::parse_from_str(timestamp, DATETIME_FORMAT)
+		.ok()
+		.map(|time| time.and_utc())
+}
+
+/// The current time, as stored in the database
+#[must_use]
+pub fn now() -> String {
+	timestamp(SystemTime::now().into())
+}
+
+/// The time `seconds` from now, as stored in the database
+#[must_use]
+pub fn seconds_from_now(seconds: u64) -> String {
+	timestamp((SystemTime::now() + Duration::from_secs(seconds)).into())
+}
+
+/// The time `days` from now, as stored in the database
+#[must_use]
+pub fn days_from_now(days: u32) -> String {
+	seconds_from_now(u64::from(days) * SECONDS_PER_DAY)
+}
+
+/// Today's date, as stored in the database
+#[must_use]
+pub fn today() -> String {
+	DateTime::<Utc>::from(SystemTime::now())
+		.format(DATE_FORMAT)
+		.to_string()
+}
+
+/// The date `days` ago, as stored in the database
+#[must_use]
+pub fn days_ago(days: u32) -> String {
+	let then = SystemTime::now()
+		.checked_sub(Duration::from_secs(u64::from(days) * SECONDS_PER_DAY))
+		.unwrap_or(SystemTime::UNIX_EPOCH);
+	DateTime::<Utc>::from(then).format(DATE_FORMAT).to_string()
+}
+
+/// Whether the query failed because a row with the same unique key already exists
+#[must_use]
+pub fn is_unique_violation(e: &sqlx::Error) -> bool {
+	matches!(e, sqlx::Error::Database(e) if e.is_unique_violation())
+}
+
+/// Whether the query failed because a row is still referred to by another, or refers to one that
+/// doesn't exist
+#[must_use]
+pub fn is_foreign_key_violation(e: &sqlx::Error) -> bool {
+	match e {
+		// sqlite reports `ON DELETE RESTRICT` as a trigger constraint rather than a foreign key one
+		sqlx::Error::Database(e) => {
+			e.is_foreign_key_violation() || e.code().as_deref() == Some("1811")
+		}
+		_ => false,
+	}
+}
+
+/// A boolean stored as an integer, as sqlite has no boolean type
+///
+/// Fields decode through it with `#[sqlx(try_from = "Flag")]`, and queries give `1` or `0`
+/// rather than `TRUE` or `FALSE` so they're integers on every database.
+#[derive(Debug, Clone, Copy)]
+pub struct Flag(i64);
+
+impl Type<Any> for Flag {
+	fn type_info() -> AnyTypeInfo {
+		<i64 as Type<Any>>::type_info()
+	}
+
+	fn compatible(ty: &AnyTypeInfo) -> bool {
+		<i64 as Type<Any>>::compatible(ty)
+	}
+}
+
+impl<'r> Decode<'r, Any> for Flag {
+	fn decode(value: AnyValueRef<'r>) -> Result<Self, BoxDynError> {
+		<i64 as Decode<Any>>::decode(value).map(Self)
+	}
+}
+
+impl From<Flag> for bool {
+	fn from(flag: Flag) -> Self {
+		flag.0 != 0
+	}
+}
@@ -0,0 +1,401 @@
+//! This module handles counting how visitors navigate the webring
+//!
+//! Every hop through `/next`, `/prev` and `/random`, and every load of the widget, adds one to a
+//! daily count for its source and destination site. Nothing about the visitor is stored. The counts
+//! are kept in memory, and written to the database by [`RingState::run_navigation_worker`], so
+//! navigating never waits for the database.
+//!
+//! It also gathers the instance-wide numbers exported as metrics.
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{debug, error, instrument, trace};
+
+use super::{RingError, RingState, sql};
+
+/// The most days of stats that can be looked at at once
+pub const MAX_STATS_DAYS: u32 = 365;
+
+/// How a visitor moved through the ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NavigationKind {
+	Next,
+	Prev,
+	Random,
+	/// The widget was shown on a member's site
+	Widget,
+}
+
+impl NavigationKind {
+	/// The name of the kind, as stored in the database
+	#[must_use]
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::Next => "next",
+			Self::Prev => "prev",
+			Self::Random => "random",
+			Self::Widget => "widget",
+		}
+	}
+}
+
+/// The navigation counted since it was last written to the database, shared by the states scoped
+/// to every ring
+#[derive(Debug, Default)]
+pub(super) struct NavigationCounts {
+	hits: Mutex<HashMap<Hop, i64>>,
+}
+
+/// Hops that are counted together, as one row of the stats
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Hop {
+	ring_id: i64,
+	day: String,
+	kind: NavigationKind,
+	source_site_id: i64,
+	destination_site_id: i64,
+}
+
+impl NavigationCounts {
+	fn add(&self, hop: Hop, hits: i64) {
+		*self
+			.hits
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.entry(hop)
+			.or_default() += hits;
+	}
+
+	/// Takes every count, leaving none behind
+	fn take(&self) -> HashMap<Hop, i64> {
+		std::mem::take(&mut self.hits.lock().unwrap_or_else(PoisonError::into_inner))
+	}
+}
+
+/// A day of navigation across the whole ring
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DailyRingStats {
+	pub day: String,
+	pub next: i64,
+	pub prev: i64,
+	pub random: i64,
+	pub widget: i64,
+}
+
+/// The navigation to and from a single site
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SiteStats {
+	pub site_id: i64,
+	pub root_url: String,
+	pub title: Option<String>,
+	/// Visitors who left the site through the ring
+	pub outgoing: i64,
+	/// Visitors the ring sent to the site
+	pub incoming: i64,
+	pub widget_loads: i64,
+}
+
+/// A day of navigation to and from a single site
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DailySiteStats {
+	pub day: String,
+	/// Visitors who left the site through the ring
+	pub outgoing: i64,
+	/// Visitors the ring sent to the site
+	pub incoming: i64,
+	pub widget_loads: i64,
+}
+
+/// The membership of a ring, and how the things the instance fetches for it are doing
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RingHealth {
+	/// The ring's slug
+	pub ring: String,
+	pub pending: i64,
+	pub approved: i64,
+	pub denied: i64,
+	/// Linked rings whose manifest was last fetched and verified without a problem
+	pub peers_ok: i64,
+	pub peers_failing: i64,
+	/// Member feeds that were last fetched for the planet without a problem
+	pub feeds_ok: i64,
+	pub feeds_failing: i64,
+}
+
+/// The state of the database connection pool
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PoolStats {
+	/// Open connections, whether in use or idle
+	pub size: u32,
+	pub idle: usize,
+	pub max: u32,
+}
+
+/// The first day of a period of `days` days, including today
+fn since(days: u32) -> String {
+	sql::days_ago(days.clamp(1, MAX_STATS_DAYS) - 1)
+}
+
+impl RingState {
+	/// Counts a visitor navigating from `source_url` to `destination_url`
+	///
+	/// Either can be left out when there isn't one, like the source of a random hop from the home
+	/// page. Urls that aren't in the order of the ring kept in memory, like those of sites that
+	/// aren't approved or the old urls of sites that moved, are counted as not given. The count is
+	/// only kept in memory until [`RingState::flush_navigation`] writes it to the database.
+	/// Counting is best effort, so problems are logged rather than returned.
+	#[instrument]
+	pub async fn record_navigation(
+		&self,
+		kind: NavigationKind,
+		source_url: Option<&str>,
+		destination_url: Option<&str>,
+	) {
+		let topology = match self.topology().await {
+			Ok(topology) => Some(topology),
+			Err(e) => {
+				error!("Could not load the ring to count navigation through it: {e}");
+				None
+			}
+		};
+		let site_id = |url: Option<&str>| {
+			url.and_then(|url| topology.as_ref()?.site_id(url))
+				.unwrap_or(0)
+		};
+		let hop = Hop {
+			ring_id: self.ring.id,
+			day: sql::today(),
+			kind,
+			source_site_id: site_id(source_url),
+			destination_site_id: site_id(destination_url),
+		};
+		trace!(
+			"Counted a {} from site {} to site {}",
+			kind.as_str(),
+			hop.source_site_id,
+			hop.destination_site_id
+		);
+		self.navigation.add(hop, 1);
+	}
+
+	/// Writes the navigation counted in memory for every ring to the database, returning how many
+	/// rows of stats were updated
+	///
+	/// Counts that couldn't be written are kept to be written next time.
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn flush_navigation(&self) -> Result<usize, RingError> {
+		let counts = self.navigation.take();
+		if counts.is_empty() {
+			return Ok(0);
+		}
+		match self.write_navigation(&counts).await {
+			Ok(()) => Ok(counts.len()),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in flush_navigation: {}",
+					e
+				);
+				for (hop, hits) in counts {
+					self.navigation.add(hop, hits);
+				}
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn write_navigation(&self, counts: &HashMap<Hop, i64>) -> Result<(), sqlx::Error> {
+		let mut tx = self.database.begin().await?;
+		for (hop, hits) in counts {
+			sqlx::query(
+				"INSERT INTO navigation_stats
+				(ring_id, day, kind, source_site_id, destination_site_id, hits)
+				VALUES ($1, $2, $3, $4, $5, $6)
+				ON CONFLICT (ring_id, day, kind, source_site_id, destination_site_id)
+				DO UPDATE SET hits = navigation_stats.hits + excluded.hits",
+			)
+			.bind(hop.ring_id)
+			.bind(&hop.day)
+			.bind(hop.kind.as_str())
+			.bind(hop.source_site_id)
+			.bind(hop.destination_site_id)
+			.bind(hits)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await
+	}
+
+	/// Writes the navigation counted in memory to the database every `interval`, forever
+	pub async fn run_navigation_worker(self, interval: Duration) {
+		let mut ticker = tokio::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match self.flush_navigation().await {
+				Ok(rows) => debug!("Wrote {} rows of navigation stats", rows),
+				Err(e) => error!("Error when writing navigation stats: {e}"),
+			}
+		}
+	}
+
+	/// Gets the navigation across the whole ring for each of the last `days` days that had any,
+	/// newest first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_ring_daily_stats(&self, days: u32) -> Result<Vec<DailyRingStats>, RingError> {
+		match sqlx::query_as(
+			"SELECT day,
+			CAST(SUM(CASE WHEN kind = 'next' THEN hits ELSE 0 END) AS BIGINT) AS next,
+			CAST(SUM(CASE WHEN kind = 'prev' THEN hits ELSE 0 END) AS BIGINT) AS prev,
+			CAST(SUM(CASE WHEN kind = 'random' THEN hits ELSE 0 END) AS BIGINT) AS random,
+			CAST(SUM(CASE WHEN kind = 'widget' THEN hits ELSE 0 END) AS BIGINT) AS widget
+			FROM navigation_stats WHERE ring_id = $1 AND day >= $2
+			GROUP BY day ORDER BY day DESC",
+		)
+		.bind(self.ring.id)
+		.bind(since(days))
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(stats) => Ok(stats),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_ring_daily_stats: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets the navigation to and from every approved site in the ring over the last `days` days
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_site_stats(&self, days: u32) -> Result<Vec<SiteStats>, RingError> {
+		match sqlx::query_as(
+			"SELECT a.site_id, a.root_url, a.title,
+			CAST(COALESCE(SUM(CASE WHEN n.source_site_id = a.site_id
+				AND n.kind IN ('next', 'prev', 'random') THEN n.hits END), 0) AS BIGINT) AS outgoing,
+			CAST(COALESCE(SUM(CASE WHEN n.destination_site_id = a.site_id THEN n.hits END), 0)
+				AS BIGINT) AS incoming,
+			CAST(COALESCE(SUM(CASE WHEN n.source_site_id = a.site_id AND n.kind = 'widget'
+				THEN n.hits END), 0) AS BIGINT) AS widget_loads
+			FROM approved_sites AS a LEFT JOIN navigation_stats AS n
+			ON (n.source_site_id = a.site_id OR n.destination_site_id = a.site_id)
+			AND n.day >= $1
+			WHERE a.ring_id = $2 GROUP BY a.site_id, a.root_url, a.title ORDER BY a.site_id",
+		)
+		.bind(since(days))
+		.bind(self.ring.id)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(stats) => Ok(stats),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_site_stats: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets the navigation to and from a site for each of the last `days` days that had any,
+	/// newest first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_site_daily_stats(
+		&self,
+		site_id: i64,
+		days: u32,
+	) -> Result<Vec<DailySiteStats>, RingError> {
+		match sqlx::query_as(
+			"SELECT day,
+			CAST(SUM(CASE WHEN source_site_id = $1 AND kind IN ('next', 'prev', 'random')
+				THEN hits ELSE 0 END) AS BIGINT) AS outgoing,
+			CAST(SUM(CASE WHEN destination_site_id = $1 THEN hits ELSE 0 END) AS BIGINT) AS incoming,
+			CAST(SUM(CASE WHEN source_site_id = $1 AND kind = 'widget' THEN hits ELSE 0 END)
+				AS BIGINT) AS widget_loads
+			FROM navigation_stats
+			WHERE ring_id = $2 AND (source_site_id = $1 OR destination_site_id = $1)
+			AND day >= $3
+			GROUP BY day ORDER BY day DESC",
+		)
+		.bind(site_id)
+		.bind(self.ring.id)
+		.bind(since(days))
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(stats) => Ok(stats),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_site_daily_stats: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets the [`RingHealth`] of every ring on the instance
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_ring_health(&self) -> Result<Vec<RingHealth>, RingError> {
+		match sqlx::query_as(
+			"SELECT r.slug AS ring,
+			(SELECT COUNT(*) FROM unapproved_sites AS s WHERE s.ring_id = r.id) AS pending,
+			(SELECT COUNT(*) FROM approved_sites AS s WHERE s.ring_id = r.id) AS approved,
+			(SELECT COUNT(*) FROM denied_sites AS s WHERE s.ring_id = r.id) AS denied,
+			(SELECT COUNT(*) FROM peer_rings AS p WHERE p.ring_id = r.id AND p.last_error IS NULL)
+				AS peers_ok,
+			(SELECT COUNT(*) FROM peer_rings AS p
+				WHERE p.ring_id = r.id AND p.last_error IS NOT NULL) AS peers_failing,
+			(SELECT COUNT(*) FROM planet_feeds AS f INNER JOIN sites AS s ON f.site_id = s.id
+				WHERE s.ring_id = r.id AND f.last_fetched_at IS NOT NULL AND f.last_error IS NULL)
+				AS feeds_ok,
+			(SELECT COUNT(*) FROM planet_feeds AS f INNER JOIN sites AS s ON f.site_id = s.id
+				WHERE s.ring_id = r.id AND f.last_error IS NOT NULL) AS feeds_failing
+			FROM rings AS r ORDER BY r.id",
+		)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(health) => Ok(health),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_ring_health: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets the [`PoolStats`] of the database connection pool
+	#[must_use]
+	pub fn pool_stats(&self) -> PoolStats {
+		PoolStats {
+			size: self.database.size(),
+			idle: self.database.num_idle(),
+			max: self.database.options().get_max_connections(),
+		}
+	}
+}
@@ -0,0 +1,951 @@
+use async_trait::async_trait;
+use sqlx::AnyPool;
+use tracing::{error, info};
+
+use super::RingStore;
+use crate::ring::{
+	ApprovedSite, DeniedSite, RingError, SiteProfile, UnapprovedSite,
+	auth::Admin,
+	importer::{IMPORT_SOURCE, ImportedSite},
+	members::MemberSite,
+	rings::Ring,
+	sql,
+	tags::Tag,
+};
+
+/// A [`RingStore`] that keeps everything in the database, alongside the rest of the webring
+#[derive(Debug, Clone)]
+pub struct DatabaseStore {
+	database: AnyPool,
+}
+
+impl DatabaseStore {
+	/// Creates a new [`DatabaseStore`] using the given, already migrated, database
+	#[must_use]
+	pub const fn new(database: AnyPool) -> Self {
+		Self { database }
+	}
+}
+
+#[async_trait]
+impl RingStore for DatabaseStore {
+	async fn get_ring(&self, ring_id: i64) -> Result<Option<Ring>, RingError> {
+		match sqlx::query_as("SELECT * FROM rings WHERE id = $1")
+			.bind(ring_id)
+			.fetch_optional(&self.database)
+			.await
+		{
+			Ok(ring) => Ok(ring),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_ring: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_ring_by_slug(&self, slug: &str) -> Result<Option<Ring>, RingError> {
+		match sqlx::query_as("SELECT * FROM rings WHERE slug = $1")
+			.bind(slug)
+			.fetch_optional(&self.database)
+			.await
+		{
+			Ok(ring) => Ok(ring),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_ring_by_slug: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_list_rings(&self) -> Result<Vec<Ring>, RingError> {
+		match sqlx::query_as("SELECT * FROM rings ORDER BY id")
+			.fetch_all(&self.database)
+			.await
+		{
+			Ok(rings) => Ok(rings),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_rings: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn add_ring(
+		&self,
+		slug: &str,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError> {
+		match sqlx::query("INSERT INTO rings (slug, name, description) VALUES ($1, $2, $3)")
+			.bind(slug)
+			.bind(name)
+			.bind(description)
+			.execute(&self.database)
+			.await
+		{
+			Ok(_query_outcome) => Ok(()),
+			Err(ref e) if sql::is_unique_violation(e) => {
+				Err(RingError::UniqueRowAlreadyPresent(slug.to_owned()))
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in add_ring: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn update_ring(
+		&self,
+		ring_id: i64,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError> {
+		match sqlx::query("UPDATE rings SET name = $1, description = $2 WHERE id = $3")
+			.bind(name)
+			.bind(description)
+			.bind(ring_id)
+			.execute(&self.database)
+			.await
+		{
+			Ok(_query_outcome) => Ok(()),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in update_ring: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn add_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		email: &str,
+		verification_token: &str,
+		profile: &SiteProfile,
+	) -> Result<(), RingError> {
+		match sqlx::query(
+			"INSERT INTO sites (ring_id, root_url, email, email_verification_token, title, description,
+			owner_name, feed_url, button_url) values ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+		)
+		.bind(ring_id)
+		.bind(root_url)
+		.bind(email)
+		.bind(verification_token)
+		.bind(&profile.title)
+		.bind(&profile.description)
+		.bind(&profile.owner_name)
+		.bind(&profile.feed_url)
+		.bind(&profile.button_url)
+		.execute(&self.database)
+		.await
+		{
+			Ok(_query_outcome) => Ok(()),
+			Err(ref e) if sql::is_unique_violation(e) => {
+				Err(RingError::UniqueRowAlreadyPresent(root_url.to_owned()))
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in add_site: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn remove_site(&self, ring_id: i64, root_url: &str) -> Result<(), RingError> {
+		let mut tx = self.database.begin().await?;
+		// Only approved sites leaving is news, so only they show up in the membership feed
+		sqlx::query(
+			"INSERT INTO site_removals (ring_id, root_url, title, date_removed)
+			SELECT ring_id, root_url, title, $1 FROM approved_sites
+			WHERE root_url = $2 AND ring_id = $3",
+		)
+		.bind(sql::now())
+		.bind(root_url)
+		.bind(ring_id)
+		.execute(&mut *tx)
+		.await?;
+		match sqlx::query("DELETE FROM sites WHERE root_url = $1 AND ring_id = $2")
+			.bind(root_url)
+			.bind(ring_id)
+			.execute(&mut *tx)
+			.await
+		{
+			Ok(query_outcome) if query_outcome.rows_affected() == 0 => {
+				Err(RingError::RowNotFound(root_url.to_owned()))
+			}
+			Ok(_query_outcome) => {
+				tx.commit().await?;
+				Ok(())
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in remove_site: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn change_site_url(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		new_url: &str,
+		alias_expires_at: Option<String>,
+	) -> Result<String, RingError> {
+		let mut tx = self.database.begin().await?;
+
+		let Some(old_url): Option<String> =
+			sqlx::query_scalar("SELECT root_url FROM sites WHERE id = $1 AND ring_id = $2")
+				.bind(site_id)
+				.bind(ring_id)
+				.fetch_optional(&mut *tx)
+				.await?
+		else {
+			return Err(RingError::RowNotFound(format!("Site with id {site_id}")));
+		};
+
+		match sqlx::query("UPDATE sites SET root_url = $1 WHERE id = $2")
+			.bind(new_url)
+			.bind(site_id)
+			.execute(&mut *tx)
+			.await
+		{
+			Ok(_query_outcome) => {}
+			Err(ref e) if sql::is_unique_violation(e) => {
+				info!(
+					"Site {} tried to move to {} but it was already registered",
+					old_url, new_url
+				);
+				return Err(RingError::UniqueRowAlreadyPresent(new_url.to_owned()));
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in change_site_url: {}",
+					e
+				);
+				return Err(RingError::UnrecoverableDatabaseError(e));
+			}
+		}
+
+		// The new url can't also be an alias in the ring, and expired aliases may as well be
+		// cleaned up
+		sqlx::query(
+			"DELETE FROM site_url_aliases WHERE expires_at <= $3
+			OR (old_url IN ($1, $2) AND site_id IN (SELECT id FROM sites WHERE ring_id = $4))",
+		)
+		.bind(new_url)
+		.bind(&old_url)
+		.bind(sql::now())
+		.bind(ring_id)
+		.execute(&mut *tx)
+		.await?;
+
+		if let Some(expires_at) = alias_expires_at {
+			sqlx::query(
+				"INSERT INTO site_url_aliases (old_url, site_id, expires_at) VALUES ($1, $2, $3)",
+			)
+			.bind(&old_url)
+			.bind(site_id)
+			.bind(expires_at)
+			.execute(&mut *tx)
+			.await?;
+		}
+
+		tx.commit().await?;
+		Ok(old_url)
+	}
+
+	async fn approve_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		admin_id: i64,
+	) -> Result<(), RingError> {
+		let mut tx = match self.database.begin().await {
+			Ok(tx) => tx,
+			Err(e) => return Err(RingError::UnrecoverableDatabaseError(e)),
+		};
+
+		let approval_id: i64 = match sqlx::query_scalar(
+			"INSERT INTO approval_records (date_added, approved_at, admin_id) VALUES ($1, $2, $3)
+			RETURNING id",
+		)
+		.bind(sql::today())
+		.bind(sql::now())
+		.bind(admin_id)
+		.fetch_one(&mut *tx)
+		.await
+		{
+			Ok(id) => id,
+			Err(e) => {
+				error!("There was an error when adding an approval record");
+				return Err(RingError::UnrecoverableDatabaseError(e));
+			}
+		};
+
+		match sqlx::query("UPDATE sites SET approval_id = $1 WHERE root_url = $2 AND ring_id = $3")
+			.bind(approval_id)
+			.bind(root_url)
+			.bind(ring_id)
+			.execute(&mut *tx)
+			.await
+		{
+			// Dropping the transaction takes the approval record back out
+			Ok(query) if query.rows_affected() == 0 => {
+				return Err(RingError::RowNotFound(format!("Site {root_url}")));
+			}
+			Ok(_query) => {}
+			// TODO: Distinguish for the type of error you get when there is a constraint error
+			// (e.g. there is already a denial_id set or vice versa)
+			Err(e) => return Err(RingError::UnrecoverableDatabaseError(e)),
+		}
+
+		if let Err(e) = tx.commit().await {
+			return Err(RingError::UnrecoverableDatabaseError(e));
+		}
+		Ok(())
+	}
+
+	async fn deny_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		reason: &str,
+		admin_id: i64,
+	) -> Result<(), RingError> {
+		let mut tx = match self.database.begin().await {
+			Ok(tx) => tx,
+			Err(e) => return Err(RingError::UnrecoverableDatabaseError(e)),
+		};
+
+		let denial_id: i64 = match sqlx::query_scalar(
+			"INSERT INTO denial_records (date_added, admin_id, reason) VALUES ($1, $2, $3)
+			RETURNING id",
+		)
+		.bind(sql::today())
+		.bind(admin_id)
+		.bind(reason)
+		.fetch_one(&mut *tx)
+		.await
+		{
+			Ok(id) => id,
+			Err(e) => {
+				error!("There was an error when adding a denial record");
+				return Err(RingError::UnrecoverableDatabaseError(e));
+			}
+		};
+
+		match sqlx::query("UPDATE sites SET denial_id = $1 WHERE root_url = $2 AND ring_id = $3")
+			.bind(denial_id)
+			.bind(root_url)
+			.bind(ring_id)
+			.execute(&mut *tx)
+			.await
+		{
+			Ok(query) if query.rows_affected() == 0 => {
+				return Err(RingError::RowNotFound(format!("Site {root_url}")));
+			}
+			Ok(_query) => {}
+			Err(e) => return Err(RingError::UnrecoverableDatabaseError(e)),
+		}
+
+		if let Err(e) = tx.commit().await {
+			return Err(RingError::UnrecoverableDatabaseError(e));
+		}
+		Ok(())
+	}
+
+	async fn get_approved_site_id(&self, ring_id: i64, root_url: &str) -> Result<i64, RingError> {
+		// Sites that have moved can still be navigated from their old url for a while
+		match sqlx::query_scalar(
+			"SELECT site_id FROM approved_sites WHERE root_url = $1 AND ring_id = $2
+			UNION ALL
+			SELECT s.site_id FROM site_url_aliases AS a
+			INNER JOIN approved_sites AS s ON a.site_id = s.site_id
+			WHERE a.old_url = $1 AND s.ring_id = $2 AND a.expires_at > $3
+			LIMIT 1",
+		)
+		.bind(root_url)
+		.bind(ring_id)
+		.bind(sql::now())
+		.fetch_one(&self.database)
+		.await
+		{
+			Ok(site_id) => Ok(site_id),
+			Err(sqlx::Error::RowNotFound) => Err(RingError::SiteNotApproved(root_url.to_owned())),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_verified_id: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_list_approved(
+		&self,
+		ring_id: i64,
+		tag: Option<&str>,
+	) -> Result<Vec<ApprovedSite>, RingError> {
+		match sqlx::query_as(
+			"SELECT * FROM approved_sites WHERE ring_id = $2 AND ($1 IS NULL OR site_id IN (
+				SELECT st.site_id FROM site_tags AS st INNER JOIN tags AS t ON st.tag_id = t.id
+				WHERE t.name = $1
+			)) ORDER BY site_id",
+		)
+		.bind(tag)
+		.bind(ring_id)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(sites) => Ok(sites),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_approved: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_approved_site(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+	) -> Result<ApprovedSite, RingError> {
+		match sqlx::query_as("SELECT * FROM approved_sites WHERE site_id = $1 AND ring_id = $2")
+			.bind(site_id)
+			.bind(ring_id)
+			.fetch_optional(&self.database)
+			.await
+		{
+			Ok(Some(site)) => Ok(site),
+			Ok(None) => Err(RingError::SiteNotApproved(format!("Site with id {site_id}"))),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_approved_site: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_list_denied(&self, ring_id: i64) -> Result<Vec<DeniedSite>, RingError> {
+		match sqlx::query_as("SELECT * FROM denied_sites WHERE ring_id = $1")
+			.bind(ring_id)
+			.fetch_all(&self.database)
+			.await
+		{
+			Ok(sites) => Ok(sites),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_denied: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_list_unapproved(&self, ring_id: i64) -> Result<Vec<UnapprovedSite>, RingError> {
+		match sqlx::query_as("SELECT * FROM unapproved_sites WHERE ring_id = $1 ORDER BY id")
+			.bind(ring_id)
+			.fetch_all(&self.database)
+			.await
+		{
+			Ok(sites) => Ok(sites),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_unapproved: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_member_site(&self, ring_id: i64, site_id: i64) -> Result<MemberSite, RingError> {
+		match sqlx::query_as(
+			"SELECT s.id, s.root_url, s.email, s.email_verified_at, s.title, s.description,
+			s.owner_name, s.feed_url, s.button_url, ar.date_added AS approval_date,
+			dr.date_added AS denial_date, dr.reason AS denial_reason
+			FROM sites AS s
+			LEFT JOIN approval_records AS ar ON s.approval_id = ar.id
+			LEFT JOIN denial_records AS dr ON s.denial_id = dr.id
+			WHERE s.id = $1 AND s.ring_id = $2",
+		)
+		.bind(site_id)
+		.bind(ring_id)
+		.fetch_optional(&self.database)
+		.await
+		{
+			Ok(Some(site)) => Ok(site),
+			Ok(None) => Err(RingError::RowNotFound(format!("Site with id {site_id}"))),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_member_site: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_site_id(&self, ring_id: i64, root_url: &str) -> Result<i64, RingError> {
+		match sqlx::query_scalar("SELECT id FROM sites WHERE root_url = $1 AND ring_id = $2")
+			.bind(root_url)
+			.bind(ring_id)
+			.fetch_optional(&self.database)
+			.await
+		{
+			Ok(Some(site_id)) => Ok(site_id),
+			Ok(None) => Err(RingError::RowNotFound(root_url.to_owned())),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_site_id: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn update_site_profile(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		profile: &SiteProfile,
+	) -> Result<(), RingError> {
+		match sqlx::query(
+			"UPDATE sites SET title = $1, description = $2, owner_name = $3, feed_url = $4,
+			button_url = $5 WHERE id = $6 AND ring_id = $7",
+		)
+		.bind(&profile.title)
+		.bind(&profile.description)
+		.bind(&profile.owner_name)
+		.bind(&profile.feed_url)
+		.bind(&profile.button_url)
+		.bind(site_id)
+		.bind(ring_id)
+		.execute(&self.database)
+		.await
+		{
+			Ok(query_outcome) if query_outcome.rows_affected() == 0 => {
+				Err(RingError::RowNotFound(format!("Site with id {site_id}")))
+			}
+			Ok(_query_outcome) => Ok(()),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in update_site_profile: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn change_site_email(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		email: &str,
+		verification_token: &str,
+	) -> Result<String, RingError> {
+		match sqlx::query_scalar(
+			"UPDATE sites SET email = $1, email_verified_at = NULL, email_verification_token = $2
+			WHERE id = $3 AND ring_id = $4 RETURNING root_url",
+		)
+		.bind(email)
+		.bind(verification_token)
+		.bind(site_id)
+		.bind(ring_id)
+		.fetch_optional(&self.database)
+		.await
+		{
+			Ok(Some(root_url)) => Ok(root_url),
+			Ok(None) => Err(RingError::RowNotFound(format!("Site with id {site_id}"))),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in change_site_email: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn verify_site_email(&self, token: &str, verified_at: &str) -> Result<String, RingError> {
+		match sqlx::query_scalar(
+			"UPDATE sites SET email_verified_at = $1, email_verification_token = NULL
+			WHERE email_verification_token = $2 RETURNING root_url",
+		)
+		.bind(verified_at)
+		.bind(token)
+		.fetch_optional(&self.database)
+		.await
+		{
+			Ok(Some(root_url)) => Ok(root_url),
+			Ok(None) => Err(RingError::RowNotFound("Email verification token".to_owned())),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in verify_site_email: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn add_member_login_token(
+		&self,
+		site_id: i64,
+		token_hash: &str,
+		expires_at: &str,
+	) -> Result<(), RingError> {
+		sqlx::query(
+			"INSERT INTO member_login_tokens (token_hash, site_id, expires_at)
+			VALUES ($1, $2, $3)",
+		)
+		.bind(token_hash)
+		.bind(site_id)
+		.bind(expires_at)
+		.execute(&self.database)
+		.await?;
+		Ok(())
+	}
+
+	async fn redeem_member_login_token(
+		&self,
+		ring_id: i64,
+		token_hash: &str,
+	) -> Result<i64, RingError> {
+		match sqlx::query_as::<_, (i64, i64)>(
+			"DELETE FROM member_login_tokens WHERE token_hash = $1 RETURNING site_id,
+			CASE WHEN expires_at > $3 AND site_id IN (SELECT id FROM sites WHERE ring_id = $2)
+			THEN 1 ELSE 0 END",
+		)
+		.bind(token_hash)
+		.bind(ring_id)
+		.bind(sql::now())
+		.fetch_optional(&self.database)
+		.await
+		{
+			Ok(Some((site_id, 1))) => Ok(site_id),
+			Ok(_) => Err(RingError::RowNotFound("Member login token".to_owned())),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in redeem_member_login_token: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_list_tags(&self, ring_id: i64) -> Result<Vec<Tag>, RingError> {
+		match sqlx::query_as(
+			"SELECT t.name, COUNT(*) AS site_count FROM tags AS t
+			INNER JOIN site_tags AS st ON t.id = st.tag_id
+			INNER JOIN approved_sites AS s ON st.site_id = s.site_id
+			WHERE s.ring_id = $1
+			GROUP BY t.id ORDER BY site_count DESC, t.name",
+		)
+		.bind(ring_id)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(tags) => Ok(tags),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_tags: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_site_tags(&self, ring_id: i64, site_id: i64) -> Result<Vec<String>, RingError> {
+		Ok(sqlx::query_scalar(
+			"SELECT t.name FROM tags AS t INNER JOIN site_tags AS st ON t.id = st.tag_id
+			INNER JOIN sites AS s ON st.site_id = s.id
+			WHERE st.site_id = $1 AND s.ring_id = $2 ORDER BY t.name",
+		)
+		.bind(site_id)
+		.bind(ring_id)
+		.fetch_all(&self.database)
+		.await?)
+	}
+
+	async fn set_site_tags(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		tags: &[String],
+	) -> Result<(), RingError> {
+		let mut tx = self.database.begin().await?;
+
+		if sqlx::query("SELECT id FROM sites WHERE id = $1 AND ring_id = $2")
+			.bind(site_id)
+			.bind(ring_id)
+			.fetch_optional(&mut *tx)
+			.await?
+			.is_none()
+		{
+			return Err(RingError::RowNotFound(format!("Site with id {site_id}")));
+		}
+
+		sqlx::query("DELETE FROM site_tags WHERE site_id = $1")
+			.bind(site_id)
+			.execute(&mut *tx)
+			.await?;
+		for tag in tags {
+			sqlx::query("INSERT INTO tags (name) VALUES ($1) ON CONFLICT DO NOTHING")
+				.bind(tag)
+				.execute(&mut *tx)
+				.await?;
+			sqlx::query(
+				"INSERT INTO site_tags (site_id, tag_id) SELECT $1, id FROM tags WHERE name = $2",
+			)
+			.bind(site_id)
+			.bind(tag)
+			.execute(&mut *tx)
+			.await?;
+		}
+		// Tags that no site uses any more aren't worth keeping
+		sqlx::query("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM site_tags)")
+			.execute(&mut *tx)
+			.await?;
+
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn get_list_site_urls(&self, ring_id: i64) -> Result<Vec<String>, RingError> {
+		match sqlx::query_scalar("SELECT root_url FROM sites WHERE ring_id = $1")
+			.bind(ring_id)
+			.fetch_all(&self.database)
+			.await
+		{
+			Ok(urls) => Ok(urls),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_site_urls: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn import_sites(
+		&self,
+		ring_id: i64,
+		sites: &[ImportedSite],
+		fallback_email: &str,
+		approved_by: Option<i64>,
+	) -> Result<(), RingError> {
+		let mut tx = self.database.begin().await?;
+		for site in sites {
+			let approval_id: Option<i64> = match approved_by {
+				Some(admin_id) => Some(
+					sqlx::query_scalar(
+						"INSERT INTO approval_records (date_added, approved_at, admin_id, source)
+						VALUES ($1, $2, $3, $4) RETURNING id",
+					)
+					.bind(sql::today())
+					.bind(sql::now())
+					.bind(admin_id)
+					.bind(IMPORT_SOURCE)
+					.fetch_one(&mut *tx)
+					.await?,
+				),
+				None => None,
+			};
+			let site_id: i64 = match sqlx::query_scalar(
+				"INSERT INTO sites (ring_id, root_url, email, title, description, owner_name,
+				feed_url, button_url, approval_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+				RETURNING id",
+			)
+			.bind(ring_id)
+			.bind(&site.url)
+			.bind(site.email.as_deref().unwrap_or(fallback_email))
+			.bind(&site.profile.title)
+			.bind(&site.profile.description)
+			.bind(&site.profile.owner_name)
+			.bind(&site.profile.feed_url)
+			.bind(&site.profile.button_url)
+			.bind(approval_id)
+			.fetch_one(&mut *tx)
+			.await
+			{
+				Ok(site_id) => site_id,
+				Err(ref e) if sql::is_unique_violation(e) => {
+					return Err(RingError::UniqueRowAlreadyPresent(site.url.clone()));
+				}
+				Err(e) => return Err(RingError::UnrecoverableDatabaseError(e)),
+			};
+			for tag in &site.tags {
+				sqlx::query("INSERT INTO tags (name) VALUES ($1) ON CONFLICT DO NOTHING")
+					.bind(tag)
+					.execute(&mut *tx)
+					.await?;
+				sqlx::query(
+					"INSERT INTO site_tags (site_id, tag_id)
+					SELECT $1, id FROM tags WHERE name = $2",
+				)
+				.bind(site_id)
+				.bind(tag)
+				.execute(&mut *tx)
+				.await?;
+			}
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn add_admin(
+		&self,
+		username: &str,
+		email: &str,
+		password_phc: &str,
+		ring_id: Option<i64>,
+	) -> Result<(), RingError> {
+		match sqlx::query(
+			"INSERT INTO admins (username, email, password_phc, ring_id) values ($1, $2, $3, $4)",
+		)
+		.bind(username)
+		.bind(email)
+		.bind(password_phc)
+		.bind(ring_id)
+		.execute(&self.database)
+		.await
+		{
+			Ok(_query_result) => Ok(()),
+			Err(ref e) if sql::is_unique_violation(e) => Err(RingError::UniqueRowAlreadyPresent(
+				format!("{username} {email}"),
+			)),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in add_admin: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn delete_admin(&self, admin_id: i64) -> Result<(), RingError> {
+		match sqlx::query("DELETE FROM admins WHERE id = $1")
+			.bind(admin_id)
+			.execute(&self.database)
+			.await
+		{
+			Ok(query) if query.rows_affected() == 0 => Err(RingError::RowNotFound(format!(
+				"Admin with admin id {admin_id:?}"
+			))),
+			Ok(_query) => Ok(()),
+			// Approval and denial records keep the admin who made them
+			Err(ref e) if sql::is_foreign_key_violation(e) => Err(RingError::RowInUse(format!(
+				"Admin with admin id {admin_id:?}"
+			))),
+			Err(e) => {
+				error!(
+					"There was a database error when trying to delete admin with id {}: {}",
+					admin_id, e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_admin(&self, admin_id: i64) -> Result<Option<Admin>, RingError> {
+		match sqlx::query_as("SELECT * FROM admins WHERE id = $1")
+			.bind(admin_id)
+			.fetch_optional(&self.database)
+			.await
+		{
+			Ok(admin) => Ok(admin),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_admin: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_admin_by_username(&self, username: &str) -> Result<Option<Admin>, RingError> {
+		match sqlx::query_as("SELECT * FROM admins WHERE username = $1")
+			.bind(username)
+			.fetch_optional(&self.database)
+			.await
+		{
+			Ok(admin) => Ok(admin),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_admin_by_username: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn get_list_admins(&self) -> Result<Vec<Admin>, RingError> {
+		match sqlx::query_as("SELECT * FROM admins ORDER BY id")
+			.fetch_all(&self.database)
+			.await
+		{
+			Ok(admins) => Ok(admins),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_admins: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	async fn set_password(&self, admin_id: i64, password_phc: &str) -> Result<(), RingError> {
+		match sqlx::query("UPDATE admins SET password_phc = $1 WHERE id = $2")
+			.bind(password_phc)
+			.bind(admin_id)
+			.execute(&self.database)
+			.await
+		{
+			Ok(query) if query.rows_affected() == 0 => Err(RingError::RowNotFound(format!(
+				"Admin with admin id {admin_id}"
+			))),
+			Ok(_query) => Ok(()),
+			Err(e) => {
+				error!("Error when trying to update admin password: {e}");
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+}
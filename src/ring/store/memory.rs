@@ -0,0 +1,735 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use async_trait::async_trait;
+
+use super::RingStore;
+use crate::ring::{
+	ApprovedSite, DeniedSite, RingError, SiteProfile, UnapprovedSite,
+	auth::Admin,
+	importer::ImportedSite,
+	members::MemberSite,
+	rings::{DEFAULT_RING_ID, Ring},
+	sql,
+	tags::Tag,
+};
+
+/// A [`RingStore`] that keeps everything in memory, for tests and trying things out
+///
+/// It starts out with just the default ring, without the default admin the database has. Clones
+/// share the same data, so a test can keep one to set things up behind the handlers' backs.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+	data: Arc<Mutex<Data>>,
+}
+
+#[derive(Debug)]
+struct Data {
+	rings: Vec<Ring>,
+	/// In the order they were added, which is the order of the ring
+	sites: Vec<Site>,
+	aliases: Vec<Alias>,
+	admins: Vec<Admin>,
+	login_tokens: Vec<LoginToken>,
+	last_ring_id: i64,
+	last_site_id: i64,
+	last_admin_id: i64,
+}
+
+impl Default for Data {
+	fn default() -> Self {
+		Self {
+			rings: vec![Ring::default()],
+			sites: Vec::new(),
+			aliases: Vec::new(),
+			admins: Vec::new(),
+			login_tokens: Vec::new(),
+			last_ring_id: DEFAULT_RING_ID,
+			last_site_id: 0,
+			last_admin_id: 0,
+		}
+	}
+}
+
+#[derive(Debug)]
+struct Site {
+	id: i64,
+	ring_id: i64,
+	root_url: String,
+	email: String,
+	email_verification_token: Option<String>,
+	email_verified_at: Option<String>,
+	profile: SiteProfile,
+	/// In alphabetical order
+	tags: Vec<String>,
+	decision: Option<Decision>,
+}
+
+#[derive(Debug)]
+enum Decision {
+	Approved {
+		date_added: String,
+		admin_id: i64,
+	},
+	Denied {
+		date_added: String,
+		reason: String,
+		admin_id: i64,
+	},
+}
+
+impl Decision {
+	const fn admin_id(&self) -> i64 {
+		match self {
+			Self::Approved { admin_id, .. } | Self::Denied { admin_id, .. } => *admin_id,
+		}
+	}
+}
+
+/// An old url of a site that moved, which still finds it until `expires_at`
+#[derive(Debug)]
+struct Alias {
+	old_url: String,
+	site_id: i64,
+	expires_at: String,
+}
+
+#[derive(Debug)]
+struct LoginToken {
+	token_hash: String,
+	site_id: i64,
+	expires_at: String,
+}
+
+impl MemoryStore {
+	fn data(&self) -> MutexGuard<'_, Data> {
+		// Nothing is left half changed when a lock is poisoned, so the data is still fine to use
+		self.data.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl Data {
+	fn admin(&self, admin_id: i64) -> Result<&Admin, RingError> {
+		self.admins
+			.iter()
+			.find(|admin| admin.id == admin_id)
+			.ok_or_else(|| RingError::RowNotFound(format!("Admin with admin id {admin_id}")))
+	}
+
+	fn approved_site(&self, site: &Site) -> Option<ApprovedSite> {
+		let Some(Decision::Approved {
+			date_added,
+			admin_id,
+		}) = &site.decision
+		else {
+			return None;
+		};
+		let admin = self.admin(*admin_id).ok()?;
+		Some(ApprovedSite {
+			site_id: site.id,
+			root_url: site.root_url.clone(),
+			site_email: site.email.clone(),
+			profile: site.profile.clone(),
+			tags: (!site.tags.is_empty()).then(|| site.tags.join(" ")),
+			date_added: date_added.clone(),
+			admin_id: admin.id,
+			admin_username: admin.username.clone(),
+			admin_email: admin.email.clone(),
+		})
+	}
+
+	/// The approved sites of a ring, in order, with `tag` if it's given
+	fn approved_sites(&self, ring_id: i64, tag: Option<&str>) -> Vec<ApprovedSite> {
+		self.sites
+			.iter()
+			.filter(|site| site.ring_id == ring_id)
+			.filter(|site| tag.is_none_or(|tag| site.tags.iter().any(|t| t == tag)))
+			.filter_map(|site| self.approved_site(site))
+			.collect()
+	}
+
+	fn site_mut(&mut self, ring_id: i64, site_id: i64) -> Result<&mut Site, RingError> {
+		self.sites
+			.iter_mut()
+			.find(|site| site.id == site_id && site.ring_id == ring_id)
+			.ok_or_else(|| RingError::RowNotFound(format!("Site with id {site_id}")))
+	}
+
+	fn decide(
+		&mut self,
+		ring_id: i64,
+		root_url: &str,
+		decision: Decision,
+	) -> Result<(), RingError> {
+		self.admin(decision.admin_id())?;
+		let Some(site) = self
+			.sites
+			.iter_mut()
+			.find(|site| site.ring_id == ring_id && site.root_url == root_url)
+		else {
+			return Err(RingError::RowNotFound(format!("Site {root_url}")));
+		};
+		// Like the database, a site can be approved again but not both approved and denied
+		match (&site.decision, &decision) {
+			(Some(Decision::Approved { .. }), Decision::Denied { .. }) => Err(
+				RingError::InvalidInput(format!("{root_url} has already been approved")),
+			),
+			(Some(Decision::Denied { .. }), Decision::Approved { .. }) => Err(
+				RingError::InvalidInput(format!("{root_url} has already been denied")),
+			),
+			_ => {
+				site.decision = Some(decision);
+				Ok(())
+			}
+		}
+	}
+}
+
+#[async_trait]
+impl RingStore for MemoryStore {
+	async fn get_ring(&self, ring_id: i64) -> Result<Option<Ring>, RingError> {
+		Ok(self
+			.data()
+			.rings
+			.iter()
+			.find(|ring| ring.id == ring_id)
+			.cloned())
+	}
+
+	async fn get_ring_by_slug(&self, slug: &str) -> Result<Option<Ring>, RingError> {
+		Ok(self
+			.data()
+			.rings
+			.iter()
+			.find(|ring| ring.slug == slug)
+			.cloned())
+	}
+
+	async fn get_list_rings(&self) -> Result<Vec<Ring>, RingError> {
+		Ok(self.data().rings.clone())
+	}
+
+	async fn add_ring(
+		&self,
+		slug: &str,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError> {
+		let mut data = self.data();
+		if data.rings.iter().any(|ring| ring.slug == slug) {
+			return Err(RingError::UniqueRowAlreadyPresent(slug.to_owned()));
+		}
+		data.last_ring_id += 1;
+		let ring = Ring {
+			id: data.last_ring_id,
+			slug: slug.to_owned(),
+			name: name.to_owned(),
+			description: description.map(str::to_owned),
+			..Ring::default()
+		};
+		data.rings.push(ring);
+		Ok(())
+	}
+
+	async fn update_ring(
+		&self,
+		ring_id: i64,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError> {
+		if let Some(ring) = self.data().rings.iter_mut().find(|ring| ring.id == ring_id) {
+			name.clone_into(&mut ring.name);
+			ring.description = description.map(str::to_owned);
+		}
+		Ok(())
+	}
+
+	async fn add_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		email: &str,
+		verification_token: &str,
+		profile: &SiteProfile,
+	) -> Result<(), RingError> {
+		let mut data = self.data();
+		if data
+			.sites
+			.iter()
+			.any(|site| site.ring_id == ring_id && site.root_url == root_url)
+		{
+			return Err(RingError::UniqueRowAlreadyPresent(root_url.to_owned()));
+		}
+		data.last_site_id += 1;
+		let id = data.last_site_id;
+		data.sites.push(Site {
+			id,
+			ring_id,
+			root_url: root_url.to_owned(),
+			email: email.to_owned(),
+			email_verification_token: Some(verification_token.to_owned()),
+			email_verified_at: None,
+			profile: profile.clone(),
+			tags: Vec::new(),
+			decision: None,
+		});
+		Ok(())
+	}
+
+	async fn remove_site(&self, ring_id: i64, root_url: &str) -> Result<(), RingError> {
+		let mut data = self.data();
+		let Some(index) = data
+			.sites
+			.iter()
+			.position(|site| site.ring_id == ring_id && site.root_url == root_url)
+		else {
+			return Err(RingError::RowNotFound(root_url.to_owned()));
+		};
+		let site = data.sites.remove(index);
+		data.aliases.retain(|alias| alias.site_id != site.id);
+		data.login_tokens.retain(|token| token.site_id != site.id);
+		Ok(())
+	}
+
+	async fn change_site_url(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		new_url: &str,
+		alias_expires_at: Option<String>,
+	) -> Result<String, RingError> {
+		let mut data = self.data();
+		if data
+			.sites
+			.iter()
+			.any(|site| site.ring_id == ring_id && site.root_url == new_url && site.id != site_id)
+		{
+			return Err(RingError::UniqueRowAlreadyPresent(new_url.to_owned()));
+		}
+		let Some(site) = data
+			.sites
+			.iter_mut()
+			.find(|site| site.id == site_id && site.ring_id == ring_id)
+		else {
+			return Err(RingError::RowNotFound(format!("Site with id {site_id}")));
+		};
+		let old_url = std::mem::replace(&mut site.root_url, new_url.to_owned());
+		let now = sql::now();
+		// The new url can't also be an alias in the ring, and expired aliases may as well be cleaned
+		// up
+		let ring_site_ids: Vec<i64> = data
+			.sites
+			.iter()
+			.filter(|site| site.ring_id == ring_id)
+			.map(|site| site.id)
+			.collect();
+		data.aliases.retain(|alias| {
+			let replaced = (alias.old_url == new_url || alias.old_url == old_url)
+				&& ring_site_ids.contains(&alias.site_id);
+			!replaced && alias.expires_at > now
+		});
+		if let Some(expires_at) = alias_expires_at {
+			data.aliases.push(Alias {
+				old_url: old_url.clone(),
+				site_id,
+				expires_at,
+			});
+		}
+		Ok(old_url)
+	}
+
+	async fn approve_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		admin_id: i64,
+	) -> Result<(), RingError> {
+		self.data().decide(
+			ring_id,
+			root_url,
+			Decision::Approved {
+				date_added: sql::today(),
+				admin_id,
+			},
+		)
+	}
+
+	async fn deny_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		reason: &str,
+		admin_id: i64,
+	) -> Result<(), RingError> {
+		self.data().decide(
+			ring_id,
+			root_url,
+			Decision::Denied {
+				date_added: sql::today(),
+				reason: reason.to_owned(),
+				admin_id,
+			},
+		)
+	}
+
+	async fn get_approved_site_id(&self, ring_id: i64, root_url: &str) -> Result<i64, RingError> {
+		let data = self.data();
+		let approved = data.approved_sites(ring_id, None);
+		if let Some(site) = approved.iter().find(|site| site.root_url == root_url) {
+			return Ok(site.site_id);
+		}
+		// Sites that have moved can still be navigated from their old url for a while
+		let now = sql::now();
+		data.aliases
+			.iter()
+			.filter(|alias| alias.old_url == root_url && alias.expires_at > now)
+			.find(|alias| approved.iter().any(|site| site.site_id == alias.site_id))
+			.map(|alias| alias.site_id)
+			.ok_or_else(|| RingError::SiteNotApproved(root_url.to_owned()))
+	}
+
+	async fn get_list_approved(
+		&self,
+		ring_id: i64,
+		tag: Option<&str>,
+	) -> Result<Vec<ApprovedSite>, RingError> {
+		Ok(self.data().approved_sites(ring_id, tag))
+	}
+
+	async fn get_approved_site(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+	) -> Result<ApprovedSite, RingError> {
+		self.data()
+			.approved_sites(ring_id, None)
+			.into_iter()
+			.find(|site| site.site_id == site_id)
+			.ok_or_else(|| RingError::SiteNotApproved(format!("Site with id {site_id}")))
+	}
+
+	async fn get_list_denied(&self, ring_id: i64) -> Result<Vec<DeniedSite>, RingError> {
+		let data = self.data();
+		Ok(data
+			.sites
+			.iter()
+			.filter(|site| site.ring_id == ring_id)
+			.filter_map(|site| {
+				let Some(Decision::Denied {
+					date_added,
+					reason,
+					admin_id,
+				}) = &site.decision
+				else {
+					return None;
+				};
+				let admin = data.admin(*admin_id).ok()?;
+				Some(DeniedSite {
+					site_id: site.id,
+					root_url: site.root_url.clone(),
+					site_email: site.email.clone(),
+					date_added: date_added.clone(),
+					reason: reason.clone(),
+					admin_id: admin.id,
+					admin_username: admin.username.clone(),
+					admin_email: admin.email.clone(),
+				})
+			})
+			.collect())
+	}
+
+	async fn get_list_unapproved(&self, ring_id: i64) -> Result<Vec<UnapprovedSite>, RingError> {
+		Ok(self
+			.data()
+			.sites
+			.iter()
+			.filter(|site| site.ring_id == ring_id && site.decision.is_none())
+			.map(|site| UnapprovedSite {
+				id: site.id,
+				root_url: site.root_url.clone(),
+				email: site.email.clone(),
+				email_verified_at: site.email_verified_at.clone(),
+			})
+			.collect())
+	}
+
+	async fn get_member_site(&self, ring_id: i64, site_id: i64) -> Result<MemberSite, RingError> {
+		let mut data = self.data();
+		let site = data.site_mut(ring_id, site_id)?;
+		let (approval_date, denial_date, denial_reason) = match &site.decision {
+			Some(Decision::Approved { date_added, .. }) => (Some(date_added.clone()), None, None),
+			Some(Decision::Denied {
+				date_added, reason, ..
+			}) => (None, Some(date_added.clone()), Some(reason.clone())),
+			None => (None, None, None),
+		};
+		Ok(MemberSite {
+			id: site.id,
+			root_url: site.root_url.clone(),
+			email: site.email.clone(),
+			email_verified_at: site.email_verified_at.clone(),
+			profile: site.profile.clone(),
+			approval_date,
+			denial_date,
+			denial_reason,
+		})
+	}
+
+	async fn get_site_id(&self, ring_id: i64, root_url: &str) -> Result<i64, RingError> {
+		self.data()
+			.sites
+			.iter()
+			.find(|site| site.ring_id == ring_id && site.root_url == root_url)
+			.map(|site| site.id)
+			.ok_or_else(|| RingError::RowNotFound(root_url.to_owned()))
+	}
+
+	async fn update_site_profile(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		profile: &SiteProfile,
+	) -> Result<(), RingError> {
+		self.data().site_mut(ring_id, site_id)?.profile = profile.clone();
+		Ok(())
+	}
+
+	async fn change_site_email(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		email: &str,
+		verification_token: &str,
+	) -> Result<String, RingError> {
+		let mut data = self.data();
+		let site = data.site_mut(ring_id, site_id)?;
+		email.clone_into(&mut site.email);
+		site.email_verification_token = Some(verification_token.to_owned());
+		site.email_verified_at = None;
+		Ok(site.root_url.clone())
+	}
+
+	async fn verify_site_email(&self, token: &str, verified_at: &str) -> Result<String, RingError> {
+		let mut data = self.data();
+		let Some(site) = data
+			.sites
+			.iter_mut()
+			.find(|site| site.email_verification_token.as_deref() == Some(token))
+		else {
+			return Err(RingError::RowNotFound("Email verification token".to_owned()));
+		};
+		site.email_verification_token = None;
+		site.email_verified_at = Some(verified_at.to_owned());
+		Ok(site.root_url.clone())
+	}
+
+	async fn add_member_login_token(
+		&self,
+		site_id: i64,
+		token_hash: &str,
+		expires_at: &str,
+	) -> Result<(), RingError> {
+		self.data().login_tokens.push(LoginToken {
+			token_hash: token_hash.to_owned(),
+			site_id,
+			expires_at: expires_at.to_owned(),
+		});
+		Ok(())
+	}
+
+	async fn redeem_member_login_token(
+		&self,
+		ring_id: i64,
+		token_hash: &str,
+	) -> Result<i64, RingError> {
+		let mut data = self.data();
+		let not_found = || RingError::RowNotFound("Member login token".to_owned());
+		let index = data
+			.login_tokens
+			.iter()
+			.position(|token| token.token_hash == token_hash)
+			.ok_or_else(not_found)?;
+		// Like the database, a token is used up even when it can't be used
+		let token = data.login_tokens.remove(index);
+		let in_ring = data
+			.sites
+			.iter()
+			.any(|site| site.id == token.site_id && site.ring_id == ring_id);
+		if in_ring && token.expires_at > sql::now() {
+			Ok(token.site_id)
+		} else {
+			Err(not_found())
+		}
+	}
+
+	async fn get_list_tags(&self, ring_id: i64) -> Result<Vec<Tag>, RingError> {
+		let mut tags: Vec<Tag> = Vec::new();
+		for site in self.data().approved_sites(ring_id, None) {
+			for name in site.tag_list() {
+				match tags.iter_mut().find(|tag| tag.name == name) {
+					Some(tag) => tag.site_count += 1,
+					None => tags.push(Tag {
+						name: name.to_owned(),
+						site_count: 1,
+					}),
+				}
+			}
+		}
+		tags.sort_by(|a, b| b.site_count.cmp(&a.site_count).then_with(|| a.name.cmp(&b.name)));
+		Ok(tags)
+	}
+
+	async fn get_site_tags(&self, ring_id: i64, site_id: i64) -> Result<Vec<String>, RingError> {
+		Ok(self
+			.data()
+			.sites
+			.iter()
+			.find(|site| site.id == site_id && site.ring_id == ring_id)
+			.map(|site| site.tags.clone())
+			.unwrap_or_default())
+	}
+
+	async fn set_site_tags(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		tags: &[String],
+	) -> Result<(), RingError> {
+		let mut data = self.data();
+		let site = data.site_mut(ring_id, site_id)?;
+		site.tags = tags.to_vec();
+		site.tags.sort();
+		Ok(())
+	}
+
+	async fn get_list_site_urls(&self, ring_id: i64) -> Result<Vec<String>, RingError> {
+		Ok(self
+			.data()
+			.sites
+			.iter()
+			.filter(|site| site.ring_id == ring_id)
+			.map(|site| site.root_url.clone())
+			.collect())
+	}
+
+	async fn import_sites(
+		&self,
+		ring_id: i64,
+		sites: &[ImportedSite],
+		fallback_email: &str,
+		approved_by: Option<i64>,
+	) -> Result<(), RingError> {
+		let mut data = self.data();
+		if let Some(admin_id) = approved_by {
+			data.admin(admin_id)?;
+		}
+		for (i, site) in sites.iter().enumerate() {
+			if data
+				.sites
+				.iter()
+				.any(|existing| existing.ring_id == ring_id && existing.root_url == site.url)
+				|| sites[..i].iter().any(|earlier| earlier.url == site.url)
+			{
+				return Err(RingError::UniqueRowAlreadyPresent(site.url.clone()));
+			}
+		}
+		for site in sites {
+			data.last_site_id += 1;
+			let mut tags = site.tags.clone();
+			tags.sort();
+			let site = Site {
+				id: data.last_site_id,
+				ring_id,
+				root_url: site.url.clone(),
+				email: site.email.as_deref().unwrap_or(fallback_email).to_owned(),
+				email_verification_token: None,
+				email_verified_at: None,
+				profile: site.profile.clone(),
+				tags,
+				decision: approved_by.map(|admin_id| Decision::Approved {
+					date_added: sql::today(),
+					admin_id,
+				}),
+			};
+			data.sites.push(site);
+		}
+		Ok(())
+	}
+
+	async fn add_admin(
+		&self,
+		username: &str,
+		email: &str,
+		password_phc: &str,
+		ring_id: Option<i64>,
+	) -> Result<(), RingError> {
+		let mut data = self.data();
+		if data
+			.admins
+			.iter()
+			.any(|admin| admin.username == username || admin.email == email)
+		{
+			return Err(RingError::UniqueRowAlreadyPresent(format!(
+				"{username} {email}"
+			)));
+		}
+		data.last_admin_id += 1;
+		let admin = Admin::new(
+			data.last_admin_id,
+			username.to_owned(),
+			email.to_owned(),
+			password_phc.to_owned(),
+			ring_id,
+		);
+		data.admins.push(admin);
+		Ok(())
+	}
+
+	async fn delete_admin(&self, admin_id: i64) -> Result<(), RingError> {
+		let mut data = self.data();
+		let Some(index) = data.admins.iter().position(|admin| admin.id == admin_id) else {
+			return Err(RingError::RowNotFound(format!(
+				"Admin with admin id {admin_id:?}"
+			)));
+		};
+		// Approvals and denials keep the admin who made them
+		if data.sites.iter().any(|site| {
+			site.decision
+				.as_ref()
+				.is_some_and(|decision| decision.admin_id() == admin_id)
+		}) {
+			return Err(RingError::RowInUse(format!(
+				"Admin with admin id {admin_id:?}"
+			)));
+		}
+		data.admins.remove(index);
+		Ok(())
+	}
+
+	async fn get_admin(&self, admin_id: i64) -> Result<Option<Admin>, RingError> {
+		Ok(self.data().admin(admin_id).ok().cloned())
+	}
+
+	async fn get_admin_by_username(&self, username: &str) -> Result<Option<Admin>, RingError> {
+		Ok(self
+			.data()
+			.admins
+			.iter()
+			.find(|admin| admin.username == username)
+			.cloned())
+	}
+
+	async fn get_list_admins(&self) -> Result<Vec<Admin>, RingError> {
+		Ok(self.data().admins.clone())
+	}
+
+	async fn set_password(&self, admin_id: i64, password_phc: &str) -> Result<(), RingError> {
+		let mut data = self.data();
+		let Some(admin) = data.admins.iter_mut().find(|admin| admin.id == admin_id) else {
+			return Err(RingError::RowNotFound(format!(
+				"Admin with admin id {admin_id}"
+			)));
+		};
+		password_phc.clone_into(&mut admin.password_phc);
+		Ok(())
+	}
+}
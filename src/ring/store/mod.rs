@@ -0,0 +1,319 @@
+//! This module handles where the rings, their sites, the decisions made about them and the admins
+//! are kept
+//!
+//! [`RingState`](super::RingState) goes through a [`RingStore`] for these, so they can live
+//! somewhere other than the database, like in memory for tests. That covers navigating the rings,
+//! joining and leaving them, approving sites, tags, the member area and logging in. The things the
+//! instance keeps about the sites on the side, like stats, the planet, peer rings, webhooks, the
+//! membership feed and backups, are still kept in the database, so they don't see sites kept in
+//! another store.
+
+use async_trait::async_trait;
+
+use super::{
+	ApprovedSite, DeniedSite, RingError, SiteProfile, UnapprovedSite, auth::Admin,
+	importer::ImportedSite, members::MemberSite, rings::Ring, tags::Tag,
+};
+
+mod database;
+mod memory;
+
+pub use database::DatabaseStore;
+pub use memory::MemoryStore;
+
+/// Somewhere to keep a webring's sites, the decisions made about them, and its admins
+///
+/// Sites belong to the ring with the given `ring_id`, and are in the ring in the order they were
+/// added. Dates are stored in the format of [`sql::today`](super::sql::today), and times in the
+/// format of [`sql::now`](super::sql::now).
+#[async_trait]
+pub trait RingStore: Send + Sync {
+	/// Gets the ring with the given id, if there is one
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_ring(&self, ring_id: i64) -> Result<Option<Ring>, RingError>;
+
+	/// Gets the ring with the given slug, if there is one
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_ring_by_slug(&self, slug: &str) -> Result<Option<Ring>, RingError>;
+
+	/// Gets every ring, the default ring first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_list_rings(&self) -> Result<Vec<Ring>, RingError>;
+
+	/// Adds a new, empty ring
+	///
+	/// # Errors
+	/// [`RingError::UniqueRowAlreadyPresent`] if a ring with that slug already exists
+	async fn add_ring(
+		&self,
+		slug: &str,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError>;
+
+	/// Changes the name and description of a ring
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn update_ring(
+		&self,
+		ring_id: i64,
+		name: &str,
+		description: Option<&str>,
+	) -> Result<(), RingError>;
+
+	/// Adds a site that is waiting for approval
+	///
+	/// # Errors
+	/// [`RingError::UniqueRowAlreadyPresent`] if a site with that url has already been added
+	async fn add_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		email: &str,
+		verification_token: &str,
+		profile: &SiteProfile,
+	) -> Result<(), RingError>;
+
+	/// Removes a site, whatever has been decided about it
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that url
+	async fn remove_site(&self, ring_id: i64, root_url: &str) -> Result<(), RingError>;
+
+	/// Moves a site to a new url, returning its old one
+	///
+	/// If `alias_expires_at` is given, the old url still finds the site until then.
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	/// [`RingError::UniqueRowAlreadyPresent`] if another site already has the new url
+	async fn change_site_url(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		new_url: &str,
+		alias_expires_at: Option<String>,
+	) -> Result<String, RingError>;
+
+	/// Records an admin approving a site
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that url in the ring
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn approve_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		admin_id: i64,
+	) -> Result<(), RingError>;
+
+	/// Records an admin denying a site
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that url in the ring
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn deny_site(
+		&self,
+		ring_id: i64,
+		root_url: &str,
+		reason: &str,
+		admin_id: i64,
+	) -> Result<(), RingError>;
+
+	/// Gets the id of the approved site with the given url, or that moved from it recently
+	///
+	/// # Errors
+	/// [`RingError::SiteNotApproved`] if there is no such approved site
+	async fn get_approved_site_id(&self, ring_id: i64, root_url: &str) -> Result<i64, RingError>;
+
+	/// Gets every approved site, only those with `tag` if it's given, in order
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_list_approved(
+		&self,
+		ring_id: i64,
+		tag: Option<&str>,
+	) -> Result<Vec<ApprovedSite>, RingError>;
+
+	/// Gets a single approved site
+	///
+	/// # Errors
+	/// [`RingError::SiteNotApproved`] if there is no approved site with that id
+	async fn get_approved_site(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+	) -> Result<ApprovedSite, RingError>;
+
+	/// Gets every denied site
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_list_denied(&self, ring_id: i64) -> Result<Vec<DeniedSite>, RingError>;
+
+	/// Gets every site waiting for approval, in order
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_list_unapproved(&self, ring_id: i64) -> Result<Vec<UnapprovedSite>, RingError>;
+
+	/// Gets a site along with the outcome of its application
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	async fn get_member_site(&self, ring_id: i64, site_id: i64) -> Result<MemberSite, RingError>;
+
+	/// Gets the id of the site with the given url, whatever has been decided about it
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that url
+	async fn get_site_id(&self, ring_id: i64, root_url: &str) -> Result<i64, RingError>;
+
+	/// Replaces the profile of a site
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	async fn update_site_profile(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		profile: &SiteProfile,
+	) -> Result<(), RingError>;
+
+	/// Changes the email of a site, which has to be verified again with `verification_token`,
+	/// returning the site's url
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	async fn change_site_email(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		email: &str,
+		verification_token: &str,
+	) -> Result<String, RingError>;
+
+	/// Marks the email of the site with the given verification token as verified at `verified_at`,
+	/// returning the site's url
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if no site has that token
+	async fn verify_site_email(&self, token: &str, verified_at: &str) -> Result<String, RingError>;
+
+	/// Keeps the hash of a token that logs the owner of a site in until `expires_at`
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn add_member_login_token(
+		&self,
+		site_id: i64,
+		token_hash: &str,
+		expires_at: &str,
+	) -> Result<(), RingError>;
+
+	/// Uses up a login token, returning the id of the site it logs in to
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if the token is unknown, expired or for a site in another ring
+	async fn redeem_member_login_token(
+		&self,
+		ring_id: i64,
+		token_hash: &str,
+	) -> Result<i64, RingError>;
+
+	/// Gets every tag used by an approved site in the ring, most used first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_list_tags(&self, ring_id: i64) -> Result<Vec<Tag>, RingError>;
+
+	/// Gets the tags of a site, in alphabetical order
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_site_tags(&self, ring_id: i64, site_id: i64) -> Result<Vec<String>, RingError>;
+
+	/// Replaces the tags of a site with `tags`, which have already been checked
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	async fn set_site_tags(
+		&self,
+		ring_id: i64,
+		site_id: i64,
+		tags: &[String],
+	) -> Result<(), RingError>;
+
+	/// Gets the url of every site in a ring, whether it's been decided on or not
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_list_site_urls(&self, ring_id: i64) -> Result<Vec<String>, RingError>;
+
+	/// Adds every site in `sites` to a ring at once, in order, with the tags they have
+	///
+	/// If `approved_by` is given, each site is approved by that admin, marked as an import.
+	/// Sites without an email get `fallback_email`, and their emails aren't verified.
+	///
+	/// # Errors
+	/// [`RingError::UniqueRowAlreadyPresent`] if a site with one of the urls has already been
+	/// added, in which case none of them are
+	async fn import_sites(
+		&self,
+		ring_id: i64,
+		sites: &[ImportedSite],
+		fallback_email: &str,
+		approved_by: Option<i64>,
+	) -> Result<(), RingError>;
+
+	/// Adds an admin, who can manage every ring if `ring_id` is `None`
+	///
+	/// # Errors
+	/// [`RingError::UniqueRowAlreadyPresent`] if the username or email is already in use
+	async fn add_admin(
+		&self,
+		username: &str,
+		email: &str,
+		password_phc: &str,
+		ring_id: Option<i64>,
+	) -> Result<(), RingError>;
+
+	/// Deletes an admin
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no admin with that id
+	/// [`RingError::RowInUse`] if the admin has approved or denied sites
+	async fn delete_admin(&self, admin_id: i64) -> Result<(), RingError>;
+
+	/// Gets the admin with the given id, if there is one
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_admin(&self, admin_id: i64) -> Result<Option<Admin>, RingError>;
+
+	/// Gets the admin with the given username, if there is one
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_admin_by_username(&self, username: &str) -> Result<Option<Admin>, RingError>;
+
+	/// Gets every admin, in the order they were added
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	async fn get_list_admins(&self) -> Result<Vec<Admin>, RingError>;
+
+	/// Replaces an admin's password hash
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no admin with that id
+	async fn set_password(&self, admin_id: i64, password_phc: &str) -> Result<(), RingError>;
+}
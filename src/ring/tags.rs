@@ -0,0 +1,127 @@
+//! This module handles tagging sites, so that each tag forms its own sub-ring
+//!
+//! Tags are short, lowercase names made up of letters, numbers and dashes, e.g. `art` or
+//! `dev-blogs`.
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{info, instrument};
+
+use super::{ApprovedSite, RingError, RingState};
+
+/// The most tags a single site can have
+pub const MAX_TAGS_PER_SITE: usize = 10;
+const MAX_TAG_LENGTH: usize = 32;
+
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct Tag {
+	pub name: String,
+	/// How many approved sites have this tag
+	pub site_count: i64,
+}
+
+/// Splits a list of tags separated by spaces or commas, lowercasing and deduplicating them
+///
+/// # Errors
+/// [`RingError::InvalidInput`] if a tag contains anything other than letters, numbers and dashes,
+/// is too long, or there are too many tags
+pub fn parse_tags(input: &str) -> Result<Vec<String>, RingError> {
+	let mut tags: Vec<String> = Vec::new();
+	for tag in input
+		.split(|c: char| c == ',' || c.is_whitespace())
+		.filter(|tag| !tag.is_empty())
+	{
+		let tag = tag.to_lowercase();
+		if tag.chars().count() > MAX_TAG_LENGTH {
+			return Err(RingError::InvalidInput(format!(
+				"The tag {tag} is longer than {MAX_TAG_LENGTH} characters"
+			)));
+		}
+		if !tag.chars().all(|c| c.is_alphanumeric() || c == '-') {
+			return Err(RingError::InvalidInput(format!(
+				"The tag {tag} can only contain letters, numbers and dashes"
+			)));
+		}
+		if !tags.contains(&tag) {
+			tags.push(tag);
+		}
+	}
+	if tags.len() > MAX_TAGS_PER_SITE {
+		return Err(RingError::InvalidInput(format!(
+			"Sites can have at most {MAX_TAGS_PER_SITE} tags"
+		)));
+	}
+	Ok(tags)
+}
+
+/// Normalises a tag a visitor asked for, e.g. with `?tag=Rust`, the same way [`parse_tags`] does,
+/// so that it finds the sites tagged `rust`. An empty tag is no tag at all.
+#[must_use]
+pub fn normalise_tag(tag: Option<&str>) -> Option<String> {
+	tag.map(|tag| tag.trim().to_lowercase())
+		.filter(|tag| !tag.is_empty())
+}
+
+impl ApprovedSite {
+	/// The site's tags
+	#[must_use]
+	pub fn tag_list(&self) -> Vec<&str> {
+		self.tags
+			.as_deref()
+			.map(|tags| tags.split(' ').collect())
+			.unwrap_or_default()
+	}
+}
+
+impl RingState {
+	/// Gets every tag used by an approved site in the ring, most used first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_list_tags(&self) -> Result<Vec<Tag>, RingError> {
+		self.store.get_list_tags(self.ring.id).await
+	}
+
+	/// Gets the tags of a site, in alphabetical order
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_site_tags(&self, site_id: i64) -> Result<Vec<String>, RingError> {
+		self.store.get_site_tags(self.ring.id, site_id).await
+	}
+
+	/// Replaces the tags of a site, see [`parse_tags`]
+	///
+	/// # Errors
+	/// [`RingError::InvalidInput`] if the tags are invalid
+	/// [`RingError::RowNotFound`] if there is no site with that id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn set_site_tags(&self, site_id: i64, tags: &str) -> Result<(), RingError> {
+		let tags = parse_tags(tags)?;
+		self.store.set_site_tags(self.ring.id, site_id, &tags).await?;
+		self.topology.invalidate(self.ring.id);
+		info!("Set tags of site {} to {:?}", site_id, tags);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{normalise_tag, parse_tags};
+
+	#[test]
+	fn parses_tags() {
+		assert_eq!(parse_tags("Art, music art").unwrap(), ["art", "music"]);
+		assert!(parse_tags("no_underscores").is_err());
+	}
+
+	#[test]
+	fn normalises_tags_like_they_are_stored() {
+		assert_eq!(normalise_tag(Some(" Rust ")).as_deref(), Some("rust"));
+		assert_eq!(normalise_tag(Some("")), None);
+		assert_eq!(normalise_tag(None), None);
+	}
+}
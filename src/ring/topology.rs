@@ -0,0 +1,168 @@
+//! This module handles keeping the order of each ring in memory, so navigating it doesn't need the
+//! database
+//!
+//! A ring's approved sites are loaded the first time someone navigates it, and thrown away
+//! whenever its membership changes. Sites navigated from an old url after moving aren't in the
+//! snapshot, so they are still looked up in the [`RingStore`](super::store::RingStore).
+
+use std::{
+	collections::HashMap,
+	sync::{
+		Arc, PoisonError, RwLock,
+		atomic::{AtomicU64, Ordering},
+	},
+	time::{Duration, Instant},
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+use super::ApprovedSite;
+
+/// How long a snapshot is used before being loaded again, so changes made outside this process,
+/// like by the `webringer site` subcommands, show up eventually
+const MAX_AGE: Duration = Duration::from_mins(1);
+
+/// The snapshot of every ring that has been navigated recently
+#[derive(Debug, Default)]
+pub(super) struct TopologyCache {
+	rings: RwLock<HashMap<i64, Arc<Topology>>>,
+	/// Goes up every time a snapshot is thrown away, so one loaded from before then isn't kept
+	generation: AtomicU64,
+}
+
+/// The approved sites of a ring, in the order they are in the ring
+#[derive(Debug)]
+pub(super) struct Topology {
+	loaded_at: Instant,
+	/// Sorted by id, which is the order of the ring
+	sites: Vec<Member>,
+	/// Where each site is in `sites`, by url
+	positions: HashMap<String, usize>,
+}
+
+#[derive(Debug)]
+struct Member {
+	id: i64,
+	root_url: String,
+	tags: Vec<String>,
+}
+
+impl Member {
+	fn has_tag(&self, tag: Option<&str>) -> bool {
+		tag.is_none_or(|tag| self.tags.iter().any(|own_tag| own_tag == tag))
+	}
+}
+
+impl Topology {
+	pub(super) fn new(approved: Vec<ApprovedSite>) -> Self {
+		let mut sites: Vec<Member> = approved
+			.into_iter()
+			.map(|site| Member {
+				id: site.site_id,
+				root_url: site.root_url,
+				tags: site
+					.tags
+					.unwrap_or_default()
+					.split_whitespace()
+					.map(str::to_owned)
+					.collect(),
+			})
+			.collect();
+		sites.sort_by_key(|site| site.id);
+		let positions = sites
+			.iter()
+			.enumerate()
+			.map(|(position, site)| (site.root_url.clone(), position))
+			.collect();
+		Self {
+			loaded_at: Instant::now(),
+			sites,
+			positions,
+		}
+	}
+
+	/// The id of the approved site with the given url
+	pub(super) fn site_id(&self, root_url: &str) -> Option<i64> {
+		self.positions
+			.get(root_url)
+			.map(|&position| self.sites[position].id)
+	}
+
+	/// The url of the first site after the site with the given id, only counting sites with `tag`
+	/// if it's given
+	pub(super) fn next(&self, site_id: i64, tag: Option<&str>) -> Option<&str> {
+		let after = self.sites.partition_point(|site| site.id <= site_id);
+		self.sites[after..]
+			.iter()
+			.find(|site| site.has_tag(tag))
+			.map(|site| site.root_url.as_str())
+	}
+
+	/// The url of the last site before the site with the given id, only counting sites with `tag`
+	/// if it's given
+	pub(super) fn prev(&self, site_id: i64, tag: Option<&str>) -> Option<&str> {
+		let before = self.sites.partition_point(|site| site.id < site_id);
+		self.sites[..before]
+			.iter()
+			.rfind(|site| site.has_tag(tag))
+			.map(|site| site.root_url.as_str())
+	}
+
+	/// The url of a random site, only picking from sites with `tag` if it's given
+	pub(super) fn random(&self, tag: Option<&str>) -> Option<&str> {
+		let sites: Vec<&Member> = self.sites.iter().filter(|site| site.has_tag(tag)).collect();
+		if sites.is_empty() {
+			return None;
+		}
+		#[allow(clippy::cast_possible_truncation)]
+		let index = (OsRng.next_u64() % sites.len() as u64) as usize;
+		Some(sites[index].root_url.as_str())
+	}
+}
+
+impl TopologyCache {
+	/// The snapshot of the ring with the given id, if there is an up to date one
+	pub(super) fn get(&self, ring_id: i64) -> Option<Arc<Topology>> {
+		self.rings
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&ring_id)
+			.filter(|topology| topology.loaded_at.elapsed() < MAX_AGE)
+			.cloned()
+	}
+
+	/// The current generation, to be passed to [`TopologyCache::insert`] along with a snapshot
+	/// loaded after getting it
+	pub(super) fn generation(&self) -> u64 {
+		self.generation.load(Ordering::Acquire)
+	}
+
+	/// Keeps the snapshot of a ring, unless its membership changed while it was being loaded
+	pub(super) fn insert(
+		&self,
+		ring_id: i64,
+		generation: u64,
+		topology: Topology,
+	) -> Arc<Topology> {
+		let topology = Arc::new(topology);
+		let mut rings = self.rings.write().unwrap_or_else(PoisonError::into_inner);
+		if self.generation() == generation {
+			rings.insert(ring_id, Arc::clone(&topology));
+		}
+		topology
+	}
+
+	/// Throws away the snapshot of a ring, after its membership changed
+	pub(super) fn invalidate(&self, ring_id: i64) {
+		let mut rings = self.rings.write().unwrap_or_else(PoisonError::into_inner);
+		self.generation.fetch_add(1, Ordering::AcqRel);
+		rings.remove(&ring_id);
+	}
+
+	/// Throws away the snapshot of every ring, after changes that could affect any of them
+	pub(super) fn invalidate_all(&self) {
+		let mut rings = self.rings.write().unwrap_or_else(PoisonError::into_inner);
+		self.generation.fetch_add(1, Ordering::AcqRel);
+		rings.clear();
+	}
+}
@@ -0,0 +1,374 @@
+//! This module handles notifying external services about changes to the webring
+//!
+//! Every [`RingEvent`] is queued in the database once for each registered webhook, and the queue
+//! is worked through by [`RingState::run_webhook_worker`]. Payloads are JSON, signed with
+//! HMAC-SHA256 using the webhook's secret, with the hex digest sent in the
+//! `X-Webringer-Signature` header as `sha256=<digest>`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::FromRow;
+use tracing::{debug, error, info, instrument, warn};
+
+use super::{RingError, RingState, auth::generate_token, sql};
+
+/// How many times a delivery is attempted before it is marked as failed
+const MAX_ATTEMPTS: i64 = 8;
+/// How many deliveries are sent per run of the worker
+const BATCH_SIZE: i64 = 50;
+
+/// Something that happened to the webring that webhooks are notified about
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RingEvent {
+	SiteApplied { root_url: String },
+	SiteApproved { root_url: String, admin_id: i64 },
+	SiteDenied { root_url: String, reason: String, admin_id: i64 },
+	SiteRemoved { root_url: String },
+	SiteMoved { old_url: String, new_url: String },
+	AdminAdded { username: String },
+	AdminDeleted { admin_id: i64 },
+}
+
+impl RingEvent {
+	/// The name of the event, as sent in the payload and the `X-Webringer-Event` header
+	#[must_use]
+	pub const fn name(&self) -> &'static str {
+		match self {
+			Self::SiteApplied { .. } => "site_applied",
+			Self::SiteApproved { .. } => "site_approved",
+			Self::SiteDenied { .. } => "site_denied",
+			Self::SiteRemoved { .. } => "site_removed",
+			Self::SiteMoved { .. } => "site_moved",
+			Self::AdminAdded { .. } => "admin_added",
+			Self::AdminDeleted { .. } => "admin_deleted",
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+	#[serde(flatten)]
+	event: &'a RingEvent,
+	/// The slug of the ring the event happened in
+	ring: &'a str,
+	timestamp: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct Webhook {
+	pub id: i64,
+	pub url: String,
+	pub secret: String,
+	pub date_added: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, FromRow)]
+pub struct WebhookDelivery {
+	pub id: i64,
+	pub webhook_url: String,
+	pub event: String,
+	pub payload: String,
+	pub status: String,
+	pub attempts: i64,
+	pub date_created: String,
+	pub last_attempt_at: Option<String>,
+	pub last_response: Option<String>,
+}
+
+#[derive(FromRow)]
+struct PendingDelivery {
+	id: i64,
+	event: String,
+	payload: String,
+	attempts: i64,
+	url: String,
+	secret: String,
+}
+
+/// Signs a payload with the given secret, returning the hex encoded HMAC-SHA256 digest
+///
+/// # Panics
+/// Never, as HMAC accepts keys of any length
+#[must_use]
+pub fn sign_payload(secret: &str, payload: &str) -> String {
+	let mut mac =
+		Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+	mac.update(payload.as_bytes());
+	hex::encode(mac.finalize().into_bytes())
+}
+
+/// Sends a single delivery, returning a description of the response and whether it succeeded
+fn send_delivery(delivery: &PendingDelivery) -> (bool, String) {
+	let agent: ureq::Agent = ureq::Agent::config_builder()
+		.timeout_global(Some(Duration::from_secs(10)))
+		.build()
+		.into();
+	match agent
+		.post(&delivery.url)
+		.header("Content-Type", "application/json")
+		.header("X-Webringer-Event", &delivery.event)
+		.header("X-Webringer-Delivery", delivery.id.to_string())
+		.header(
+			"X-Webringer-Signature",
+			format!("sha256={}", sign_payload(&delivery.secret, &delivery.payload)),
+		)
+		.send(&delivery.payload)
+	{
+		Ok(response) => (true, response.status().to_string()),
+		Err(e) => (false, e.to_string()),
+	}
+}
+
+impl RingState {
+	/// Queues an event to be delivered to every registered webhook
+	///
+	/// Errors are logged rather than returned, as a failure to queue a webhook shouldn't undo the
+	/// change that caused it
+	#[instrument]
+	pub(super) async fn enqueue_event(&self, event: RingEvent) {
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |duration| duration.as_secs());
+		let payload = match serde_json::to_string(&WebhookPayload {
+			event: &event,
+			ring: &self.ring.slug,
+			timestamp,
+		}) {
+			Ok(payload) => payload,
+			Err(e) => {
+				error!("Could not serialize webhook payload: {e}");
+				return;
+			}
+		};
+		let name = event.name();
+		match sqlx::query(
+			"INSERT INTO webhook_deliveries (webhook_id, event, payload, date_created, next_attempt_at)
+			SELECT id, $1, $2, $3, $3 FROM webhooks",
+		)
+		.bind(name)
+		.bind(payload)
+		.bind(sql::now())
+		.execute(&self.database)
+		.await
+		{
+			Ok(query_outcome) => debug!(
+				"Queued {} event for {} webhooks",
+				name,
+				query_outcome.rows_affected()
+			),
+			Err(e) => error!("There was a database error when queueing a webhook event: {e}"),
+		}
+	}
+
+	/// Registers a new webhook, generating a secret for it
+	///
+	/// # Errors
+	/// [`RingError::UniqueRowAlreadyPresent`] if a webhook with that url already exists
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn add_webhook(&self, url: &str, admin_id: i64) -> Result<(), RingError> {
+		let secret = generate_token();
+		match sqlx::query(
+			"INSERT INTO webhooks (url, secret, date_added, admin_id) VALUES ($1, $2, $3, $4)",
+		)
+		.bind(url)
+		.bind(secret)
+		.bind(sql::today())
+		.bind(admin_id)
+		.execute(&self.database)
+		.await
+		{
+			Ok(_query_outcome) => {
+				info!("Added webhook {}", url);
+				Ok(())
+			}
+			Err(ref e) if sql::is_unique_violation(e) => {
+				info!("Tried to add webhook {} but it was already present", url);
+				Err(RingError::UniqueRowAlreadyPresent(url.to_owned()))
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in add_webhook: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Removes a webhook, along with its delivery log
+	///
+	/// # Errors
+	/// [`RingError::RowNotFound`] if there is no webhook with the given id
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn remove_webhook(&self, webhook_id: i64) -> Result<(), RingError> {
+		match sqlx::query("DELETE FROM webhooks WHERE id = $1")
+			.bind(webhook_id)
+			.execute(&self.database)
+			.await
+		{
+			Ok(query_outcome) if query_outcome.rows_affected() == 0 => Err(
+				RingError::RowNotFound(format!("Webhook with id {webhook_id}")),
+			),
+			Ok(_query_outcome) => {
+				info!("Removed webhook with id {}", webhook_id);
+				Ok(())
+			}
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in remove_webhook: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets all the registered webhooks
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_list_webhooks(&self) -> Result<Vec<Webhook>, RingError> {
+		match sqlx::query_as("SELECT * FROM webhooks ORDER BY id")
+			.fetch_all(&self.database)
+			.await
+		{
+			Ok(webhooks) => Ok(webhooks),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_webhooks: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Gets the most recent webhook deliveries, newest first
+	///
+	/// # Errors
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	#[instrument]
+	pub async fn get_list_webhook_deliveries(
+		&self,
+		limit: i64,
+	) -> Result<Vec<WebhookDelivery>, RingError> {
+		match sqlx::query_as(
+			"SELECT d.id, w.url AS webhook_url, d.event, d.payload, d.status, d.attempts,
+			d.date_created, d.last_attempt_at, d.last_response
+			FROM webhook_deliveries AS d
+			INNER JOIN webhooks AS w ON d.webhook_id = w.id
+			ORDER BY d.id DESC LIMIT $1",
+		)
+		.bind(limit)
+		.fetch_all(&self.database)
+		.await
+		{
+			Ok(deliveries) => Ok(deliveries),
+			Err(e) => {
+				error!(
+					"There was an unrecoverable database error in get_list_webhook_deliveries: {}",
+					e
+				);
+				Err(RingError::UnrecoverableDatabaseError(e))
+			}
+		}
+	}
+
+	/// Sends all deliveries that are due, rescheduling failed ones with an exponential backoff
+	///
+	/// Returns the number of deliveries attempted
+	///
+	/// # Errors
+	/// [`RingError::TaskJoin`] if the task sending a delivery panics
+	/// [`RingError::UnrecoverableDatabaseError`] if there is a problem with the database
+	pub async fn deliver_pending_webhooks(&self) -> Result<usize, RingError> {
+		let due: Vec<PendingDelivery> = sqlx::query_as(
+			"SELECT d.id, d.event, d.payload, d.attempts, w.url, w.secret
+			FROM webhook_deliveries AS d
+			INNER JOIN webhooks AS w ON d.webhook_id = w.id
+			WHERE d.status = 'pending' AND d.next_attempt_at <= $1
+			ORDER BY d.id LIMIT $2",
+		)
+		.bind(sql::now())
+		.bind(BATCH_SIZE)
+		.fetch_all(&self.database)
+		.await?;
+
+		let attempted = due.len();
+		for delivery in due {
+			let (delivery, (succeeded, response)) = tokio::task::spawn_blocking(move || {
+				let outcome = send_delivery(&delivery);
+				(delivery, outcome)
+			})
+			.await?;
+
+			let attempts = delivery.attempts + 1;
+			let status = if succeeded {
+				debug!("Delivered webhook {} to {}", delivery.id, delivery.url);
+				"delivered"
+			} else if attempts >= MAX_ATTEMPTS {
+				warn!(
+					"Giving up on webhook delivery {} to {}: {}",
+					delivery.id, delivery.url, response
+				);
+				"failed"
+			} else {
+				info!(
+					"Webhook delivery {} to {} failed, will retry: {}",
+					delivery.id, delivery.url, response
+				);
+				"pending"
+			};
+			// 30 seconds, doubling with each attempt
+			let next_attempt = sql::seconds_from_now(30 << attempts.clamp(0, 16));
+			sqlx::query(
+				"UPDATE webhook_deliveries
+				SET status = $1, attempts = $2, last_attempt_at = $3, next_attempt_at = $4,
+				last_response = $5 WHERE id = $6",
+			)
+			.bind(status)
+			.bind(attempts)
+			.bind(sql::now())
+			.bind(next_attempt)
+			.bind(response)
+			.bind(delivery.id)
+			.execute(&self.database)
+			.await?;
+		}
+		Ok(attempted)
+	}
+
+	/// Works through the webhook queue forever, checking for due deliveries every `interval`
+	pub async fn run_webhook_worker(self, interval: Duration) {
+		let mut ticker = tokio::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match self.deliver_pending_webhooks().await {
+				Ok(0) => {}
+				Ok(attempted) => debug!("Attempted {} webhook deliveries", attempted),
+				Err(e) => error!("Error when delivering webhooks: {e}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::sign_payload;
+
+	#[test]
+	fn signs_with_hmac_sha256() {
+		// Test case 2 from RFC 4231
+		assert_eq!(
+			sign_payload("Jefe", "what do ya want for nothing?"),
+			"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+		);
+	}
+}
@@ -0,0 +1,60 @@
+//! This module handles starting the background work a running webring needs
+//!
+//! Emails and webhooks are queued and sent later, linked rings, members' feeds and their buttons
+//! are fetched again now and then, and navigation is counted in memory until it's written to the database.
+//! Nothing is sent, fetched or written until [`RingState::spawn_workers`] is called.
+
+use std::time::Duration;
+
+use super::RingState;
+
+/// How often each background worker runs
+#[derive(Debug, Clone)]
+pub struct WorkerIntervals {
+	/// How often the webhook queue is checked for deliveries that are due
+	pub webhooks: Duration,
+	/// How often the email queue is checked for emails that are due
+	pub emails: Duration,
+	/// How often the manifests of linked rings are fetched again
+	pub peer_refresh: Duration,
+	/// How often members' feeds are fetched for the planet
+	pub planet_refresh: Duration,
+	/// How often members' buttons are checked for ones that are new, changed or a day old
+	pub button_refresh: Duration,
+	/// How often the navigation counted in memory is written to the database
+	pub navigation_flush: Duration,
+}
+
+impl Default for WorkerIntervals {
+	fn default() -> Self {
+		Self {
+			webhooks: Duration::from_secs(10),
+			emails: Duration::from_secs(10),
+			peer_refresh: Duration::from_hours(1),
+			planet_refresh: Duration::from_hours(1),
+			button_refresh: Duration::from_mins(1),
+			navigation_flush: Duration::from_secs(10),
+		}
+	}
+}
+
+impl RingState {
+	/// Starts every background worker, which run until the runtime shuts down
+	///
+	/// Navigation counted since the last flush is lost unless [`RingState::flush_navigation`] is
+	/// called before shutting down.
+	///
+	/// # Panics
+	/// Panics if it isn't called from within a Tokio runtime
+	pub fn spawn_workers(&self, intervals: &WorkerIntervals) {
+		tokio::spawn(self.clone().run_webhook_worker(intervals.webhooks));
+		tokio::spawn(self.clone().run_email_worker(intervals.emails));
+		tokio::spawn(self.clone().run_peer_refresh_worker(intervals.peer_refresh));
+		tokio::spawn(self.clone().run_planet_worker(intervals.planet_refresh));
+		tokio::spawn(self.clone().run_button_worker(intervals.button_refresh));
+		tokio::spawn(
+			self.clone()
+				.run_navigation_worker(intervals.navigation_flush),
+		);
+	}
+}
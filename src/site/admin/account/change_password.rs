@@ -5,24 +5,30 @@ use axum::{
 	response::{Html, IntoResponse, Redirect},
 };
 use axum_messages::{Message, Messages};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
 
-use crate::ring::{RingError, auth::AuthSession};
+use crate::ring::{RingError, auth::AuthSession, rings::Ring};
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "admin/account/change-password.html")]
 pub struct ChangePasswordTemplate {
+	ring: Ring,
 	messages: Vec<Message>,
 }
 
-pub(super) async fn get(messages: Messages) -> impl IntoResponse {
+themed!(ChangePasswordTemplate, "admin/account/change-password.html");
+
+pub(super) async fn get(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
 	match {
 		ChangePasswordTemplate {
+			ring: state.ring().clone(),
 			messages: messages.into_iter().collect(),
 		}
 	}
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered change password html");
@@ -51,6 +57,7 @@ pub(super) async fn post(
 		error!("Tried to alter an admin account when not logged in");
 		return StatusCode::UNAUTHORIZED.into_response();
 	};
+	let login_path = auth_session.backend.ring().instance_path("/login");
 	if input.new != input.confirm_new {
 		messages.error("New password and Confirmed new password do not match");
 		return Redirect::to("./change-password").into_response();
@@ -63,9 +70,9 @@ pub(super) async fn post(
 		Ok(()) => {
 			info!("Successfully changed admin password");
 			// TODO: Make this an actual page
-			Html(
-				"Your password has successfully been changed. Please <a href=\"/login\">login</a> again.",
-			)
+			Html(format!(
+				"Your password has successfully been changed. Please <a href=\"{login_path}\">login</a> again."
+			))
 			.into_response()
 		}
 		Err(RingError::UnauthorisedAdmin) => {
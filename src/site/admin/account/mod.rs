@@ -1,20 +1,23 @@
 use askama::Template;
 use axum::{
 	Router,
-	extract::{Query, State},
+	extract::Query,
 	http::StatusCode,
 	response::{Html, IntoResponse, Redirect},
 	routing::{get, post},
 };
 use axum_login::AuthUser;
 use axum_messages::Messages;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, warn};
 
 use crate::ring::{
 	RingError, RingState,
 	auth::{Admin, AuthSession},
+	rings::Ring,
 };
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
 
 mod change_password;
 
@@ -27,15 +30,23 @@ pub(super) fn router(state: RingState) -> Router {
 		.with_state(state)
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "admin/account.html")]
 pub struct AdminAccountViewTemplate {
+	ring: Ring,
 	admin: Admin,
 	delete_button_pressed: bool,
 }
 
-async fn view(auth_session: AuthSession, Query(params): Query<DeleteParams>) -> impl IntoResponse {
+themed!(AdminAccountViewTemplate, "admin/account.html");
+
+async fn view(
+	auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+	Query(params): Query<DeleteParams>,
+) -> impl IntoResponse {
 	match (AdminAccountViewTemplate {
+		ring: state.ring().clone(),
 		admin: if let Some(admin) = auth_session.user {
 			admin
 		} else {
@@ -51,7 +62,7 @@ async fn view(auth_session: AuthSession, Query(params): Query<DeleteParams>) ->
 			}
 		},
 	})
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered admin view HTML");
@@ -72,14 +83,14 @@ pub struct DeleteParams {
 
 async fn delete_account(
 	mut auth_session: AuthSession,
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
 	messages: Messages,
 	Query(params): Query<DeleteParams>,
 ) -> impl IntoResponse {
 	if params.delete_confirmed != Some("true".to_owned()) {
 		return (
 			[("content-length", "0")],
-			Redirect::to("/admin/account?delete_pressed=true"),
+			Redirect::to(&state.ring().path("/admin/account?delete_pressed=true")),
 		)
 			.into_response();
 	}
@@ -103,7 +114,11 @@ async fn delete_account(
 	match state.delete_admin(admin.id()).await {
 		Ok(()) => {
 			info!("Deleted admin {:?}", admin);
-			([("content-type", "0")], Redirect::to("/")).into_response()
+			(
+				[("content-type", "0")],
+				Redirect::to(&state.ring().home_path()),
+			)
+				.into_response()
 		}
 		Err(RingError::RowNotFound(_message)) => {
 			error!(
@@ -112,9 +127,7 @@ async fn delete_account(
 			);
 			StatusCode::INTERNAL_SERVER_ERROR.into_response()
 		}
-		Err(RingError::UnrecoverableDatabaseError(sqlx::Error::Database(ref e)))
-			if e.code().as_deref() == Some("1811") =>
-		{
+		Err(RingError::RowInUse(_admin)) => {
 			info!(
 				"Attempting to delete admin failed as admin has existing approved/denied sites: {:?}",
 				admin
@@ -129,7 +142,7 @@ async fn delete_account(
 			messages.error(
 				"You cannot delete this admin account as it has associated approvals/denials",
 			);
-			Redirect::to("/admin").into_response()
+			Redirect::to(&state.ring().path("/admin")).into_response()
 		}
 		Err(e) => {
 			error!("There was an error when trying to delete an admin: {e}");
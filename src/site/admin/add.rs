@@ -1,29 +1,37 @@
 use askama::Template;
 use axum::{
 	Form,
-	extract::State,
 	http::StatusCode,
 	response::{Html, IntoResponse, Redirect},
 };
 use axum_messages::{Message, Messages};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use tracing::{debug, error};
 
-use crate::ring::{RingError, RingState};
+use crate::ring::{RingError, auth::AuthSession, rings::Ring};
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "admin/add.html")]
 pub struct AdminAddTemplate {
+	ring: Ring,
 	messages: Vec<Message>,
+	/// Whether the new admin can be allowed to manage every ring
+	can_add_instance_admin: bool,
 }
 
+themed!(AdminAddTemplate, "admin/add.html");
+
 #[derive(Deserialize)]
 pub struct AddAdminForm {
 	username: String,
 	email: String,
 	password: String,
 	confirm_password: String,
+	/// Whether the new admin can only manage the current ring
+	ring_only: Option<String>,
 }
 
 impl Debug for AddAdminForm {
@@ -33,29 +41,40 @@ impl Debug for AddAdminForm {
 			.field("email", &self.email)
 			.field("password", &"redacted")
 			.field("confirm_password", &"redacted")
+			.field("ring_only", &self.ring_only)
 			.finish()
 	}
 }
 
 pub(super) async fn post(
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
+	auth_session: AuthSession,
 	messages: Messages,
 	Form(data): Form<AddAdminForm>,
 ) -> impl IntoResponse {
-	static PATH: &str = "/admin/add";
+	let path = state.ring().path("/admin/add");
+	let Some(admin) = auth_session.user else {
+		return StatusCode::UNAUTHORIZED.into_response();
+	};
+	// Admins of a single ring can only add admins for that ring
+	let ring_id = if data.ring_only.is_some() || !admin.is_instance_admin() {
+		Some(state.ring().id)
+	} else {
+		None
+	};
 	if data.password != data.confirm_password {
 		debug!("Passwords don't match");
 		messages.error("Passwords do not match");
-		return Redirect::to(PATH).into_response();
+		return Redirect::to(&path).into_response();
 	}
 	if let Err(e) = state
-		.add_admin(data.username.clone(), data.email, data.password)
+		.add_admin(data.username.clone(), data.email, data.password, ring_id)
 		.await
 	{
 		match e {
 			RingError::UniqueRowAlreadyPresent(values) => {
 				messages.error(format!("Email or username is already taken: {values}"));
-				Redirect::to(PATH).into_response()
+				Redirect::to(&path).into_response()
 			}
 			RingError::TaskJoin(e) => {
 				error!("Task join error when adding admin: {}", e);
@@ -75,17 +94,25 @@ pub(super) async fn post(
 		}
 	} else {
 		messages.info(format!("Added new admin {}", data.username));
-		Redirect::to(PATH).into_response()
+		Redirect::to(&path).into_response()
 	}
 }
 
-pub(super) async fn get(messages: Messages) -> impl IntoResponse {
+pub(super) async fn get(
+	messages: Messages,
+	auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
 	match {
 		AdminAddTemplate {
+			ring: state.ring().clone(),
 			messages: messages.into_iter().collect(),
+			can_add_instance_admin: auth_session
+				.user
+				.is_some_and(|admin| admin.is_instance_admin()),
 		}
 	}
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered admin add html");
@@ -1,6 +1,5 @@
 use axum::{
 	Form,
-	extract::State,
 	http::StatusCode,
 	response::{IntoResponse, Redirect},
 };
@@ -9,7 +8,8 @@ use axum_messages::Messages;
 use serde::Deserialize;
 use tracing::error;
 
-use crate::ring::{RingState, auth::AuthSession};
+use crate::ring::{RingError, auth::AuthSession};
+use crate::site::CurrentRing;
 
 #[derive(Debug, Deserialize)]
 pub struct ApproveSiteForm {
@@ -19,16 +19,21 @@ pub struct ApproveSiteForm {
 pub(super) async fn post(
 	messages: Messages,
 	auth_session: AuthSession,
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
 	Form(form): Form<ApproveSiteForm>,
 ) -> impl IntoResponse {
 	let Some(admin) = auth_session.user else {
 		return StatusCode::UNAUTHORIZED.into_response();
 	};
-	if let Err(e) = state.approve_site(&form.url, admin.id()).await {
-		error!("Error when trying to approve site: {e}");
-		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-	}
-	messages.info(format!("Site {} approved", form.url));
-	Redirect::to("/admin/view").into_response()
+	match state.approve_site(&form.url, admin.id()).await {
+		Ok(()) => messages.info(format!("Site {} approved", form.url)),
+		Err(RingError::RowNotFound(_)) => {
+			messages.error(format!("There is no site {} to approve", form.url))
+		}
+		Err(e) => {
+			error!("Error when trying to approve site: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/view")).into_response()
 }
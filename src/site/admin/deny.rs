@@ -1,6 +1,5 @@
 use axum::{
 	Form,
-	extract::State,
 	http::StatusCode,
 	response::{IntoResponse, Redirect},
 };
@@ -9,7 +8,8 @@ use axum_messages::Messages;
 use serde::Deserialize;
 use tracing::error;
 
-use crate::ring::{RingState, auth::AuthSession};
+use crate::ring::{RingError, auth::AuthSession};
+use crate::site::CurrentRing;
 
 #[derive(Debug, Deserialize)]
 pub struct DenySiteForm {
@@ -20,19 +20,24 @@ pub struct DenySiteForm {
 pub(super) async fn post(
 	messages: Messages,
 	auth_session: AuthSession,
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
 	Form(form): Form<DenySiteForm>,
 ) -> impl IntoResponse {
 	let Some(admin) = auth_session.user else {
 		return StatusCode::UNAUTHORIZED.into_response();
 	};
-	if let Err(e) = state.deny_site(&form.url, &form.reason, admin.id()).await {
-		error!("Error when trying to deny site: {e}");
-		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-	}
-	messages.info(format!(
-		"Site {} denied with reason {}",
-		form.url, form.reason
-	));
-	Redirect::to("/admin/view").into_response()
+	match state.deny_site(&form.url, &form.reason, admin.id()).await {
+		Ok(()) => messages.info(format!(
+			"Site {} denied with reason {}",
+			form.url, form.reason
+		)),
+		Err(RingError::RowNotFound(_)) => {
+			messages.error(format!("There is no site {} to deny", form.url))
+		}
+		Err(e) => {
+			error!("Error when trying to deny site: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/view")).into_response()
 }
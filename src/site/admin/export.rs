@@ -0,0 +1,39 @@
+use axum::{
+	http::{StatusCode, header},
+	response::IntoResponse,
+};
+use tracing::{debug, error};
+
+use crate::site::CurrentRing;
+
+/// Downloads every ring, site, admin and webhook on the instance as JSON
+pub(super) async fn get(CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	let export = match state.export().await {
+		Ok(export) => export,
+		Err(e) => {
+			error!("Error when exporting the instance: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	match serde_json::to_string_pretty(&export) {
+		Ok(json) => {
+			debug!("Successfully serialised the export");
+			let filename = format!(
+				"attachment; filename=\"webringer-{}.json\"",
+				export.exported_at.replace([' ', ':'], "-")
+			);
+			(
+				[
+					(header::CONTENT_TYPE, "application/json".to_owned()),
+					(header::CONTENT_DISPOSITION, filename),
+				],
+				json,
+			)
+				.into_response()
+		}
+		Err(e) => {
+			error!("Error when serialising the export: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
@@ -0,0 +1,170 @@
+use askama::Template;
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_login::AuthUser;
+use axum_messages::{Message, Messages};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::ring::{
+	RingError,
+	auth::AuthSession,
+	importer::{ImportFormat, ImportPreview, ImportStatus, ImportedSite, parse_import},
+	rings::Ring,
+};
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
+
+#[derive(Template, Serialize)]
+#[template(path = "admin/import.html")]
+pub struct AdminImportTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+	input: String,
+	/// The format picked, or none to work it out from the input
+	format: Option<ImportFormat>,
+	formats: [ImportFormat; 4],
+	/// Why the input couldn't be read
+	problem: Option<String>,
+	preview: Option<Vec<ImportPreview>>,
+	new_sites: usize,
+}
+
+themed!(AdminImportTemplate, "admin/import.html");
+
+impl AdminImportTemplate {
+	fn render_response(self) -> Response {
+		match self.render_themed() {
+			Ok(s) => {
+				debug!("Successfully rendered admin import html");
+				Html(s).into_response()
+			}
+			Err(e) => {
+				error!("Error when rendering admin import html: {}", e);
+				StatusCode::INTERNAL_SERVER_ERROR.into_response()
+			}
+		}
+	}
+}
+
+const FORMATS: [ImportFormat; 4] = [
+	ImportFormat::Urls,
+	ImportFormat::Json,
+	ImportFormat::Csv,
+	ImportFormat::Html,
+];
+
+pub(super) async fn get(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	AdminImportTemplate {
+		ring: state.ring().clone(),
+		messages: messages.into_iter().collect(),
+		input: String::new(),
+		format: None,
+		formats: FORMATS,
+		problem: None,
+		preview: None,
+		new_sites: 0,
+	}
+	.render_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportForm {
+	input: String,
+	/// Empty to work the format out from the input
+	format: String,
+	/// Whether to approve the sites or leave them waiting, only given when importing
+	status: Option<String>,
+}
+
+impl ImportForm {
+	fn format(&self) -> Result<Option<ImportFormat>, String> {
+		if self.format.is_empty() {
+			Ok(None)
+		} else {
+			self.format.parse().map(Some)
+		}
+	}
+
+	fn parse(&self) -> Result<Vec<ImportedSite>, String> {
+		let format = self
+			.format()?
+			.unwrap_or_else(|| ImportFormat::detect(&self.input));
+		match parse_import(format, &self.input) {
+			Ok(sites) => Ok(sites),
+			Err(RingError::InvalidInput(problem)) => Err(problem),
+			Err(e) => Err(e.to_string()),
+		}
+	}
+}
+
+pub(super) async fn preview(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<ImportForm>,
+) -> impl IntoResponse {
+	let mut template = AdminImportTemplate {
+		ring: state.ring().clone(),
+		messages: messages.into_iter().collect(),
+		input: String::new(),
+		format: form.format().ok().flatten(),
+		formats: FORMATS,
+		problem: None,
+		preview: None,
+		new_sites: 0,
+	};
+	match form.parse() {
+		Ok(sites) => match state.preview_import(sites).await {
+			Ok(preview) => {
+				template.new_sites = preview
+					.iter()
+					.filter(|entry| entry.status == ImportStatus::New)
+					.count();
+				template.preview = Some(preview);
+			}
+			Err(e) => {
+				error!("Error when previewing an import: {e}");
+				return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+			}
+		},
+		Err(problem) => template.problem = Some(problem),
+	}
+	template.input = form.input;
+	template.render_response()
+}
+
+pub(super) async fn post(
+	messages: Messages,
+	auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<ImportForm>,
+) -> impl IntoResponse {
+	let Some(admin) = auth_session.user else {
+		return StatusCode::UNAUTHORIZED.into_response();
+	};
+	let sites = match form.parse() {
+		Ok(sites) => sites,
+		Err(problem) => {
+			messages.error(problem);
+			return Redirect::to(&state.ring().path("/admin/import")).into_response();
+		}
+	};
+	let approve = form.status.as_deref() != Some("pending");
+	match state
+		.import_sites(sites, admin.id(), &admin.email, approve)
+		.await
+	{
+		Ok(added) if approve => messages.info(format!("Imported and approved {added} sites")),
+		Ok(added) => {
+			messages.info(format!("Imported {added} sites, which are waiting for approval"))
+		}
+		Err(e) => {
+			error!("Error when importing sites: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/view")).into_response()
+}
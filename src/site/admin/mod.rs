@@ -1,23 +1,36 @@
 use askama::Template;
 use axum::{
 	Router,
-	extract::State,
+	extract::{OriginalUri, Request},
 	http::StatusCode,
-	response::{Html, IntoResponse, Redirect},
+	middleware::{self, Next},
+	response::{Html, IntoResponse, Redirect, Response},
 	routing::{get, post},
 };
-use axum_login::login_required;
 use axum_messages::{Message, Messages};
+use serde::Serialize;
 use tracing::{debug, error, info, warn};
 
+use super::CurrentRing;
 use crate::ring::{
-	ApprovedSite, DeniedSite, RingError, RingState, UnapprovedSite, auth::AuthSession,
+	ApprovedSite, DeniedSite, RingError, RingState, UnapprovedSite, auth::AuthSession, rings::Ring,
 };
+use crate::theme::{ThemedTemplate, themed};
 
 mod account;
 mod add;
 mod approve;
 mod deny;
+mod export;
+mod import;
+mod move_site;
+mod peers;
+mod planet;
+mod resend_verification;
+mod rings;
+mod stats;
+mod tags;
+mod webhooks;
 
 pub fn router(state: RingState) -> Router {
 	Router::new()
@@ -25,27 +38,121 @@ pub fn router(state: RingState) -> Router {
 		.route("/view", get(view))
 		.route("/deny", post(deny::post))
 		.route("/approve", post(approve::post))
+		.route("/resend-verification", post(resend_verification::post))
+		.route("/move", post(move_site::post))
+		.route("/tags", post(tags::post))
 		.route("/add", get(add::get))
 		.route("/add", post(add::post))
+		.route("/ring", post(rings::update))
+		.route("/peers", get(peers::get))
+		.route("/peers/add", post(peers::add))
+		.route("/peers/delete", post(peers::delete))
+		.route("/peers/refresh", post(peers::refresh))
+		.route("/planet", get(planet::get))
+		.route("/planet/exclude", post(planet::exclude))
+		.route("/stats", get(stats::get))
+		.route("/import", get(import::get))
+		.route("/import", post(import::post))
+		.route("/import/preview", post(import::preview))
+		.merge(
+			Router::new()
+				.route("/webhooks", get(webhooks::get))
+				.route("/webhooks/add", post(webhooks::add))
+				.route("/webhooks/delete", post(webhooks::delete))
+				.route("/rings", get(rings::get))
+				.route("/rings/add", post(rings::add))
+				.route("/export", get(export::get))
+				.route_layer(middleware::from_fn(require_instance_admin)),
+		)
 		.route("/logout", post(logout))
 		.with_state(state.clone())
-		.nest("/account", account::router(state))
-		.route_layer(login_required!(RingState, login_url = "/login"))
+		.nest("/account", account::router(state.clone()))
+		.route_layer(middleware::from_fn_with_state(state.clone(), require_ring_admin))
+		.route_layer(middleware::from_fn_with_state(state, require_login))
 }
 
-#[derive(Template)]
+/// Sends visitors who aren't logged in to the login page, to come back here once they are
+async fn require_login(
+	auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+	OriginalUri(original_uri): OriginalUri,
+	request: Request,
+	next: Next,
+) -> Response {
+	if auth_session.user.is_some() {
+		return next.run(request).await;
+	}
+	let query =
+		serde_urlencoded::to_string([("next", original_uri.to_string())]).unwrap_or_default();
+	let login_url = format!("{}?{query}", state.ring().instance_path("/login"));
+	Redirect::temporary(&login_url).into_response()
+}
+
+/// Stops admins of one ring from managing any other
+async fn require_ring_admin(
+	auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+	request: Request,
+	next: Next,
+) -> Response {
+	match auth_session.user {
+		Some(admin) if admin.can_manage(state.ring()) => next.run(request).await,
+		Some(admin) => {
+			info!(
+				"Admin {:?} tried to manage ring {} which they aren't an admin of",
+				admin,
+				state.ring().slug
+			);
+			StatusCode::FORBIDDEN.into_response()
+		}
+		None => StatusCode::UNAUTHORIZED.into_response(),
+	}
+}
+
+/// Only lets admins who can manage every ring through, for settings that affect the whole instance
+async fn require_instance_admin(
+	auth_session: AuthSession,
+	request: Request,
+	next: Next,
+) -> Response {
+	match auth_session.user {
+		Some(admin) if admin.is_instance_admin() => next.run(request).await,
+		Some(admin) => {
+			info!(
+				"Admin {:?} tried to change instance settings without being an instance admin",
+				admin
+			);
+			StatusCode::FORBIDDEN.into_response()
+		}
+		None => StatusCode::UNAUTHORIZED.into_response(),
+	}
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "admin/landing_page.html")]
 pub struct AdminLandingPageTemplate {
+	ring: Ring,
 	messages: Vec<Message>,
+	instance_admin: bool,
 }
 
-async fn landing_page(messages: Messages) -> impl IntoResponse {
+themed!(AdminLandingPageTemplate, "admin/landing_page.html");
+
+async fn landing_page(
+	messages: Messages,
+	auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
 	match {
 		AdminLandingPageTemplate {
+			ring: state.ring().clone(),
 			messages: messages.into_iter().collect(),
+			instance_admin: auth_session
+				.user
+				.is_some_and(|admin| admin.is_instance_admin()),
 		}
 	}
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered admin landing page html");
@@ -73,17 +180,21 @@ where
 	}
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "admin/sites_view.html")]
 pub struct AdminViewSitesTemplate {
+	ring: Ring,
 	messages: Vec<Message>,
 	unapproved_sites: Vec<UnapprovedSite>,
 	approved_sites: Vec<ApprovedSite>,
 	denied_sites: Vec<DeniedSite>,
 }
 
-async fn view(messages: Messages, State(state): State<RingState>) -> impl IntoResponse {
+themed!(AdminViewSitesTemplate, "admin/sites_view.html");
+
+async fn view(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
 	match (AdminViewSitesTemplate {
+		ring: state.ring().clone(),
 		messages: messages.into_iter().collect(),
 		unapproved_sites: {
 			match handle_async(state.get_list_unapproved()).await {
@@ -92,7 +203,7 @@ async fn view(messages: Messages, State(state): State<RingState>) -> impl IntoRe
 			}
 		},
 		approved_sites: {
-			match handle_async(state.get_list_approved()).await {
+			match handle_async(state.get_list_approved(None)).await {
 				Some(sites) => sites,
 				None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
 			}
@@ -104,7 +215,7 @@ async fn view(messages: Messages, State(state): State<RingState>) -> impl IntoRe
 			}
 		},
 	})
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered admin list view html");
@@ -117,11 +228,17 @@ async fn view(messages: Messages, State(state): State<RingState>) -> impl IntoRe
 	}
 }
 
-async fn logout(mut auth_session: AuthSession) -> impl IntoResponse {
+async fn logout(
+	mut auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
 	match auth_session.logout().await {
 		Ok(Some(admin)) => info!("Admin {:?} logged out", admin),
 		Ok(None) => warn!("Tried to logout but there was no active user"),
 		Err(e) => error!("Error when logging out admin: {}", e),
 	}
-	([("content-length", "0")], Redirect::to("/"))
+	(
+		[("content-length", "0")],
+		Redirect::to(&state.ring().home_path()),
+	)
 }
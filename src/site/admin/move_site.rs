@@ -0,0 +1,44 @@
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{IntoResponse, Redirect},
+};
+use axum_messages::Messages;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::ring::RingError;
+use crate::site::CurrentRing;
+
+#[derive(Debug, Deserialize)]
+pub struct MoveSiteForm {
+	site_id: i64,
+	url: String,
+	keep_old_url: Option<String>,
+}
+
+pub(super) async fn post(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<MoveSiteForm>,
+) -> impl IntoResponse {
+	let grace_period_days = form.keep_old_url.map(|_| crate::site::URL_GRACE_PERIOD_DAYS);
+	match state
+		.change_site_url(form.site_id, &form.url, grace_period_days)
+		.await
+	{
+		Ok(()) => messages.info(format!("Site moved to {}", form.url)),
+		Err(RingError::OwnershipNotVerified(problem)) => messages.error(format!(
+			"Could not move site to {}: {problem}. The owner needs to host the site's auth hash at the new url first",
+			form.url
+		)),
+		Err(RingError::UniqueRowAlreadyPresent(url)) => {
+			messages.error(format!("The site {url} has already been registered"))
+		}
+		Err(e) => {
+			error!("Error when trying to move site: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/view")).into_response()
+}
@@ -0,0 +1,110 @@
+use askama::Template;
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{Html, IntoResponse, Redirect},
+};
+use axum_messages::{Message, Messages};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::ring::{RingError, federation::PeerRing, rings::Ring};
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
+
+#[derive(Template, Serialize)]
+#[template(path = "admin/peers.html")]
+pub struct AdminPeersTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+	peers: Vec<PeerRing>,
+	/// Where this ring's own manifest is published, for other rings to link to
+	manifest_url: String,
+}
+
+themed!(AdminPeersTemplate, "admin/peers.html");
+
+pub(super) async fn get(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	match (AdminPeersTemplate {
+		ring: state.ring().clone(),
+		messages: messages.into_iter().collect(),
+		peers: match super::handle_async(state.get_list_peer_rings()).await {
+			Some(peers) => peers,
+			None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		},
+		manifest_url: state.manifest_url(),
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered admin peers html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering admin peers html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddPeerForm {
+	url: String,
+}
+
+pub(super) async fn add(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<AddPeerForm>,
+) -> impl IntoResponse {
+	match state.add_peer_ring(&form.url).await {
+		Ok(()) => messages.info(format!("Linked to the ring at {}", form.url)),
+		Err(RingError::Federation(problem)) => messages.error(problem),
+		Err(RingError::UniqueRowAlreadyPresent(url)) => {
+			messages.error(format!("This ring already links to {url}"))
+		}
+		Err(e) => {
+			error!("Error when trying to add peer ring: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/peers")).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PeerForm {
+	id: i64,
+}
+
+pub(super) async fn delete(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<PeerForm>,
+) -> impl IntoResponse {
+	match state.remove_peer_ring(form.id).await {
+		Ok(()) => messages.info("Peer ring removed"),
+		Err(RingError::RowNotFound(_query)) => messages.error("That peer ring no longer exists"),
+		Err(e) => {
+			error!("Error when trying to remove peer ring: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/peers")).into_response()
+}
+
+pub(super) async fn refresh(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<PeerForm>,
+) -> impl IntoResponse {
+	match state.refresh_peer_ring(form.id).await {
+		Ok(()) => messages.info("Peer ring refreshed"),
+		Err(RingError::Federation(problem)) => messages.error(problem),
+		Err(RingError::RowNotFound(_query)) => messages.error("That peer ring no longer exists"),
+		Err(e) => {
+			error!("Error when trying to refresh peer ring: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/peers")).into_response()
+}
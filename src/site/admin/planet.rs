@@ -0,0 +1,72 @@
+use askama::Template;
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{Html, IntoResponse, Redirect},
+};
+use axum_messages::{Message, Messages};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::ring::{RingError, planet::PlanetFeed, rings::Ring};
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
+
+#[derive(Template, Serialize)]
+#[template(path = "admin/planet.html")]
+pub struct AdminPlanetTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+	feeds: Vec<PlanetFeed>,
+}
+
+themed!(AdminPlanetTemplate, "admin/planet.html");
+
+pub(super) async fn get(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	match (AdminPlanetTemplate {
+		ring: state.ring().clone(),
+		messages: messages.into_iter().collect(),
+		feeds: match super::handle_async(state.get_list_planet_feeds()).await {
+			Some(feeds) => feeds,
+			None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		},
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered admin planet html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering admin planet html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExcludeFeedForm {
+	site_id: i64,
+	/// Whether to leave the feed out of the planet, rather than put it back in
+	excluded: bool,
+}
+
+pub(super) async fn exclude(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<ExcludeFeedForm>,
+) -> impl IntoResponse {
+	match state
+		.set_planet_feed_excluded(form.site_id, form.excluded)
+		.await
+	{
+		Ok(()) if form.excluded => messages.info("The feed has been left out of the planet"),
+		Ok(()) => messages.info("The feed has been put back in the planet"),
+		Err(RingError::RowNotFound(_query)) => messages.error("That site no longer exists"),
+		Err(e) => {
+			error!("Error when trying to exclude planet feed: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/planet")).into_response()
+}
@@ -0,0 +1,34 @@
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{IntoResponse, Redirect},
+};
+use axum_messages::Messages;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::ring::RingError;
+use crate::site::CurrentRing;
+
+#[derive(Debug, Deserialize)]
+pub struct ResendVerificationForm {
+	site_id: i64,
+}
+
+pub(super) async fn post(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<ResendVerificationForm>,
+) -> impl IntoResponse {
+	match state.resend_verification_email(form.site_id).await {
+		Ok(()) => messages.info("A new verification email is on its way"),
+		Err(RingError::InvalidInput(problem) | RingError::RowNotFound(problem)) => {
+			messages.error(problem)
+		}
+		Err(e) => {
+			error!("Error when resending a verification email: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/view")).into_response()
+}
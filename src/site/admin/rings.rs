@@ -0,0 +1,99 @@
+use askama::Template;
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{Html, IntoResponse, Redirect},
+};
+use axum_messages::{Message, Messages};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::ring::{RingError, rings::Ring};
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
+
+#[derive(Template, Serialize)]
+#[template(path = "admin/rings.html")]
+pub struct AdminRingsTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+	rings: Vec<Ring>,
+}
+
+themed!(AdminRingsTemplate, "admin/rings.html");
+
+pub(super) async fn get(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	match (AdminRingsTemplate {
+		ring: state.ring().clone(),
+		messages: messages.into_iter().collect(),
+		rings: match super::handle_async(state.get_list_rings()).await {
+			Some(rings) => rings,
+			None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		},
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered admin rings html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering admin rings html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddRingForm {
+	slug: String,
+	name: String,
+	description: Option<String>,
+}
+
+pub(super) async fn add(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<AddRingForm>,
+) -> impl IntoResponse {
+	match state
+		.add_ring(&form.slug, &form.name, form.description.as_deref())
+		.await
+	{
+		Ok(()) => messages.info(format!("Added ring {}", form.name)),
+		Err(RingError::InvalidInput(problem)) => messages.error(problem),
+		Err(RingError::UniqueRowAlreadyPresent(slug)) => {
+			messages.error(format!("There is already a ring called {slug}"))
+		}
+		Err(e) => {
+			error!("Error when trying to add ring: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/rings")).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRingForm {
+	name: String,
+	description: Option<String>,
+}
+
+pub(super) async fn update(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<UpdateRingForm>,
+) -> impl IntoResponse {
+	match state
+		.update_ring(&form.name, form.description.as_deref())
+		.await
+	{
+		Ok(()) => messages.info("Ring details updated"),
+		Err(RingError::InvalidInput(problem)) => messages.error(problem),
+		Err(e) => {
+			error!("Error when trying to update ring: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin")).into_response()
+}
@@ -0,0 +1,64 @@
+use askama::Template;
+use axum::{
+	extract::Query,
+	http::StatusCode,
+	response::{Html, IntoResponse},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::ring::{
+	rings::Ring,
+	stats::{DailyRingStats, MAX_STATS_DAYS, SiteStats},
+};
+use crate::site::{CurrentRing, ring::DEFAULT_STATS_DAYS};
+use crate::theme::{ThemedTemplate, themed};
+
+#[derive(Template, Serialize)]
+#[template(path = "admin/stats.html")]
+pub struct AdminStatsTemplate {
+	ring: Ring,
+	days: u32,
+	daily: Vec<DailyRingStats>,
+	sites: Vec<SiteStats>,
+}
+
+themed!(AdminStatsTemplate, "admin/stats.html");
+
+#[derive(Debug, Deserialize)]
+pub struct AdminStatsParams {
+	days: Option<u32>,
+}
+
+pub(super) async fn get(
+	Query(params): Query<AdminStatsParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let days = params
+		.days
+		.unwrap_or(DEFAULT_STATS_DAYS)
+		.clamp(1, MAX_STATS_DAYS);
+	match (AdminStatsTemplate {
+		ring: state.ring().clone(),
+		days,
+		daily: match super::handle_async(state.get_ring_daily_stats(days)).await {
+			Some(daily) => daily,
+			None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		},
+		sites: match super::handle_async(state.get_site_stats(days)).await {
+			Some(sites) => sites,
+			None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		},
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered admin stats html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering admin stats html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
@@ -0,0 +1,33 @@
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{IntoResponse, Redirect},
+};
+use axum_messages::Messages;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::ring::RingError;
+use crate::site::CurrentRing;
+
+#[derive(Debug, Deserialize)]
+pub struct SiteTagsForm {
+	site_id: i64,
+	tags: String,
+}
+
+pub(super) async fn post(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<SiteTagsForm>,
+) -> impl IntoResponse {
+	match state.set_site_tags(form.site_id, &form.tags).await {
+		Ok(()) => messages.info("Tags updated"),
+		Err(RingError::InvalidInput(problem)) => messages.error(problem),
+		Err(e) => {
+			error!("Error when trying to set site tags: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/view")).into_response()
+}
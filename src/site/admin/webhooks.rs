@@ -0,0 +1,111 @@
+use askama::Template;
+use axum::{
+	Form,
+	http::StatusCode,
+	response::{Html, IntoResponse, Redirect},
+};
+use axum_login::AuthUser;
+use axum_messages::{Message, Messages};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::ring::{
+	RingError,
+	auth::AuthSession,
+	rings::Ring,
+	webhooks::{Webhook, WebhookDelivery},
+};
+use crate::site::CurrentRing;
+use crate::theme::{ThemedTemplate, themed};
+
+/// How many deliveries are shown in the delivery log
+const DELIVERY_LOG_LENGTH: i64 = 100;
+
+#[derive(Template, Serialize)]
+#[template(path = "admin/webhooks.html")]
+pub struct AdminWebhooksTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+	webhooks: Vec<Webhook>,
+	deliveries: Vec<WebhookDelivery>,
+}
+
+themed!(AdminWebhooksTemplate, "admin/webhooks.html");
+
+pub(super) async fn get(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	match (AdminWebhooksTemplate {
+		ring: state.ring().clone(),
+		messages: messages.into_iter().collect(),
+		webhooks: match super::handle_async(state.get_list_webhooks()).await {
+			Some(webhooks) => webhooks,
+			None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		},
+		deliveries: match super::handle_async(
+			state.get_list_webhook_deliveries(DELIVERY_LOG_LENGTH),
+		)
+		.await
+		{
+			Some(deliveries) => deliveries,
+			None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		},
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered admin webhooks html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering admin webhooks html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddWebhookForm {
+	url: String,
+}
+
+pub(super) async fn add(
+	messages: Messages,
+	auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<AddWebhookForm>,
+) -> impl IntoResponse {
+	let Some(admin) = auth_session.user else {
+		return StatusCode::UNAUTHORIZED.into_response();
+	};
+	match state.add_webhook(&form.url, admin.id()).await {
+		Ok(()) => messages.info(format!("Added webhook {}", form.url)),
+		Err(RingError::UniqueRowAlreadyPresent(url)) => {
+			messages.error(format!("The webhook {url} has already been added"))
+		}
+		Err(e) => {
+			error!("Error when trying to add webhook: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/webhooks")).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteWebhookForm {
+	id: i64,
+}
+
+pub(super) async fn delete(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<DeleteWebhookForm>,
+) -> impl IntoResponse {
+	match state.remove_webhook(form.id).await {
+		Ok(()) => messages.info("Webhook removed"),
+		Err(RingError::RowNotFound(_query)) => messages.error("That webhook no longer exists"),
+		Err(e) => {
+			error!("Error when trying to remove webhook: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/admin/webhooks")).into_response()
+}
@@ -0,0 +1,149 @@
+//! Exports of the member list, for subscribing to every member at once or mirroring the list
+
+use askama::Template;
+use axum::{
+	Json,
+	extract::Query,
+	http::{StatusCode, header},
+	response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, instrument};
+
+use super::CurrentRing;
+use crate::ring::{directory::PublicSite, rings::Ring, tags::normalise_tag};
+use crate::theme::{ThemedTemplate, themed};
+
+#[derive(Debug, Deserialize)]
+pub struct DirectoryParams {
+	/// Only export sites with this tag
+	tag: Option<String>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "list.opml", escape = "html")]
+pub struct OpmlTemplate {
+	ring: Ring,
+	home_url: String,
+	/// Only the sites that have a feed
+	sites: Vec<PublicSite>,
+}
+
+themed!(OpmlTemplate, "list.opml");
+
+#[instrument]
+pub async fn opml(
+	Query(params): Query<DirectoryParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let tag = normalise_tag(params.tag.as_deref());
+	let directory = match state.get_directory(tag.as_deref()).await {
+		Ok(directory) => directory,
+		Err(e) => {
+			error!("Error when getting the member directory: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	match (OpmlTemplate {
+		ring: state.ring().clone(),
+		home_url: directory.home_url,
+		sites: directory
+			.sites
+			.into_iter()
+			.filter(|site| site.profile.feed_url.is_some())
+			.collect(),
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered list opml");
+			([(header::CONTENT_TYPE, "text/x-opml; charset=utf-8")], s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering list opml: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[instrument]
+pub async fn json(
+	Query(params): Query<DirectoryParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let tag = normalise_tag(params.tag.as_deref());
+	match state.get_directory(tag.as_deref()).await {
+		Ok(directory) => Json(directory).into_response(),
+		Err(e) => {
+			error!("Error when getting the member directory: {e}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[instrument]
+pub async fn csv(
+	Query(params): Query<DirectoryParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let tag = normalise_tag(params.tag.as_deref());
+	match state.get_directory(tag.as_deref()).await {
+		Ok(directory) => (
+			[(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+			directory.to_csv(),
+		)
+			.into_response(),
+		Err(e) => {
+			error!("Error when getting the member directory: {e}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use askama::Template;
+
+	use super::OpmlTemplate;
+	use crate::ring::{SiteProfile, directory::PublicSite, rings::Ring};
+
+	#[test]
+	fn escapes_opml() {
+		let title = "Tom & Jerry's \"<blog>\"";
+		let opml = OpmlTemplate {
+			ring: Ring {
+				name: "A & B".to_owned(),
+				..Ring::default()
+			},
+			home_url: "https://ring.example/".to_owned(),
+			sites: vec![PublicSite {
+				id: 1,
+				url: "https://a.example/?a=1&b=2".to_owned(),
+				profile: SiteProfile {
+					title: Some(title.to_owned()),
+					feed_url: Some("https://a.example/feed?format=rss&full=1".to_owned()),
+					..SiteProfile::default()
+				},
+				tags: Vec::new(),
+				date_added: "2025-05-09".to_owned(),
+				profile_url: "https://ring.example/members/1".to_owned(),
+			}],
+		}
+		.render()
+		.unwrap();
+		let document = roxmltree::Document::parse(&opml).unwrap();
+		let outline = document
+			.descendants()
+			.find(|node| node.attribute("type") == Some("rss"))
+			.unwrap();
+		assert_eq!(outline.attribute("text"), Some(title));
+		assert_eq!(
+			outline.attribute("xmlUrl"),
+			Some("https://a.example/feed?format=rss&full=1")
+		);
+		assert_eq!(
+			outline.attribute("htmlUrl"),
+			Some("https://a.example/?a=1&b=2")
+		);
+	}
+}
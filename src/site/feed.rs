@@ -0,0 +1,141 @@
+//! The Atom and RSS feeds of sites joining and leaving the webring
+
+use askama::Template;
+use axum::{
+	http::{HeaderMap, StatusCode, header},
+	response::IntoResponse,
+};
+use sha2::{Digest, Sha256};
+use serde::Serialize;
+use tracing::{debug, error, instrument};
+
+use super::CurrentRing;
+use crate::ring::{
+	RingState,
+	feed::{FEED_EPOCH, FeedEntry, http_date, rfc3339},
+	rings::Ring,
+};
+use crate::theme::{ThemedTemplate, themed};
+
+/// How many entries are in the feeds
+const FEED_LENGTH: i64 = 50;
+/// How long feed readers and proxies can cache the feeds for, in seconds
+const FEED_MAX_AGE: u32 = 900;
+
+#[derive(Template, Serialize)]
+#[template(path = "feed/atom.xml")]
+pub struct AtomFeedTemplate {
+	ring: Ring,
+	entries: Vec<FeedEntry>,
+	home_url: String,
+	feed_url: String,
+	members_url: String,
+	updated: String,
+}
+
+themed!(AtomFeedTemplate, "feed/atom.xml");
+
+#[derive(Template, Serialize)]
+#[template(path = "feed/rss.xml")]
+pub struct RssFeedTemplate {
+	ring: Ring,
+	entries: Vec<FeedEntry>,
+	home_url: String,
+	feed_url: String,
+	members_url: String,
+	updated: String,
+}
+
+themed!(RssFeedTemplate, "feed/rss.xml");
+
+#[instrument]
+pub async fn atom(headers: HeaderMap, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	let Some((entries, updated)) = get_entries(&state).await else {
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	};
+	let template = AtomFeedTemplate {
+		ring: state.ring().clone(),
+		entries,
+		home_url: state.home_url(),
+		feed_url: state.url("/feed.atom"),
+		members_url: state.url("/members"),
+		updated: rfc3339(&updated),
+	};
+	feed_response(&headers, &template, "application/atom+xml", &updated)
+}
+
+#[instrument]
+pub async fn rss(headers: HeaderMap, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	let Some((entries, updated)) = get_entries(&state).await else {
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	};
+	let template = RssFeedTemplate {
+		ring: state.ring().clone(),
+		entries,
+		home_url: state.home_url(),
+		feed_url: state.url("/feed.rss"),
+		members_url: state.url("/members"),
+		updated: http_date(&updated),
+	};
+	feed_response(&headers, &template, "application/rss+xml", &updated)
+}
+
+/// Gets the feed's entries and when the newest of them happened
+async fn get_entries(state: &RingState) -> Option<(Vec<FeedEntry>, String)> {
+	match state.get_membership_feed(FEED_LENGTH).await {
+		Ok(entries) => {
+			let updated = entries
+				.first()
+				.map_or_else(|| FEED_EPOCH.to_owned(), |entry| entry.date.clone());
+			Some((entries, updated))
+		}
+		Err(e) => {
+			error!("Error when getting the membership feed: {e}");
+			None
+		}
+	}
+}
+
+/// Renders a feed with caching headers, or tells the client its cached copy is still fresh
+pub(super) fn feed_response<T: ThemedTemplate>(
+	request_headers: &HeaderMap,
+	template: &T,
+	content_type: &'static str,
+	updated: &str,
+) -> axum::response::Response {
+	let body = match template.render_themed() {
+		Ok(s) => {
+			debug!("Successfully rendered {} feed", content_type);
+			s
+		}
+		Err(e) => {
+			error!("Error when rendering {} feed: {}", content_type, e);
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	let etag = format!("\"{}\"", hex::encode(&Sha256::digest(body.as_bytes())[..16]));
+	let last_modified = http_date(updated);
+	let headers = [
+		(header::CACHE_CONTROL, format!("public, max-age={FEED_MAX_AGE}")),
+		(header::ETAG, etag.clone()),
+		(header::LAST_MODIFIED, last_modified.clone()),
+	];
+	let not_modified = match request_headers.get(header::IF_NONE_MATCH) {
+		Some(if_none_match) => if_none_match
+			.to_str()
+			.is_ok_and(|tags| tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*")),
+		None => request_headers
+			.get(header::IF_MODIFIED_SINCE)
+			.is_some_and(|since| since.to_str().is_ok_and(|since| since == last_modified)),
+	};
+	if not_modified {
+		debug!("The client's cached {} feed is still fresh", content_type);
+		return (StatusCode::NOT_MODIFIED, headers).into_response();
+	}
+	(
+		headers,
+		[(header::CONTENT_TYPE, format!("{content_type}; charset=utf-8"))],
+		body,
+	)
+		.into_response()
+}
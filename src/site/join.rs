@@ -1,47 +1,67 @@
+use std::fmt::Debug;
+
 use askama::Template;
 use axum::{
-	extract::{Form, Query, State},
+	extract::{Form, Query},
 	http::StatusCode,
 	response::{Html, IntoResponse, Redirect},
 };
 use axum_messages::{Message, Messages};
-use serde::Deserialize;
-use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, instrument};
 
-use crate::ring::{RingError, RingState};
+use super::CurrentRing;
+use crate::ring::{RingError, SiteProfile, ownership, rings::Ring};
+use crate::theme::{ThemedTemplate, themed};
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "join.html")]
 pub struct JoinTemplate {
+	ring: Ring,
 	messages: Vec<Message>,
 	url: String,
 	email: String,
+	profile: SiteProfile,
 	url_hash: String,
 }
 
-#[derive(Debug, Deserialize)]
+themed!(JoinTemplate, "join.html");
+
+#[derive(Serialize, Deserialize)]
 pub struct JoinParams {
 	url: String,
 	email: String,
+	#[serde(flatten)]
+	profile: SiteProfile,
 }
 
-#[instrument]
-pub async fn get(messages: Messages, Query(params): Query<JoinParams>) -> impl IntoResponse {
-	let mut hasher = Sha256::new();
-	hasher.update(&params.url);
-	let hashed_url = hasher.finalize();
-	let hashed_url_hex = hex::encode(hashed_url);
+impl Debug for JoinParams {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("JoinParams")
+			.field("url", &self.url)
+			.field("email", &"redacted")
+			.field("profile", &self.profile)
+			.finish()
+	}
+}
 
+#[instrument]
+pub async fn get(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Query(params): Query<JoinParams>,
+) -> impl IntoResponse {
 	match {
 		JoinTemplate {
+			ring: state.ring().clone(),
 			messages: messages.into_iter().collect(),
+			url_hash: ownership::auth_hash(&params.url),
 			url: params.url,
 			email: params.email,
-			url_hash: hashed_url_hex,
+			profile: params.profile.normalised(),
 		}
 	}
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered join html");
@@ -54,56 +74,66 @@ pub async fn get(messages: Messages, Query(params): Query<JoinParams>) -> impl I
 	}
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Deserialize)]
 pub struct JoinForm {
 	url: String,
 	email: String,
-	url_hash: String,
+	#[serde(flatten)]
+	profile: SiteProfile,
+}
+
+impl Debug for JoinForm {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("JoinForm")
+			.field("url", &self.url)
+			.field("email", &"redacted")
+			.field("profile", &self.profile)
+			.finish()
+	}
 }
 
 #[instrument]
 pub async fn post(
 	messages: Messages,
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
 	Form(data): Form<JoinForm>,
 ) -> impl IntoResponse {
-	let redirect_here =
-		Redirect::to(&format!("/join?url={}&email={}", data.url, data.email)).into_response();
-	let response = match ureq::get(format!("{}/webringer/auth", data.url)).call() {
-		Ok(response) if response.status() == StatusCode::NOT_FOUND => {
-			messages.error(format!(
-				"Got a 404 error when trying to get {}/webringer/auth",
-				data.url
-			));
-			return redirect_here;
+	let profile = data.profile.normalised();
+	let redirect_here = match serde_urlencoded::to_string(JoinParams {
+		url: data.url.clone(),
+		email: data.email.clone(),
+		profile: profile.clone(),
+	}) {
+		Ok(query) => {
+			Redirect::to(&format!("{}/join?{query}", state.ring().base_path())).into_response()
 		}
-		Ok(response) => match response.into_body().read_to_string() {
-			Ok(text) => text.trim().to_owned(),
-			Err(e) => {
-				error!("Error when converting join verify body to text: {e}");
-				return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-			}
-		},
 		Err(e) => {
-			messages.error(format!(
-				"There was an error when getting the verification string from your site: {e}"
-			));
-			return redirect_here;
+			error!("Error when encoding join params: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
 		}
 	};
-
-	if response != data.url_hash {
-		debug!("Response: {} Url hash: {}", response, data.url_hash);
-		messages.error(format!(
-			"Url hash found but did not match: {} != {}",
-			response, data.url_hash
-		));
+	if let Err(RingError::InvalidInput(problem)) = profile.validate() {
+		messages.error(problem);
 		return redirect_here;
 	}
+	match ownership::verify_ownership(&data.url).await {
+		Ok(()) => {}
+		Err(RingError::OwnershipNotVerified(problem)) => {
+			messages.error(problem);
+			return redirect_here;
+		}
+		Err(e) => {
+			error!("Error when verifying site ownership: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	}
 
-	match state.add_site(&data.url, &data.email).await {
+	match state.add_site(&data.url, &data.email, &profile).await {
 		Ok(()) => {
-			messages.info("Your site has been registered, please wait for admin to approve it");
+			messages.info(format!(
+				"Your site has been registered. We've sent a confirmation link to {}, please click it and then wait for an admin to approve your site",
+				data.email
+			));
 			redirect_here
 		}
 		Err(RingError::UniqueRowAlreadyPresent(site)) => {
@@ -123,3 +153,46 @@ pub async fn post(
 		}
 	}
 }
+
+#[derive(Template, Serialize)]
+#[template(path = "verify_email.html")]
+pub struct VerifyEmailTemplate {
+	ring: Ring,
+	url: Option<String>,
+}
+
+themed!(VerifyEmailTemplate, "verify_email.html");
+
+#[derive(Deserialize)]
+pub struct VerifyParams {
+	token: String,
+}
+
+pub async fn verify(
+	CurrentRing(state): CurrentRing,
+	Query(params): Query<VerifyParams>,
+) -> impl IntoResponse {
+	let url = match state.verify_site_email(&params.token).await {
+		Ok(url) => Some(url),
+		Err(RingError::RowNotFound(_query)) => None,
+		Err(e) => {
+			error!("Error when verifying site email: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	match (VerifyEmailTemplate {
+		ring: state.ring().clone(),
+		url,
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered verify email html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering verify email html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
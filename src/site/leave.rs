@@ -1,11 +1,12 @@
 use axum::{
-	extract::{Form, State},
+	extract::Form,
 	response::{Html, IntoResponse},
 };
 use serde::Deserialize;
 use tracing::{info, instrument, warn};
 
-use crate::ring::{RingError, RingState};
+use super::CurrentRing;
+use crate::ring::RingError;
 
 #[instrument]
 pub async fn get() -> &'static str {
@@ -20,7 +21,7 @@ pub struct LeaveForm {
 
 #[instrument]
 pub async fn post(
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
 	Form(data): Form<LeaveForm>,
 ) -> impl IntoResponse {
 	match state.remove_site(&data.url).await {
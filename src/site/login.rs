@@ -6,18 +6,26 @@ use axum::{
 	response::{Html, IntoResponse, Redirect},
 };
 use axum_messages::{Message, Messages};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
 
-use crate::ring::auth::{AuthSession, Credentials};
+use super::CurrentRing;
+use crate::ring::{
+	auth::{AuthSession, Credentials},
+	rings::Ring,
+};
+use crate::theme::{ThemedTemplate, themed};
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "login.html")]
 pub struct LoginTemplate {
+	ring: Ring,
 	messages: Vec<Message>,
 	next: Option<String>,
 }
 
+themed!(LoginTemplate, "login.html");
+
 // This allows us to extract the "next" field from the query string. We use this
 // to redirect after log in.
 #[derive(Debug, Deserialize)]
@@ -27,6 +35,7 @@ pub struct NextUrl {
 
 pub async fn post(
 	mut auth_session: AuthSession,
+	CurrentRing(state): CurrentRing,
 	messages: Messages,
 	Form(creds): Form<Credentials>,
 ) -> impl IntoResponse {
@@ -38,7 +47,7 @@ pub async fn post(
 		Ok(None) => {
 			debug!("Authentication failed with credentials {:?}", &creds);
 			messages.error("Invalid credentials");
-			let mut login_url = "/login".to_owned();
+			let mut login_url = state.ring().instance_path("/login");
 			if let Some(next) = creds.next {
 				login_url = format!("{login_url}?next={next}");
 			}
@@ -58,22 +67,27 @@ pub async fn post(
 	}
 
 	creds.next.as_ref().map_or_else(|| {
-		debug!("Login successful, redirecting to /");
-		Redirect::to("/").into_response()
+		debug!("Login successful, redirecting to the home page");
+		Redirect::to(&state.ring().home_path()).into_response()
 	}, |next| {
 		debug!("Login successful, redirecting to {}", &next);
 		Redirect::to(next).into_response()
 	})
 }
 
-pub async fn get(messages: Messages, Query(NextUrl { next }): Query<NextUrl>) -> impl IntoResponse {
+pub async fn get(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Query(NextUrl { next }): Query<NextUrl>,
+) -> impl IntoResponse {
 	match {
 		LoginTemplate {
+			ring: state.ring().clone(),
 			messages: messages.into_iter().collect(),
 			next,
 		}
 	}
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered login html");
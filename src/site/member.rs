@@ -0,0 +1,407 @@
+//! The self-service portal where site owners manage their membership of the webring
+
+use askama::Template;
+use axum::{
+	Form, Router,
+	extract::Query,
+	http::StatusCode,
+	response::{Html, IntoResponse, Redirect},
+	routing::{get, post},
+};
+use axum_messages::{Message, Messages};
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+use tracing::{debug, error, info, warn};
+
+use super::CurrentRing;
+use crate::ring::{RingError, RingState, SiteProfile, members::MemberSite, ownership, rings::Ring};
+use crate::theme::{ThemedTemplate, themed};
+
+/// The session key the logged in site's id is stored under
+const MEMBER_SITE_KEY: &str = "member_site_id";
+
+pub fn router(state: RingState) -> Router {
+	Router::new()
+		.route("/", get(dashboard))
+		.route("/login", get(login_get))
+		.route("/login", post(login_post))
+		.route("/login/verify", get(login_verify))
+		.route("/profile", post(profile))
+		.route("/email", post(email))
+		.route("/resend-verification", post(resend_verification))
+		.route("/tags", post(tags))
+		.route("/change-url", get(change_url_get))
+		.route("/change-url", post(change_url_post))
+		.route("/leave", post(leave))
+		.route("/logout", post(logout))
+		.with_state(state)
+}
+
+/// Gets the id of the site the current session is logged in to
+async fn logged_in_site(session: &Session) -> Option<i64> {
+	match session.get::<i64>(MEMBER_SITE_KEY).await {
+		Ok(site_id) => site_id,
+		Err(e) => {
+			error!("Error when reading member session: {e}");
+			None
+		}
+	}
+}
+
+fn render<T: ThemedTemplate>(template: &T, name: &str) -> axum::response::Response {
+	match template.render_themed() {
+		Ok(s) => {
+			debug!("Successfully rendered {} html", name);
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering {} html: {}", name, e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "member/login.html")]
+pub struct MemberLoginTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+}
+
+themed!(MemberLoginTemplate, "member/login.html");
+
+async fn login_get(messages: Messages, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	render(
+		&MemberLoginTemplate {
+			ring: state.ring().clone(),
+			messages: messages.into_iter().collect(),
+		},
+		"member login",
+	)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+	url: String,
+}
+
+async fn login_post(
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<LoginForm>,
+) -> impl IntoResponse {
+	match state.send_member_login_link(&form.url).await {
+		// Don't reveal which sites are registered
+		Ok(()) | Err(RingError::RowNotFound(_)) => {
+			messages.info(format!(
+				"If {} is registered, we've emailed a login link to its owner",
+				form.url
+			));
+		}
+		Err(RingError::Email(e)) => {
+			messages.error(format!("We couldn't send you a login email: {e}"));
+		}
+		Err(e) => {
+			error!("Error when sending member login link: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	}
+	Redirect::to(&state.ring().path("/member/login")).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct LoginVerifyParams {
+	token: String,
+}
+
+async fn login_verify(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Query(params): Query<LoginVerifyParams>,
+) -> impl IntoResponse {
+	let site_id = match state.redeem_member_login_token(&params.token).await {
+		Ok(site_id) => site_id,
+		Err(RingError::RowNotFound(_)) => {
+			messages.error("That login link isn't valid, it may have expired or already been used");
+			return Redirect::to(&state.ring().path("/member/login")).into_response();
+		}
+		Err(e) => {
+			error!("Error when redeeming member login token: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	if let Err(e) = session.cycle_id().await {
+		error!("Error when cycling member session id: {e}");
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	}
+	if let Err(e) = session.insert(MEMBER_SITE_KEY, site_id).await {
+		error!("Error when logging in member: {e}");
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	}
+	info!("Member of site {} logged in", site_id);
+	Redirect::to(&state.ring().path("/member")).into_response()
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "member/dashboard.html")]
+pub struct MemberDashboardTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+	site: MemberSite,
+	tags: String,
+}
+
+themed!(MemberDashboardTemplate, "member/dashboard.html");
+
+async fn dashboard(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let Some(site_id) = logged_in_site(&session).await else {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	};
+	let site = match state.get_member_site(site_id).await {
+		Ok(site) => site,
+		Err(RingError::RowNotFound(_)) => {
+			warn!("Logged in member's site {} no longer exists", site_id);
+			if let Err(e) = session.flush().await {
+				error!("Error when logging out member: {e}");
+			}
+			return Redirect::to(&state.ring().path("/member/login")).into_response();
+		}
+		Err(e) => {
+			error!("Error when getting member site: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	let tags = match state.get_site_tags(site_id).await {
+		Ok(tags) => tags.join(" "),
+		Err(e) => {
+			error!("Error when getting member site tags: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	render(
+		&MemberDashboardTemplate {
+			ring: state.ring().clone(),
+			messages: messages.into_iter().collect(),
+			site,
+			tags,
+		},
+		"member dashboard",
+	)
+}
+
+async fn profile(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(profile): Form<SiteProfile>,
+) -> impl IntoResponse {
+	let Some(site_id) = logged_in_site(&session).await else {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	};
+	match state
+		.update_site_profile(site_id, &profile.normalised())
+		.await
+	{
+		Ok(()) => messages.info("Your profile has been updated"),
+		Err(RingError::InvalidInput(problem)) => messages.error(problem),
+		Err(e) => {
+			error!("Error when updating site profile: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/member")).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagsForm {
+	tags: String,
+}
+
+async fn tags(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<TagsForm>,
+) -> impl IntoResponse {
+	let Some(site_id) = logged_in_site(&session).await else {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	};
+	match state.set_site_tags(site_id, &form.tags).await {
+		Ok(()) => messages.info("Your tags have been updated"),
+		Err(RingError::InvalidInput(problem)) => messages.error(problem),
+		Err(e) => {
+			error!("Error when updating site tags: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/member")).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailForm {
+	email: String,
+}
+
+async fn email(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<EmailForm>,
+) -> impl IntoResponse {
+	let Some(site_id) = logged_in_site(&session).await else {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	};
+	match state.change_site_email(site_id, &form.email).await {
+		Ok(()) => messages.info(format!(
+			"Your email has been changed, we've sent a confirmation link to {}",
+			form.email
+		)),
+		Err(e) => {
+			error!("Error when changing site email: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/member")).into_response()
+}
+
+async fn resend_verification(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let Some(site_id) = logged_in_site(&session).await else {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	};
+	match state.resend_verification_email(site_id).await {
+		Ok(()) => messages.info("We've sent you a new confirmation link"),
+		Err(RingError::InvalidInput(problem)) => messages.error(problem),
+		Err(e) => {
+			error!("Error when resending verification email: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	Redirect::to(&state.ring().path("/member")).into_response()
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "member/change_url.html")]
+pub struct MemberChangeUrlTemplate {
+	ring: Ring,
+	messages: Vec<Message>,
+	url: String,
+	url_hash: String,
+	grace_period_days: u32,
+}
+
+themed!(MemberChangeUrlTemplate, "member/change_url.html");
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeUrlParams {
+	url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeUrlForm {
+	url: String,
+	/// Whether navigation from the old url should keep working for a while
+	keep_old_url: Option<String>,
+}
+
+async fn change_url_get(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Query(params): Query<ChangeUrlParams>,
+) -> impl IntoResponse {
+	if logged_in_site(&session).await.is_none() {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	}
+	render(
+		&MemberChangeUrlTemplate {
+			ring: state.ring().clone(),
+			messages: messages.into_iter().collect(),
+			url_hash: ownership::auth_hash(&params.url),
+			url: params.url,
+			grace_period_days: super::URL_GRACE_PERIOD_DAYS,
+		},
+		"member change url",
+	)
+}
+
+async fn change_url_post(
+	session: Session,
+	messages: Messages,
+	CurrentRing(state): CurrentRing,
+	Form(form): Form<ChangeUrlForm>,
+) -> impl IntoResponse {
+	let Some(site_id) = logged_in_site(&session).await else {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	};
+	let redirect_back = match serde_urlencoded::to_string([("url", &form.url)]) {
+		Ok(query) => {
+			Redirect::to(&state.ring().path(&format!("/member/change-url?{query}"))).into_response()
+		}
+		Err(e) => {
+			error!("Error when encoding change url params: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	let grace_period_days = form.keep_old_url.map(|_| super::URL_GRACE_PERIOD_DAYS);
+	match state
+		.change_site_url(site_id, &form.url, grace_period_days)
+		.await
+	{
+		Ok(()) => {
+			messages.info(format!("Your site has been moved to {}", form.url));
+			Redirect::to(&state.ring().path("/member")).into_response()
+		}
+		Err(RingError::OwnershipNotVerified(problem)) => {
+			messages.error(problem);
+			redirect_back
+		}
+		Err(RingError::UniqueRowAlreadyPresent(url)) => {
+			messages.error(format!("The site {url} has already been registered"));
+			redirect_back
+		}
+		Err(e) => {
+			error!("Error when changing site url: {e}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+async fn leave(session: Session, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	let Some(site_id) = logged_in_site(&session).await else {
+		return Redirect::to(&state.ring().path("/member/login")).into_response();
+	};
+	let site = match state.get_member_site(site_id).await {
+		Ok(site) => site,
+		Err(e) => {
+			error!("Error when getting member site to leave: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	if let Err(e) = state.remove_site(&site.root_url).await {
+		error!("Error when removing member site: {e}");
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	}
+	if let Err(e) = session.flush().await {
+		error!("Error when logging out member who left: {e}");
+	}
+	Html("Your site has been removed from the webring!").into_response()
+}
+
+async fn logout(session: Session, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	if let Err(e) = session.flush().await {
+		error!("Error when logging out member: {e}");
+	}
+	(
+		[("content-length", "0")],
+		Redirect::to(&state.ring().home_path()),
+	)
+}
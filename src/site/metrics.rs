@@ -0,0 +1,132 @@
+//! This module handles exporting metrics for Prometheus, when webringer is built with the
+//! `metrics` feature
+//!
+//! Request counts and latencies and navigation outcomes are counted as they happen. Membership
+//! counts, the health of linked rings and planet feeds, and the database pool are read from the
+//! database whenever `/metrics` is scraped.
+
+use std::time::Instant;
+
+use axum::{
+	extract::{MatchedPath, Request, State},
+	http::{StatusCode, header},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use tracing::{debug, error, instrument};
+
+use crate::ring::{RingError, RingState, stats::NavigationKind};
+
+const REQUEST_DURATION: &str = "webringer_http_request_duration_seconds";
+const REQUEST_DURATION_BUCKETS: &[f64] = &[
+	0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The state of the `/metrics` handler
+#[derive(Clone)]
+pub struct Metrics {
+	handle: PrometheusHandle,
+	state: RingState,
+}
+
+impl std::fmt::Debug for Metrics {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Metrics").finish_non_exhaustive()
+	}
+}
+
+impl Metrics {
+	/// Installs the global metrics recorder, so the counts made by [`track_requests`] and
+	/// [`count_navigation`] are kept
+	///
+	/// # Errors
+	/// Returns a [`BuildError`] if a global recorder has already been installed
+	pub fn install(state: RingState) -> Result<Self, BuildError> {
+		let handle = PrometheusBuilder::new()
+			.set_buckets_for_metric(
+				Matcher::Full(REQUEST_DURATION.to_owned()),
+				REQUEST_DURATION_BUCKETS,
+			)?
+			.install_recorder()?;
+		Ok(Self { handle, state })
+	}
+}
+
+/// Middleware counting every request and how long it took, by method, route and status
+pub async fn track_requests(request: Request, next: Next) -> Response {
+	let start = Instant::now();
+	let method = request.method().to_string();
+	let route = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map_or_else(|| "unmatched".to_owned(), |path| path.as_str().to_owned());
+	let response = next.run(request).await;
+	metrics::counter!(
+		"webringer_http_requests_total",
+		"method" => method.clone(),
+		"route" => route.clone(),
+		"status" => response.status().as_u16().to_string(),
+	)
+	.increment(1);
+	metrics::histogram!(REQUEST_DURATION, "method" => method, "route" => route)
+		.record(start.elapsed().as_secs_f64());
+	response
+}
+
+/// Counts whether a visitor navigating through `ring` was sent on, or which [`RingError`]
+/// stopped them
+pub fn count_navigation(ring: &str, kind: NavigationKind, url: &Result<String, RingError>) {
+	metrics::counter!(
+		"webringer_navigation_total",
+		"ring" => ring.to_owned(),
+		"kind" => kind.as_str(),
+		"outcome" => url.as_ref().map_or_else(RingError::name, |_url| "ok"),
+	)
+	.increment(1);
+}
+
+#[instrument]
+pub async fn render(State(metrics): State<Metrics>) -> impl IntoResponse {
+	match metrics.state.get_ring_health().await {
+		Ok(health) => {
+			for ring in health {
+				for (state, count) in [
+					("pending", ring.pending),
+					("approved", ring.approved),
+					("denied", ring.denied),
+				] {
+					gauge("webringer_sites", &ring.ring, "state", state, count);
+				}
+				for (state, count) in [("ok", ring.peers_ok), ("failing", ring.peers_failing)] {
+					gauge("webringer_peer_rings", &ring.ring, "state", state, count);
+				}
+				for (state, count) in [("ok", ring.feeds_ok), ("failing", ring.feeds_failing)] {
+					gauge("webringer_planet_feeds", &ring.ring, "state", state, count);
+				}
+			}
+		}
+		Err(e) => {
+			error!("Error when getting ring health for metrics: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	}
+	let pool = metrics.state.pool_stats();
+	metrics::gauge!("webringer_db_pool_connections").set(pool.size);
+	metrics::gauge!("webringer_db_pool_idle_connections")
+		.set(u32::try_from(pool.idle).unwrap_or(u32::MAX));
+	metrics::gauge!("webringer_db_pool_max_connections").set(pool.max);
+
+	metrics.handle.run_upkeep();
+	debug!("Successfully rendered metrics");
+	(
+		[(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+		metrics.handle.render(),
+	)
+		.into_response()
+}
+
+fn gauge(name: &'static str, ring: &str, label: &'static str, value: &'static str, count: i64) {
+	metrics::gauge!(name, "ring" => ring.to_owned(), label => value)
+		.set(u32::try_from(count).unwrap_or(u32::MAX));
+}
@@ -2,22 +2,125 @@
 //! Where all the [axum] handlers etc live
 
 use askama::Template;
-use axum::response::Html;
+use axum::{
+	extract::{FromRequestParts, RawPathParams},
+	http::{StatusCode, request::Parts},
+	response::{Html, IntoResponse, Response},
+};
+use serde::Serialize;
+use tracing::{debug, error};
+
+use crate::ring::{RingError, RingState, rings::Ring};
+use crate::theme::{ThemedTemplate, themed};
 
 pub mod admin;
+pub mod directory;
+pub mod feed;
 pub mod join;
 pub mod leave;
 pub mod login;
+pub mod member;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod planet;
 pub mod ring;
 
-#[derive(askama::Template)]
+/// How long navigation from a site's old url keeps working after it moves, if the mover asks
+pub const URL_GRACE_PERIOD_DAYS: u32 = 30;
+
+/// Only lets pages load styles, fonts and images from this instance, so visitors don't make
+/// requests anywhere else. Even members' buttons are served from the instance's own copies.
+pub const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'self'; font-src 'self'; \
+	img-src 'self'; form-action 'self'; base-uri 'none'; frame-ancestors 'none'";
+
+/// [`CONTENT_SECURITY_POLICY`] for the widget, which members put on their sites
+pub const WIDGET_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'self'; \
+	font-src 'self'; img-src 'self'; base-uri 'none'";
+
+/// The [`RingState`] scoped to the ring a request is for
+///
+/// Routes nested under `/r/{ring}` are for the ring with that slug, and every other route is for
+/// the default ring. Requests for rings that don't exist get a 404.
+#[derive(Debug)]
+pub struct CurrentRing(pub RingState);
+
+impl FromRequestParts<RingState> for CurrentRing {
+	type Rejection = Response;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		state: &RingState,
+	) -> Result<Self, Self::Rejection> {
+		let slug = RawPathParams::from_request_parts(parts, state)
+			.await
+			.ok()
+			.and_then(|params| {
+				params
+					.iter()
+					.find(|(key, _value)| *key == "ring")
+					.map(|(_key, value)| value.to_owned())
+			});
+		let scoped = match slug {
+			Some(ref slug) => state.for_ring(slug).await,
+			None => state.for_default_ring().await,
+		};
+		match scoped {
+			Ok(state) => Ok(Self(state)),
+			Err(RingError::RowNotFound(ring)) => {
+				debug!("Someone tried to visit {} which doesn't exist", ring);
+				Err(StatusCode::NOT_FOUND.into_response())
+			}
+			Err(e) => {
+				error!("Error when getting the current ring: {e}");
+				Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+			}
+		}
+	}
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "index.html")]
-pub struct IndexTemplate {}
+pub struct IndexTemplate {
+	ring: Ring,
+}
+
+themed!(IndexTemplate, "index.html");
+
+#[derive(Template, Serialize)]
+#[template(path = "not_found.html")]
+pub struct NotFoundTemplate {
+	ring: Ring,
+}
+
+themed!(NotFoundTemplate, "not_found.html");
+
+/// The page for everything that isn't there, linking back to the home of the default ring
+///
+/// # Panics
+/// Panics if there is a problem in [`Template::render`]
+pub async fn not_found(CurrentRing(state): CurrentRing) -> (StatusCode, Html<String>) {
+	(
+		StatusCode::NOT_FOUND,
+		Html(
+			NotFoundTemplate {
+				ring: state.ring().clone(),
+			}
+			.render_themed()
+			.expect("Problem rendering not found template"),
+		),
+	)
+}
 
 /// Gets the index of the site
 ///
 /// # Panics
 /// Panics if there is a problem in [`Template::render`]
-pub async fn index() -> Html<String> {
-	Html(IndexTemplate {}.render().expect("Problem rendering index template"))
+pub async fn index(CurrentRing(state): CurrentRing) -> Html<String> {
+	Html(
+		IndexTemplate {
+			ring: state.ring().clone(),
+		}
+		.render_themed()
+		.expect("Problem rendering index template"),
+	)
 }
@@ -0,0 +1,94 @@
+//! The planet, a combined view of posts from members' own feeds
+
+use askama::Template;
+use axum::{
+	http::{HeaderMap, StatusCode},
+	response::{Html, IntoResponse},
+};
+use serde::Serialize;
+use tracing::{debug, error, instrument};
+
+use super::{CurrentRing, feed::feed_response};
+use crate::ring::{
+	RingState,
+	feed::{FEED_EPOCH, rfc3339},
+	planet::PlanetEntry,
+	rings::Ring,
+};
+use crate::theme::{ThemedTemplate, themed};
+
+/// How many posts are shown on the planet
+const PLANET_LENGTH: i64 = 50;
+
+#[derive(Template, Serialize)]
+#[template(path = "planet.html")]
+pub struct PlanetTemplate {
+	ring: Ring,
+	entries: Vec<PlanetEntry>,
+}
+
+themed!(PlanetTemplate, "planet.html");
+
+#[derive(Template, Serialize)]
+#[template(path = "feed/planet.xml")]
+pub struct PlanetFeedTemplate {
+	ring: Ring,
+	entries: Vec<PlanetEntry>,
+	home_url: String,
+	page_url: String,
+	feed_url: String,
+	updated: String,
+}
+
+themed!(PlanetFeedTemplate, "feed/planet.xml");
+
+async fn get_entries(state: &RingState) -> Option<Vec<PlanetEntry>> {
+	match state.get_planet_entries(PLANET_LENGTH).await {
+		Ok(entries) => Some(entries),
+		Err(e) => {
+			error!("Error when getting the planet entries: {e}");
+			None
+		}
+	}
+}
+
+#[instrument]
+pub async fn page(CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	let Some(entries) = get_entries(&state).await else {
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	};
+	match (PlanetTemplate {
+		ring: state.ring().clone(),
+		entries,
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered planet html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering planet html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[instrument]
+pub async fn atom(headers: HeaderMap, CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	let Some(entries) = get_entries(&state).await else {
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	};
+	let updated = entries
+		.first()
+		.map_or_else(|| FEED_EPOCH.to_owned(), |entry| entry.published.clone());
+	let template = PlanetFeedTemplate {
+		ring: state.ring().clone(),
+		entries,
+		home_url: state.home_url(),
+		page_url: state.url("/planet"),
+		feed_url: state.url("/planet.atom"),
+		updated: rfc3339(&updated),
+	};
+	feed_response(&headers, &template, "application/atom+xml", &updated)
+}
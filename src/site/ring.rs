@@ -1,39 +1,94 @@
 use askama::Template;
 use axum::{
-	extract::{Query, State},
-	http::{self, StatusCode},
+	extract::{Path, Query},
+	http::{self, StatusCode, header},
 	response::{Html, IntoResponse, Redirect},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::ring::{RingError, RingState};
+use super::CurrentRing;
+use crate::ring::{
+	ApprovedSite, RingError,
+	federation::{PeerRing, SIGNATURE_HEADER},
+	rings::Ring,
+	stats::{DailySiteStats, MAX_STATS_DAYS, NavigationKind},
+	tags::{Tag, normalise_tag},
+};
+use crate::theme::{ThemedTemplate, themed};
 
 #[derive(Debug, Deserialize)]
 pub struct MoveParams {
 	current: String,
+	/// Only navigate between sites with this tag
+	tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagParams {
+	tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RandomParams {
+	/// The site the visitor is coming from, if they're coming from one
+	current: Option<String>,
+	tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsParams {
+	/// How many days of stats to show
+	days: Option<u32>,
+}
+
+/// How many days of stats are shown if the visitor doesn't ask for a number
+pub const DEFAULT_STATS_DAYS: u32 = 30;
+
+#[derive(Debug, Deserialize)]
+pub struct MemberPath {
+	id: i64,
 }
 
 #[instrument]
 pub async fn next(
 	Query(params): Query<MoveParams>,
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
 ) -> impl IntoResponse {
-	next_prev_redirect(state.get_next(&params.current).await, params.current).await
+	let tag = normalise_tag(params.tag.as_deref());
+	let url = state.get_next(&params.current, tag.as_deref()).await;
+	#[cfg(feature = "metrics")]
+	super::metrics::count_navigation(&state.ring().slug, NavigationKind::Next, &url);
+	if let Ok(ref url) = url {
+		state
+			.record_navigation(NavigationKind::Next, Some(&params.current), Some(url))
+			.await;
+	}
+	next_prev_redirect(url, params.current, state.ring().home_path()).await
 }
 
 #[instrument]
 pub async fn prev(
 	Query(params): Query<MoveParams>,
-	State(state): State<RingState>,
+	CurrentRing(state): CurrentRing,
 ) -> impl IntoResponse {
-	next_prev_redirect(state.get_prev(&params.current).await, params.current).await
+	let tag = normalise_tag(params.tag.as_deref());
+	let url = state.get_prev(&params.current, tag.as_deref()).await;
+	#[cfg(feature = "metrics")]
+	super::metrics::count_navigation(&state.ring().slug, NavigationKind::Prev, &url);
+	if let Ok(ref url) = url {
+		state
+			.record_navigation(NavigationKind::Prev, Some(&params.current), Some(url))
+			.await;
+	}
+	next_prev_redirect(url, params.current, state.ring().home_path()).await
 }
 
 #[instrument]
 async fn next_prev_redirect(
 	url: Result<String, RingError>,
 	original_url: String,
+	home_path: String,
 ) -> impl IntoResponse {
 	match url {
 		Ok(url) => {
@@ -46,7 +101,7 @@ async fn next_prev_redirect(
 		}
 		Err(RingError::RowNotFound(_query)) => {
 			debug!("End of webring found, redirecting user to home");
-			Redirect::to(".").into_response()
+			Redirect::to(&home_path).into_response()
 		}
 		Err(RingError::UnrecoverableDatabaseError(_e)) => {
 			Redirect::to(&original_url).into_response()
@@ -62,9 +117,19 @@ async fn next_prev_redirect(
 }
 
 #[instrument]
-pub async fn random(State(state): State<RingState>) -> impl IntoResponse {
-	match state.get_random_site().await {
+pub async fn random(
+	Query(params): Query<RandomParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let tag = normalise_tag(params.tag.as_deref());
+	let url = state.get_random_site(tag.as_deref()).await;
+	#[cfg(feature = "metrics")]
+	super::metrics::count_navigation(&state.ring().slug, NavigationKind::Random, &url);
+	match url {
 		Ok(url) => {
+			state
+				.record_navigation(NavigationKind::Random, params.current.as_deref(), Some(&url))
+				.await;
 			info!("Redirecting user to {}", &url);
 			Redirect::to(&url).into_response()
 		}
@@ -79,28 +144,68 @@ pub async fn random(State(state): State<RingState>) -> impl IntoResponse {
 	}
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "list.html")]
 pub struct ListTemplate {
-	sites: Vec<String>,
+	ring: Ring,
+	sites: Vec<ApprovedSite>,
+	tags: Vec<Tag>,
+	tag: Option<String>,
+	/// Other webrings this one links to
+	peers: Vec<PeerRing>,
 }
 
+themed!(ListTemplate, "list.html");
+
 #[instrument]
-pub async fn list(State(state): State<RingState>) -> impl IntoResponse {
-	match match state.get_list_approved().await {
+pub async fn list(
+	Query(params): Query<TagParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let tags = match state.get_list_tags().await {
+		Ok(tags) => tags,
+		Err(e) => {
+			error!("Error when getting the list of tags: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	// Peer rings are kept in the database even when the sites aren't, so the list still works
+	// without them
+	let peers = match state.get_list_peer_rings().await {
+		Ok(peers) => peers
+			.into_iter()
+			.filter(|peer| peer.last_error.is_none())
+			.collect(),
+		Err(e) => {
+			error!("Error when getting the list of peer rings: {e}");
+			Vec::new()
+		}
+	};
+	let tag = normalise_tag(params.tag.as_deref());
+	match match state.get_list_approved(tag.as_deref()).await {
 		Ok(sites) => ListTemplate {
-			sites: sites.into_iter().map(|site| site.root_url).collect(),
+			ring: state.ring().clone(),
+			sites,
+			tags,
+			tag,
+			peers,
 		},
 		Err(RingError::RowNotFound(_query)) => {
 			warn!("There are currently no approved sites in the webring");
-			ListTemplate { sites: vec![] }
+			ListTemplate {
+				ring: state.ring().clone(),
+				sites: vec![],
+				tags,
+				tag,
+				peers,
+			}
 		}
 		Err(e) => {
 			error!("Error when getting the list of approved sites: {e}");
 			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
 		}
 	}
-	.render()
+	.render_themed()
 	{
 		Ok(s) => {
 			debug!("Successfully rendered list html");
@@ -112,3 +217,234 @@ pub async fn list(State(state): State<RingState>) -> impl IntoResponse {
 		}
 	}
 }
+
+#[derive(Template, Serialize)]
+#[template(path = "member.html")]
+pub struct MemberTemplate {
+	ring: Ring,
+	site: ApprovedSite,
+}
+
+themed!(MemberTemplate, "member.html");
+
+#[instrument]
+pub async fn member(
+	Path(MemberPath { id: site_id }): Path<MemberPath>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let site = match state.get_approved_site(site_id).await {
+		Ok(site) => site,
+		Err(RingError::SiteNotApproved(_site)) => {
+			debug!("Someone tried to view the profile of unapproved site {site_id}");
+			return StatusCode::NOT_FOUND.into_response();
+		}
+		Err(e) => {
+			error!("Error when getting member site: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	match (MemberTemplate {
+		ring: state.ring().clone(),
+		site,
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered member html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering member html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+/// Serves the instance's copy of a member's button
+#[instrument]
+pub async fn button(
+	Path(MemberPath { id: site_id }): Path<MemberPath>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	match state.get_button(site_id).await {
+		Ok(Some(button)) => (
+			[
+				(header::CONTENT_TYPE, button.content_type),
+				(header::CACHE_CONTROL, "public, max-age=3600".to_owned()),
+				(header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+			],
+			button.image,
+		)
+			.into_response(),
+		Ok(None) => {
+			debug!("Site {site_id} has no button to serve");
+			StatusCode::NOT_FOUND.into_response()
+		}
+		Err(e) => {
+			error!("Error when getting the button of site {site_id}: {e}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "member_stats.html")]
+pub struct MemberStatsTemplate {
+	ring: Ring,
+	site: ApprovedSite,
+	days: u32,
+	stats: Vec<DailySiteStats>,
+}
+
+themed!(MemberStatsTemplate, "member_stats.html");
+
+#[instrument]
+pub async fn member_stats(
+	Path(MemberPath { id: site_id }): Path<MemberPath>,
+	Query(params): Query<StatsParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let site = match state.get_approved_site(site_id).await {
+		Ok(site) => site,
+		Err(RingError::SiteNotApproved(_site)) => {
+			debug!("Someone tried to view the stats of unapproved site {site_id}");
+			return StatusCode::NOT_FOUND.into_response();
+		}
+		Err(e) => {
+			error!("Error when getting member site: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	let days = params
+		.days
+		.unwrap_or(DEFAULT_STATS_DAYS)
+		.clamp(1, MAX_STATS_DAYS);
+	let daily = match state.get_site_daily_stats(site_id, days).await {
+		Ok(daily) => daily,
+		Err(e) => {
+			error!("Error when getting member site stats: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	match (MemberStatsTemplate {
+		ring: state.ring().clone(),
+		site,
+		days,
+		stats: daily,
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered member stats html");
+			Html(s).into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering member stats html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "widget.html")]
+pub struct WidgetTemplate {
+	ring: Ring,
+	/// The query string for the navigation links, with the site and tag the widget is on
+	query: String,
+}
+
+themed!(WidgetTemplate, "widget.html");
+
+/// A small navigation bar for members to embed in their site with an iframe
+#[instrument]
+pub async fn widget(
+	Query(params): Query<MoveParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	let query = match serde_urlencoded::to_string([
+		("current", Some(&params.current)),
+		("tag", normalise_tag(params.tag.as_deref()).as_ref()),
+	]) {
+		Ok(query) => query,
+		Err(e) => {
+			error!("Error when encoding widget params: {e}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+	state
+		.record_navigation(NavigationKind::Widget, Some(&params.current), None)
+		.await;
+	match (WidgetTemplate {
+		ring: state.ring().clone(),
+		query,
+	})
+	.render_themed()
+	{
+		Ok(s) => {
+			debug!("Successfully rendered widget html");
+			(
+				[(header::CONTENT_SECURITY_POLICY, super::WIDGET_CONTENT_SECURITY_POLICY)],
+				Html(s),
+			)
+				.into_response()
+		}
+		Err(e) => {
+			error!("Error when rendering widget html: {}", e);
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+/// Serves the ring's signed manifest, for other instances to link to it
+#[instrument]
+pub async fn manifest(CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	match state.get_ring_manifest().await {
+		Ok((body, signature)) => (
+			[(header::CONTENT_TYPE, "application/json")],
+			[(SIGNATURE_HEADER, signature)],
+			body,
+		)
+			.into_response(),
+		Err(e) => {
+			error!("Error when getting the ring manifest: {e}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RingMoveParams {
+	/// The home url of the ring the visitor is coming from
+	current: Option<String>,
+}
+
+#[instrument]
+pub async fn next_ring(
+	Query(params): Query<RingMoveParams>,
+	CurrentRing(state): CurrentRing,
+) -> impl IntoResponse {
+	ring_redirect(state.get_next_ring(params.current.as_deref()).await)
+}
+
+#[instrument]
+pub async fn random_ring(CurrentRing(state): CurrentRing) -> impl IntoResponse {
+	ring_redirect(state.get_random_ring().await)
+}
+
+fn ring_redirect(url: Result<String, RingError>) -> axum::response::Response {
+	match url {
+		Ok(url) => {
+			debug!("Redirecting user to ring {url}");
+			Redirect::to(&url).into_response()
+		}
+		Err(RingError::RowNotFound(_query)) => {
+			warn!("There are currently no peer rings to navigate to");
+			Html("<h1>Error</h1><br><p>This webring isn't linked to any other webrings yet :(")
+				.into_response()
+		}
+		Err(e) => {
+			error!("Error when navigating between rings: {e}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
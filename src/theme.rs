@@ -0,0 +1,270 @@
+//! This module handles changing how the site looks without rebuilding webringer
+//!
+//! A theme directory can have a `templates` directory and a `static` directory, laid out like the
+//! ones in this repository. A template in it is used instead of the built in one with the same
+//! path, and is rendered with minijinja, getting the same fields as the built in one. `ring` has
+//! the same methods too, like `ring.path("/list")`. A template that extends or includes another
+//! needs that one to be in the theme as well. Files in `static` are served instead of the built in
+//! ones with the same path.
+
+use std::{path::PathBuf, sync::Arc};
+
+use askama::Template;
+use minijinja::{
+	Environment, Error, ErrorKind, State, Value, context,
+	value::{Enumerator, Object, from_args},
+};
+use serde::Serialize;
+
+use crate::ring::rings::Ring;
+
+/// The name of the instance when none is set
+const DEFAULT_NAME: &str = "WebRinger";
+
+/// How the site looks, beyond what the built in templates and files do
+#[derive(Debug, Default)]
+pub struct Theme {
+	/// Where templates and static files are looked for before the built in ones
+	dir: Option<PathBuf>,
+	name: Option<String>,
+	logo: Option<String>,
+	footer: Option<String>,
+	/// Renders the templates in the theme directory, loading each one the first time it's used
+	templates: Option<Environment<'static>>,
+}
+
+impl Theme {
+	/// Uses the templates and static files in `dir` instead of the built in ones
+	#[must_use]
+	pub fn with_dir(self, dir: impl Into<PathBuf>) -> Self {
+		let dir = dir.into();
+		let mut templates = Environment::new();
+		templates.set_loader(minijinja::path_loader(dir.join("templates")));
+		Self {
+			dir: Some(dir),
+			templates: Some(templates),
+			..self
+		}
+	}
+
+	/// Sets the name of the whole instance, shown in every page's footer
+	#[must_use]
+	pub fn with_name(self, name: impl Into<String>) -> Self {
+		Self {
+			name: Some(name.into()),
+			..self
+		}
+	}
+
+	/// Sets the url of the logo shown on every page, which is relative to where the instance is
+	/// served if it starts with `/`
+	#[must_use]
+	pub fn with_logo(self, logo: impl Into<String>) -> Self {
+		Self {
+			logo: Some(logo.into()),
+			..self
+		}
+	}
+
+	/// Sets the text shown in every page's footer
+	#[must_use]
+	pub fn with_footer(self, footer: impl Into<String>) -> Self {
+		Self {
+			footer: Some(footer.into()),
+			..self
+		}
+	}
+
+	/// The name of the whole instance
+	#[must_use]
+	pub fn name(&self) -> &str {
+		self.name.as_deref().unwrap_or(DEFAULT_NAME)
+	}
+
+	/// The url of the logo, if one is set
+	#[must_use]
+	pub fn logo(&self) -> Option<&str> {
+		self.logo.as_deref()
+	}
+
+	/// The text shown in the footer, if any is set
+	#[must_use]
+	pub fn footer(&self) -> Option<&str> {
+		self.footer.as_deref()
+	}
+
+	/// The directory static files are looked for in before the built in ones, if there is one
+	pub(crate) fn static_dir(&self) -> Option<PathBuf> {
+		self.dir.as_deref().map(|dir| dir.join("static"))
+	}
+
+	/// Renders the theme's copy of the template at `path`, if it has one
+	fn render<T: Serialize + ?Sized>(
+		&self,
+		path: &str,
+		ring: &Ring,
+		template: &T,
+	) -> Option<Result<String, Error>> {
+		let templates = self.templates.as_ref()?;
+		let theme_template = match templates.get_template(path) {
+			Ok(theme_template) => theme_template,
+			Err(e) if e.kind() == ErrorKind::TemplateNotFound => return None,
+			Err(e) => return Some(Err(e)),
+		};
+		let ring = Value::from_object(RingValue(ring.clone()));
+		Some(theme_template.render(context! { ring, ..Value::from_serialize(template) }))
+	}
+}
+
+/// A built in template, which a [`Theme`] can replace with its own copy
+pub(crate) trait ThemedTemplate: Template + Serialize {
+	/// Where the template is, relative to the `templates` directory
+	const PATH: &'static str;
+
+	/// The ring the template is being rendered for, which has the theme
+	fn ring(&self) -> &Ring;
+
+	/// Renders the theme's copy of the template if it has one, or the built in one if not
+	fn render_themed(&self) -> askama::Result<String> {
+		let ring = self.ring();
+		match ring.theme.render(Self::PATH, ring, self) {
+			Some(rendered) => rendered.map_err(|e| askama::Error::Custom(Box::new(e))),
+			None => self.render(),
+		}
+	}
+}
+
+/// Implements [`ThemedTemplate`] for a template with a `ring` field, given its path
+macro_rules! themed {
+	($template:ty, $path:literal) => {
+		impl $crate::theme::ThemedTemplate for $template {
+			const PATH: &'static str = $path;
+
+			fn ring(&self) -> &$crate::ring::rings::Ring {
+				&self.ring
+			}
+		}
+	};
+}
+pub(crate) use themed;
+
+/// A [`Ring`] as theme templates see it, with the same methods the built in templates use
+#[derive(Debug)]
+struct RingValue(Ring);
+
+impl Object for RingValue {
+	fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+		match key.as_str()? {
+			"id" => Some(Value::from(self.0.id)),
+			"slug" => Some(Value::from(self.0.slug.clone())),
+			"name" => Some(Value::from(self.0.name.clone())),
+			"description" => Some(Value::from(self.0.description.clone())),
+			_ => None,
+		}
+	}
+
+	fn enumerate(self: &Arc<Self>) -> Enumerator {
+		Enumerator::Str(&["id", "slug", "name", "description"])
+	}
+
+	fn call_method(
+		self: &Arc<Self>,
+		_state: &State<'_, '_>,
+		method: &str,
+		args: &[Value],
+	) -> Result<Value, Error> {
+		let ring = &self.0;
+		Ok(match method {
+			"path" => Value::from(ring.path(from_args::<(&str,)>(args)?.0)),
+			"instance_path" => Value::from(ring.instance_path(from_args::<(&str,)>(args)?.0)),
+			"is_default" => Value::from(ring.is_default()),
+			"base_path" => Value::from(ring.base_path()),
+			"home_path" => Value::from(ring.home_path()),
+			"instance_name" => Value::from(ring.instance_name()),
+			"logo_path" => Value::from(ring.logo_path()),
+			"footer" => Value::from(ring.footer()),
+			_ => return Err(Error::from(ErrorKind::UnknownMethod)),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use askama::Template;
+	use serde::Serialize;
+
+	use super::{Theme, ThemedTemplate};
+	use crate::ring::rings::Ring;
+
+	#[derive(Template, Serialize)]
+	#[template(source = "built in {{ greeting }}", ext = "txt")]
+	struct Greeting {
+		ring: Ring,
+		greeting: &'static str,
+	}
+
+	themed!(Greeting, "greeting.txt");
+
+	#[derive(Template, Serialize)]
+	#[template(source = "built in farewell", ext = "txt")]
+	struct Farewell {
+		ring: Ring,
+	}
+
+	themed!(Farewell, "farewell.txt");
+
+	#[derive(Template, Serialize)]
+	#[template(source = "built in broken", ext = "txt")]
+	struct Broken {
+		ring: Ring,
+	}
+
+	themed!(Broken, "broken.txt");
+
+	fn ring(theme: Theme) -> Ring {
+		Ring {
+			id: 2,
+			slug: "art".to_owned(),
+			theme: Arc::new(theme),
+			..Ring::default()
+		}
+	}
+
+	#[test]
+	fn theme_templates_replace_built_in_ones_that_they_have() {
+		let dir = std::env::temp_dir().join(format!("webringer-theme-{}", std::process::id()));
+		std::fs::create_dir_all(dir.join("templates")).unwrap();
+		std::fs::write(
+			dir.join("templates/greeting.txt"),
+			"{{ greeting }} from {{ ring.path('/list') }}",
+		)
+		.unwrap();
+		std::fs::write(dir.join("templates/broken.txt"), "{{ greeting").unwrap();
+		let ring = ring(Theme::default().with_dir(&dir));
+
+		let greeting = Greeting {
+			ring: ring.clone(),
+			greeting: "hello",
+		}
+		.render_themed();
+		let farewell = Farewell { ring: ring.clone() }.render_themed();
+		let broken = Broken { ring }.render_themed();
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(greeting.unwrap(), "hello from /r/art/list");
+		assert_eq!(farewell.unwrap(), "built in farewell");
+		// A mistake in the theme is shown, rather than quietly using the built in template
+		assert!(broken.is_err());
+	}
+
+	#[test]
+	fn built_in_templates_are_used_without_a_theme_directory() {
+		let greeting = Greeting {
+			ring: ring(Theme::default()),
+			greeting: "hello",
+		};
+		assert_eq!(greeting.render_themed().unwrap(), "built in hello");
+	}
+}
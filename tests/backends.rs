@@ -0,0 +1,867 @@
+//! Runs the core [`RingState`] operations against a freshly migrated database of each kind
+//!
+//! sqlite is always tested, in memory. Postgres needs a server, so its test is ignored unless
+//! asked for, and then `DATABASE_URL` has to be a `postgres://` url; each run creates, and
+//! afterwards drops, its own databases on that server, so it needs Postgres 13 or newer and a user
+//! allowed to create databases:
+//!
+//! ```sh
+//! DATABASE_URL=postgres://postgres@localhost/postgres cargo test --test backends -- --ignored
+//! ```
+#![warn(clippy::pedantic, clippy::all, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+use std::{
+	sync::{Arc, Mutex},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	Router,
+	extract::Path,
+	http::{HeaderMap, StatusCode, header},
+	response::IntoResponse,
+	routing::{get, post},
+};
+use axum_login::AuthUser;
+use ed25519_dalek::SigningKey;
+use sqlx::{AnyPool, any::AnyPoolOptions, migrate::Migrator};
+use tokio::net::TcpListener;
+
+use webringer::ring::{
+	RingError, RingState, SiteProfile,
+	email::Mailer,
+	export::Decision,
+	federation::{
+		MANIFEST_VERSION, ManifestNavigation, RingManifest, SIGNATURE_HEADER, generate_signing_key,
+		sign_manifest,
+	},
+	importer::ImportedSite,
+	sql,
+	stats::NavigationKind,
+	store::{DatabaseStore, RingStore},
+	webhooks::sign_payload,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// The admin every database starts with
+const DEFAULT_ADMIN_ID: i64 = 1;
+
+fn profile(title: &str) -> SiteProfile {
+	SiteProfile {
+		title: Some(title.to_owned()),
+		..SiteProfile::default()
+	}
+}
+
+async fn add_approved_site(state: &RingState, url: &str) {
+	state
+		.add_site(url, "owner@example.com", &profile(url))
+		.await
+		.unwrap();
+	state.approve_site(url, DEFAULT_ADMIN_ID).await.unwrap();
+}
+
+async fn navigation(state: &RingState) {
+	let started = sql::now();
+	for url in ["https://a.example", "https://b.example", "https://c.example"] {
+		add_approved_site(state, url).await;
+	}
+	state
+		.add_site("https://pending.example", "owner@example.com", &profile("Pending"))
+		.await
+		.unwrap();
+
+	assert_eq!(
+		state.get_next("https://a.example", None).await.unwrap(),
+		"https://b.example"
+	);
+	assert_eq!(
+		state.get_prev("https://c.example", None).await.unwrap(),
+		"https://b.example"
+	);
+	assert!(matches!(
+		state.get_next("https://c.example", None).await,
+		Err(RingError::RowNotFound(_))
+	));
+	assert!(matches!(
+		state.get_next("https://pending.example", None).await,
+		Err(RingError::SiteNotApproved(_))
+	));
+	assert!(
+		state
+			.get_random_site(None)
+			.await
+			.unwrap()
+			.ends_with(".example")
+	);
+
+	let approved = state.get_list_approved(None).await.unwrap();
+	let urls: Vec<&str> = approved.iter().map(|site| site.root_url.as_str()).collect();
+	assert_eq!(urls, ["https://a.example", "https://b.example", "https://c.example"]);
+	assert_eq!(approved[0].profile.title.as_deref(), Some("https://a.example"));
+	let unapproved = state.get_list_unapproved().await.unwrap();
+	assert_eq!(unapproved.len(), 1);
+	assert_eq!(unapproved[0].root_url, "https://pending.example");
+
+	state
+		.deny_site("https://pending.example", "Not a personal site", DEFAULT_ADMIN_ID)
+		.await
+		.unwrap();
+	let denied = state.get_list_denied().await.unwrap();
+	assert_eq!(denied[0].reason, "Not a personal site");
+	assert!(matches!(
+		state
+			.approve_site("https://unknown.example", DEFAULT_ADMIN_ID)
+			.await,
+		Err(RingError::RowNotFound(_))
+	));
+	assert!(matches!(
+		state
+			.deny_site("https://unknown.example", "Spam", DEFAULT_ADMIN_ID)
+			.await,
+		Err(RingError::RowNotFound(_))
+	));
+	assert!(state.get_list_unapproved().await.unwrap().is_empty());
+
+	state.remove_site("https://b.example").await.unwrap();
+	assert_eq!(
+		state.get_next("https://a.example", None).await.unwrap(),
+		"https://c.example"
+	);
+	let feed = state.get_membership_feed(10).await.unwrap();
+	assert_eq!(feed.len(), 3);
+	assert_eq!(feed.iter().filter(|entry| !entry.joined).count(), 1);
+	// Joins are dated when they were approved, not midnight that day
+	assert!(feed.iter().all(|entry| entry.date >= started));
+}
+
+async fn tags(state: &RingState) {
+	let site_id = approved_site_id(state, "https://c.example").await;
+	state.set_site_tags(site_id, "Art, music").await.unwrap();
+	assert_eq!(state.get_site_tags(site_id).await.unwrap(), ["art", "music"]);
+	assert_eq!(
+		state.get_random_site(Some("art")).await.unwrap(),
+		"https://c.example"
+	);
+	let tagged = state.get_list_approved(Some("music")).await.unwrap();
+	assert_eq!(tagged.len(), 1);
+	assert_eq!(tagged[0].tag_list(), ["art", "music"]);
+	let tags = state.get_list_tags().await.unwrap();
+	assert_eq!(tags.len(), 2);
+	assert_eq!(tags[0].site_count, 1);
+}
+
+async fn approved_site_id(state: &RingState, url: &str) -> i64 {
+	state
+		.get_list_approved(None)
+		.await
+		.unwrap()
+		.into_iter()
+		.find(|site| site.root_url == url)
+		.unwrap()
+		.site_id
+}
+
+async fn members(state: &RingState, store: &DatabaseStore) {
+	let site_id = approved_site_id(state, "https://a.example").await;
+	store
+		.change_site_email(1, site_id, "new@example.com", "verify-me")
+		.await
+		.unwrap();
+	assert_eq!(
+		state.verify_site_email("verify-me").await.unwrap(),
+		"https://a.example"
+	);
+	assert!(matches!(
+		state.verify_site_email("verify-me").await,
+		Err(RingError::RowNotFound(_))
+	));
+
+	state
+		.update_site_profile(site_id, &profile("New title"))
+		.await
+		.unwrap();
+	let site = state.get_member_site(site_id).await.unwrap();
+	assert_eq!(site.profile.title.as_deref(), Some("New title"));
+	assert_eq!(site.email, "new@example.com");
+	assert!(site.email_verified_at.is_some());
+	assert!(site.is_approved());
+
+	state
+		.send_member_login_link("https://a.example")
+		.await
+		.unwrap();
+	assert!(matches!(
+		state.send_member_login_link("https://unknown.example").await,
+		Err(RingError::RowNotFound(_))
+	));
+	store
+		.add_member_login_token(site_id, "hash", &sql::seconds_from_now(60))
+		.await
+		.unwrap();
+	assert_eq!(
+		store.redeem_member_login_token(1, "hash").await.unwrap(),
+		site_id
+	);
+	assert!(matches!(
+		store.redeem_member_login_token(1, "hash").await,
+		Err(RingError::RowNotFound(_))
+	));
+}
+
+async fn rings_and_imports(state: &RingState) {
+	state
+		.add_ring("art", "Art ring", Some("Art sites"))
+		.await
+		.unwrap();
+	assert!(matches!(
+		state.add_ring("art", "Another art ring", None).await,
+		Err(RingError::UniqueRowAlreadyPresent(_))
+	));
+	let art = state.for_ring("art").await.unwrap();
+	assert_eq!(art.ring().name, "Art ring");
+	art.update_ring("Art webring", None).await.unwrap();
+	let rings = state.get_list_rings().await.unwrap();
+	assert_eq!(rings.len(), 2);
+	assert!(rings[0].is_default());
+	assert_eq!(rings[1].name, "Art webring");
+
+	let sites = vec![
+		ImportedSite {
+			url: "https://imported.example".to_owned(),
+			email: None,
+			profile: profile("Imported"),
+			tags: vec!["Painting".to_owned()],
+		},
+		// Already in the default ring, which doesn't stop it joining this one too
+		ImportedSite {
+			url: "https://a.example".to_owned(),
+			email: None,
+			profile: SiteProfile::default(),
+			tags: Vec::new(),
+		},
+	];
+	let added = art
+		.import_sites(sites.clone(), DEFAULT_ADMIN_ID, "admin@example.com", true)
+		.await
+		.unwrap();
+	assert_eq!(added, 2);
+	let added = art
+		.import_sites(sites, DEFAULT_ADMIN_ID, "admin@example.com", true)
+		.await
+		.unwrap();
+	assert_eq!(added, 0);
+	assert!(matches!(
+		art.add_site("https://a.example", "owner@example.com", &profile("A"))
+			.await,
+		Err(RingError::UniqueRowAlreadyPresent(_))
+	));
+	let approved = art.get_list_approved(Some("painting")).await.unwrap();
+	assert_eq!(approved.len(), 1);
+	assert_eq!(approved[0].site_email, "admin@example.com");
+	// Rings don't see each other's sites
+	assert!(matches!(
+		art.get_next("https://c.example", None).await,
+		Err(RingError::SiteNotApproved(_))
+	));
+	assert_eq!(
+		art.get_next("https://imported.example", None)
+			.await
+			.unwrap(),
+		"https://a.example"
+	);
+
+	// The same hop in two rings is counted in each
+	for ring in [state, &art] {
+		ring.record_navigation(NavigationKind::Random, None, None)
+			.await;
+	}
+	assert_eq!(state.flush_navigation().await.unwrap(), 2);
+	for ring in [state, &art] {
+		let days = ring.get_ring_daily_stats(1).await.unwrap();
+		assert_eq!(days[0].random, 1);
+	}
+}
+
+async fn admins(state: &RingState, store: &DatabaseStore) {
+	state
+		.add_admin(
+			"second".to_owned(),
+			"second@example.com".to_owned(),
+			"password".to_owned(),
+			None,
+		)
+		.await
+		.unwrap();
+	assert!(matches!(
+		state
+			.add_admin(
+				"second".to_owned(),
+				"other@example.com".to_owned(),
+				"password".to_owned(),
+				None,
+			)
+			.await,
+		Err(RingError::UniqueRowAlreadyPresent(_))
+	));
+	let second = store.get_admin_by_username("second").await.unwrap().unwrap();
+	state
+		.add_site("https://d.example", "owner@example.com", &profile("D"))
+		.await
+		.unwrap();
+	state
+		.approve_site("https://d.example", second.id())
+		.await
+		.unwrap();
+	// The admin who approved a site can't be deleted while the approval refers to them
+	assert!(matches!(
+		state.delete_admin(second.id()).await,
+		Err(RingError::RowInUse(_))
+	));
+	state.remove_site("https://d.example").await.unwrap();
+}
+
+/// Sites imported without a verification token can be sent one, which waits in the queue while the
+/// SMTP server is down
+async fn emails(state: &RingState, pool: &AnyPool) {
+	let mailer = Mailer::new(
+		Some("smtp://127.0.0.1:1"),
+		"webring@example.com",
+		"https://ring.example",
+	)
+	.unwrap();
+	let art = state.for_ring("art").await.unwrap().with_mailer(mailer);
+	let site_id = art.get_list_approved(None).await.unwrap()[0].site_id;
+	assert_eq!(art.get_member_site(site_id).await.unwrap().email_verified_at, None);
+	art.resend_verification_email(site_id).await.unwrap();
+
+	let queued: Vec<(String, i64)> = sqlx::query_as("SELECT recipient, attempts FROM queued_emails")
+		.fetch_all(pool)
+		.await
+		.unwrap();
+	assert_eq!(queued, [("admin@example.com".to_owned(), 0)]);
+	assert_eq!(art.send_queued_emails().await.unwrap(), 1);
+	let attempts: i64 = sqlx::query_scalar("SELECT attempts FROM queued_emails")
+		.fetch_one(pool)
+		.await
+		.unwrap();
+	assert_eq!(attempts, 1);
+	// It isn't due again for a while
+	assert_eq!(art.send_queued_emails().await.unwrap(), 0);
+
+	let site_id = approved_site_id(state, "https://a.example").await;
+	assert!(matches!(
+		state.resend_verification_email(site_id).await,
+		Err(RingError::InvalidInput(_))
+	));
+}
+
+/// Sites that move keep their place, and can be navigated from their old url until the grace
+/// period is over
+async fn moves(pool: &AnyPool, store: &DatabaseStore) {
+	let state = RingState::new(pool.clone());
+	let a = approved_site_id(&state, "https://a.example").await;
+	let c = approved_site_id(&state, "https://c.example").await;
+	let old_url = store
+		.change_site_url(1, c, "https://moved.example", Some(sql::days_from_now(30)))
+		.await
+		.unwrap();
+	assert_eq!(old_url, "https://c.example");
+	assert!(matches!(
+		store
+			.change_site_url(1, a, "https://moved.example", None)
+			.await,
+		Err(RingError::UniqueRowAlreadyPresent(_))
+	));
+	// The aliases are only looked at when the url isn't in the cached order of the ring
+	let state = RingState::new(pool.clone());
+	assert_eq!(approved_site_id(&state, "https://moved.example").await, c);
+	assert_eq!(
+		state.get_next("https://a.example", None).await.unwrap(),
+		"https://moved.example"
+	);
+	assert_eq!(
+		state.get_prev("https://c.example", None).await.unwrap(),
+		"https://a.example"
+	);
+
+	store
+		.change_site_url(
+			1,
+			a,
+			"https://a.example.net",
+			Some("2000-01-01 00:00:00".to_owned()),
+		)
+		.await
+		.unwrap();
+	let state = RingState::new(pool.clone());
+	assert!(matches!(
+		state.get_next("https://a.example", None).await,
+		Err(RingError::SiteNotApproved(_))
+	));
+	assert_eq!(
+		state.get_prev("https://moved.example", None).await.unwrap(),
+		"https://a.example.net"
+	);
+}
+
+/// Checks that the errors the database gives are told apart the same way on every backend
+async fn violations(pool: &AnyPool) {
+	let e = sqlx::query("INSERT INTO rings (slug, name) VALUES ('default', 'Duplicate')")
+		.execute(pool)
+		.await
+		.unwrap_err();
+	assert!(sql::is_unique_violation(&e), "{e}");
+	assert!(!sql::is_foreign_key_violation(&e), "{e}");
+
+	let e = sqlx::query("INSERT INTO site_tags (site_id, tag_id) VALUES (-1, -1)")
+		.execute(pool)
+		.await
+		.unwrap_err();
+	assert!(sql::is_foreign_key_violation(&e), "{e}");
+	assert!(!sql::is_unique_violation(&e), "{e}");
+
+	let e = sqlx::query("DELETE FROM admins WHERE id = $1")
+		.bind(DEFAULT_ADMIN_ID)
+		.execute(pool)
+		.await
+		.unwrap_err();
+	assert!(sql::is_foreign_key_violation(&e), "{e}");
+}
+
+/// Serves `router` on a local port, returning its url
+async fn serve(router: Router) -> String {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let url = format!("http://{}", listener.local_addr().unwrap());
+	tokio::spawn(async move { axum::serve(listener, router).await });
+	url
+}
+
+async fn webhooks(state: &RingState) {
+	let received = Arc::new(Mutex::new(Vec::new()));
+	let log = received.clone();
+	let url = serve(Router::new().route(
+		"/hook",
+		post(move |headers: HeaderMap, body: String| async move {
+			let signature = headers["X-Webringer-Signature"]
+				.to_str()
+				.unwrap()
+				.to_owned();
+			log.lock().unwrap().push((signature, body));
+		}),
+	))
+	.await;
+	// Nothing listens on a port once its listener is dropped
+	let closed = TcpListener::bind("127.0.0.1:0")
+		.await
+		.unwrap()
+		.local_addr()
+		.unwrap();
+
+	let hook = format!("{url}/hook");
+	state.add_webhook(&hook, DEFAULT_ADMIN_ID).await.unwrap();
+	assert!(matches!(
+		state.add_webhook(&hook, DEFAULT_ADMIN_ID).await,
+		Err(RingError::UniqueRowAlreadyPresent(_))
+	));
+	state
+		.add_webhook(&format!("http://{closed}/hook"), DEFAULT_ADMIN_ID)
+		.await
+		.unwrap();
+	state
+		.add_site(
+			"https://hooked.example",
+			"owner@example.com",
+			&profile("Hooked"),
+		)
+		.await
+		.unwrap();
+
+	assert_eq!(state.deliver_pending_webhooks().await.unwrap(), 2);
+	// Failed deliveries aren't due again for a while
+	assert_eq!(state.deliver_pending_webhooks().await.unwrap(), 0);
+	let deliveries = state.get_list_webhook_deliveries(10).await.unwrap();
+	assert_eq!(deliveries.len(), 2);
+	let delivered = deliveries.iter().find(|d| d.webhook_url == hook).unwrap();
+	assert_eq!(delivered.status, "delivered");
+	assert_eq!(delivered.event, "site_applied");
+	let failed = deliveries.iter().find(|d| d.webhook_url != hook).unwrap();
+	assert_eq!((failed.status.as_str(), failed.attempts), ("pending", 1));
+	assert!(failed.last_response.is_some());
+
+	let webhooks = state.get_list_webhooks().await.unwrap();
+	let (signature, body) = received.lock().unwrap().pop().unwrap();
+	assert_eq!(
+		signature,
+		format!("sha256={}", sign_payload(&webhooks[0].secret, &body))
+	);
+	assert!(body.contains("https://hooked.example"));
+
+	state.remove_webhook(webhooks[1].id).await.unwrap();
+	assert!(matches!(
+		state.remove_webhook(webhooks[1].id).await,
+		Err(RingError::RowNotFound(_))
+	));
+	assert!(
+		state
+			.get_list_webhook_deliveries(10)
+			.await
+			.unwrap()
+			.iter()
+			.all(|d| d.webhook_url == hook)
+	);
+}
+
+async fn stats(state: &RingState) {
+	let approved = state.get_list_approved(None).await.unwrap();
+	let (a, b) = (&approved[0], &approved[1]);
+	for _ in 0..2 {
+		state
+			.record_navigation(NavigationKind::Next, Some(&a.root_url), Some(&b.root_url))
+			.await;
+	}
+	assert_eq!(state.flush_navigation().await.unwrap(), 1);
+	assert_eq!(state.flush_navigation().await.unwrap(), 0);
+	// Counts for a row that's already there are added to it
+	state
+		.record_navigation(NavigationKind::Next, Some(&a.root_url), Some(&b.root_url))
+		.await;
+	state
+		.record_navigation(NavigationKind::Widget, Some(&a.root_url), None)
+		.await;
+	assert_eq!(state.flush_navigation().await.unwrap(), 2);
+
+	let today = &state.get_ring_daily_stats(1).await.unwrap()[0];
+	assert_eq!(today.day, sql::today());
+	assert_eq!((today.next, today.widget), (3, 1));
+	let sites = state.get_site_stats(1).await.unwrap();
+	let site = |id| sites.iter().find(|site| site.site_id == id).unwrap();
+	assert_eq!(
+		(site(a.site_id).outgoing, site(a.site_id).widget_loads),
+		(3, 1)
+	);
+	assert_eq!(site(b.site_id).incoming, 3);
+	let days = state.get_site_daily_stats(b.site_id, 1).await.unwrap();
+	assert_eq!((days[0].incoming, days[0].outgoing), (3, 0));
+
+	let health = state.get_ring_health().await.unwrap();
+	let default = health.iter().find(|ring| ring.ring == "default").unwrap();
+	assert_eq!(default.approved, i64::try_from(approved.len()).unwrap());
+	assert_eq!(default.pending, 1);
+}
+
+/// Serves a member's feed, which is only sent again when it's changed, and their button
+async fn serve_member_site() -> String {
+	const FEED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+		<feed xmlns="http://www.w3.org/2005/Atom">
+			<entry>
+				<id>tag:blog.example,2026:1</id>
+				<title>Hello</title>
+				<link href="/hello"/>
+				<published>2026-01-01T00:00:00Z</published>
+			</entry>
+		</feed>"#;
+	let feed = |headers: HeaderMap| async move {
+		if headers
+			.get(header::IF_NONE_MATCH)
+			.is_some_and(|etag| etag == "\"1\"")
+		{
+			StatusCode::NOT_MODIFIED.into_response()
+		} else {
+			([(header::ETAG, "\"1\"")], FEED).into_response()
+		}
+	};
+	serve(
+		Router::new()
+			.route("/feed.xml", get(feed))
+			.route("/button.gif", get(|| async { "GIF89a" })),
+	)
+	.await
+}
+
+async fn planet_and_buttons(state: &RingState) {
+	let url = serve_member_site().await;
+	let profile = SiteProfile {
+		feed_url: Some(format!("{url}/feed.xml")),
+		button_url: Some(format!("{url}/button.gif")),
+		..profile("Blog")
+	};
+	state
+		.add_site(&url, "owner@example.com", &profile)
+		.await
+		.unwrap();
+	state.approve_site(&url, DEFAULT_ADMIN_ID).await.unwrap();
+	let site_id = approved_site_id(state, &url).await;
+
+	assert_eq!(state.refresh_planet().await.unwrap(), 1);
+	// Not modified since last time
+	assert_eq!(state.refresh_planet().await.unwrap(), 1);
+	let entries = state.get_planet_entries(10).await.unwrap();
+	assert_eq!(entries.len(), 1);
+	assert_eq!(entries[0].site_name(), "Blog");
+	assert_eq!(entries[0].url, format!("{url}/hello"));
+	assert_eq!(entries[0].published, "2026-01-01 00:00:00");
+	let feeds = state.get_list_planet_feeds().await.unwrap();
+	assert_eq!(
+		(feeds[0].entry_count, feeds[0].last_error.as_deref()),
+		(1, None)
+	);
+
+	state.set_planet_feed_excluded(site_id, true).await.unwrap();
+	assert!(state.get_planet_entries(10).await.unwrap().is_empty());
+	assert_eq!(state.refresh_planet().await.unwrap(), 0);
+	state
+		.set_planet_feed_excluded(site_id, false)
+		.await
+		.unwrap();
+	assert_eq!(state.get_planet_entries(10).await.unwrap().len(), 1);
+	assert!(matches!(
+		state.set_planet_feed_excluded(0, true).await,
+		Err(RingError::RowNotFound(_))
+	));
+
+	assert!(state.get_button(site_id).await.unwrap().is_none());
+	assert_eq!(state.refresh_buttons().await.unwrap(), 1);
+	assert_eq!(state.refresh_buttons().await.unwrap(), 0);
+	let button = state.get_button(site_id).await.unwrap().unwrap();
+	assert_eq!(button.content_type, "image/gif");
+	assert_eq!(button.image, b"GIF89a");
+}
+
+/// Serves the manifests of rings on another instance, at `<url>/<name>/ring.json`, signed with
+/// whatever key is in `key` at the time, returning the instance's url. They're at
+/// `<url>/<name>/moved.json` too, which isn't where they say they're published.
+async fn serve_peer_rings(key: Arc<Mutex<SigningKey>>) -> String {
+	// The manifests need the url, which isn't known until the router is being served
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let url = format!("http://{}", listener.local_addr().unwrap());
+	let instance_url = url.clone();
+	let manifest = move |Path(name): Path<String>| async move {
+		let ring_url = format!("{instance_url}/{name}");
+		let key = key.lock().unwrap().clone();
+		let body = serde_json::to_string(&RingManifest {
+			version: MANIFEST_VERSION,
+			name: format!("Ring {name}"),
+			description: None,
+			home_url: format!("{ring_url}/"),
+			manifest_url: format!("{ring_url}/ring.json"),
+			navigation: ManifestNavigation {
+				next: format!("{ring_url}/next"),
+				prev: format!("{ring_url}/prev"),
+				random: format!("{ring_url}/random"),
+				next_ring: format!("{ring_url}/next-ring"),
+				random_ring: format!("{ring_url}/random-ring"),
+			},
+			members: Vec::new(),
+			peers: Vec::new(),
+			public_key: hex::encode(key.verifying_key().to_bytes()),
+			generated_at: 0,
+		})
+		.unwrap();
+		([(SIGNATURE_HEADER, sign_manifest(&key, &body))], body)
+	};
+	let router = Router::new()
+		.route("/{name}/ring.json", get(manifest.clone()))
+		.route("/{name}/moved.json", get(manifest));
+	tokio::spawn(async move { axum::serve(listener, router).await });
+	url
+}
+
+async fn federation(state: &RingState) {
+	let key = Arc::new(Mutex::new(generate_signing_key()));
+	let url = serve_peer_rings(key.clone()).await;
+	let (first, second) = (format!("{url}/first/"), format!("{url}/second/"));
+
+	state
+		.add_peer_ring(&format!("{first}ring.json"))
+		.await
+		.unwrap();
+	state
+		.add_peer_ring(&format!("{second}ring.json"))
+		.await
+		.unwrap();
+	assert!(matches!(
+		state.add_peer_ring(&format!("{first}ring.json")).await,
+		Err(RingError::UniqueRowAlreadyPresent(_))
+	));
+	assert!(matches!(
+		state.add_peer_ring(&format!("{first}moved.json")).await,
+		Err(RingError::Federation(problem)) if problem.contains("different url")
+	));
+	let peers = state.get_list_peer_rings().await.unwrap();
+	assert_eq!(peers.len(), 2);
+	assert_eq!(peers[0].name, "Ring first");
+
+	assert_eq!(state.get_next_ring(None).await.unwrap(), first);
+	assert_eq!(state.get_next_ring(Some(&first)).await.unwrap(), second);
+	// After the last peer comes this ring again
+	assert_eq!(
+		state.get_next_ring(Some(&second)).await.unwrap(),
+		state.home_url()
+	);
+	assert_eq!(state.refresh_all_peer_rings().await.unwrap(), 2);
+
+	// A peer that changes its key is no longer trusted, or linked to
+	*key.lock().unwrap() = generate_signing_key();
+	assert!(matches!(
+		state.refresh_peer_ring(peers[1].id).await,
+		Err(RingError::Federation(_))
+	));
+	let peers = state.get_list_peer_rings().await.unwrap();
+	assert_eq!(peers[0].last_error, None);
+	assert!(
+		peers[1]
+			.last_error
+			.as_deref()
+			.is_some_and(|problem| problem.contains("different key"))
+	);
+	assert_eq!(
+		state.get_next_ring(Some(&first)).await.unwrap(),
+		state.home_url()
+	);
+	assert_eq!(state.get_random_ring().await.unwrap(), first);
+	assert_eq!(state.refresh_all_peer_rings().await.unwrap(), 0);
+}
+
+/// Exports everything `state` holds, imports it into the freshly migrated `fresh`, and checks
+/// exporting that gives back the same
+async fn export_round_trip(state: &RingState, fresh: &RingState) {
+	// Makes the signing key, so it's exported too
+	state.get_ring_manifest().await.unwrap();
+	let export = state.export().await.unwrap();
+	assert!(export.signing_key.is_some());
+	assert!(export.rings.len() > 1);
+	let mut approvals = export
+		.rings
+		.iter()
+		.flat_map(|ring| &ring.sites)
+		.filter_map(|site| match &site.decision {
+			Some(Decision::Approved { approved_at, .. }) => approved_at.as_ref(),
+			_ => None,
+		});
+	assert!(approvals.next().is_some());
+
+	let summary = fresh.import(&export).await.unwrap();
+	assert!(summary.sites_added > 0);
+	assert!(summary.signing_key_imported);
+	let mut imported = fresh.export().await.unwrap();
+	imported.exported_at.clone_from(&export.exported_at);
+	assert_eq!(
+		serde_json::to_value(&imported).unwrap(),
+		serde_json::to_value(&export).unwrap()
+	);
+	// Manifests are signed with the same key
+	let public_key = |(body, _signature): (String, String)| {
+		serde_json::from_str::<RingManifest>(&body)
+			.unwrap()
+			.public_key
+	};
+	assert_eq!(
+		public_key(fresh.get_ring_manifest().await.unwrap()),
+		public_key(state.get_ring_manifest().await.unwrap())
+	);
+
+	let summary = fresh.import(&export).await.unwrap();
+	assert_eq!(summary.sites_added, 0);
+	assert!(!summary.signing_key_imported);
+}
+
+async fn exercise(pool: AnyPool, fresh: AnyPool) {
+	let state = RingState::new(pool.clone());
+	let store = DatabaseStore::new(pool.clone());
+	navigation(&state).await;
+	tags(&state).await;
+	members(&state, &store).await;
+	rings_and_imports(&state).await;
+	emails(&state, &pool).await;
+	admins(&state, &store).await;
+	moves(&pool, &store).await;
+	violations(&pool).await;
+	webhooks(&state).await;
+	stats(&state).await;
+	planet_and_buttons(&state).await;
+	federation(&state).await;
+	export_round_trip(&state, &RingState::new(fresh)).await;
+}
+
+#[tokio::test]
+async fn sqlite() {
+	sqlx::any::install_default_drivers();
+	// Every connection to an in memory database gets its own, so there can only be one
+	let mut pools = Vec::new();
+	for _ in 0..2 {
+		let pool = AnyPoolOptions::new()
+			.max_connections(1)
+			.connect("sqlite::memory:")
+			.await
+			.unwrap();
+		MIGRATOR.run(&pool).await.unwrap();
+		pools.push(pool);
+	}
+	let fresh = pools.pop().unwrap();
+	exercise(pools.pop().unwrap(), fresh).await;
+}
+
+/// Creates a migrated database with a unique name on the Postgres server, returning its name and a
+/// pool connected to it
+async fn create_postgres_database(server: &AnyPool, server_url: &str) -> (String, AnyPool) {
+	let name = format!(
+		"webringer_test_{}",
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap()
+			.as_nanos()
+	);
+	sqlx::query(&format!("CREATE DATABASE {name}"))
+		.execute(server)
+		.await
+		.unwrap();
+
+	let (without_query, query) = server_url
+		.split_once('?')
+		.map_or((server_url, None), |(url, query)| (url, Some(query)));
+	let (host, _database) = without_query
+		.rsplit_once('/')
+		.filter(|(host, _database)| host.contains("//") && !host.ends_with('/'))
+		.unwrap_or((without_query, ""));
+	let url = match query {
+		Some(query) => format!("{host}/{name}?{query}"),
+		None => format!("{host}/{name}"),
+	};
+	let pool = AnyPoolOptions::new().connect(&url).await.unwrap();
+	POSTGRES_MIGRATOR.run(&pool).await.unwrap();
+	(name, pool)
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server in DATABASE_URL"]
+async fn postgres() {
+	let server_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+	assert!(
+		server_url.starts_with("postgres://") || server_url.starts_with("postgresql://"),
+		"DATABASE_URL isn't a postgres:// url"
+	);
+	sqlx::any::install_default_drivers();
+	let server = AnyPoolOptions::new()
+		.max_connections(1)
+		.connect(&server_url)
+		.await
+		.unwrap();
+	let (name, pool) = create_postgres_database(&server, &server_url).await;
+	let (fresh_name, fresh) = create_postgres_database(&server, &server_url).await;
+	// Drop the database even if the tests fail
+	let result = tokio::spawn(exercise(pool.clone(), fresh.clone())).await;
+	pool.close().await;
+	fresh.close().await;
+	for name in [name, fresh_name] {
+		sqlx::query(&format!("DROP DATABASE {name} WITH (FORCE)"))
+			.execute(&server)
+			.await
+			.unwrap();
+	}
+	if let Err(e) = result {
+		std::panic::resume_unwind(e.into_panic());
+	}
+}
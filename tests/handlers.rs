@@ -0,0 +1,436 @@
+//! Drives the router with every ring, site and admin kept in a [`MemoryStore`], and a database that
+//! was never migrated, to check that the pages go through the store
+#![warn(clippy::pedantic, clippy::all, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+use std::time::Duration;
+
+use axum::{
+	Router,
+	body::{Body, to_bytes},
+	http::{Request, Response, StatusCode, header},
+	routing::get,
+};
+use sha2::{Digest, Sha256};
+use sqlx::any::AnyPoolOptions;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+use webringer::{
+	app::WebringerApp,
+	ring::{
+		RingState, SiteProfile,
+		ownership::auth_hash,
+		sql,
+		store::{MemoryStore, RingStore},
+	},
+};
+
+const ADMIN_PASSWORD: &str = "correct horse battery staple";
+
+/// A browser with its own cookies, so sessions carry over between requests
+struct Client {
+	router: Router,
+	cookie: Option<String>,
+}
+
+impl Client {
+	async fn send(&mut self, request: Request<Body>) -> Response<Body> {
+		let mut request = request;
+		if let Some(cookie) = &self.cookie {
+			request
+				.headers_mut()
+				.insert(header::COOKIE, cookie.parse().unwrap());
+		}
+		let response = self.router.clone().oneshot(request).await.unwrap();
+		if let Some(set_cookie) = response.headers().get(header::SET_COOKIE) {
+			let cookie = set_cookie.to_str().unwrap();
+			self.cookie = Some(cookie.split(';').next().unwrap().to_owned());
+		}
+		response
+	}
+
+	async fn get(&mut self, uri: &str) -> Response<Body> {
+		self.send(Request::get(uri).body(Body::empty()).unwrap())
+			.await
+	}
+
+	async fn post(&mut self, uri: &str, form: &[(&str, &str)]) -> Response<Body> {
+		self.send(
+			Request::post(uri)
+				.header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+				.body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+				.unwrap(),
+		)
+		.await
+	}
+}
+
+fn location(response: &Response<Body>) -> &str {
+	response
+		.headers()
+		.get(header::LOCATION)
+		.expect("response is a redirect")
+		.to_str()
+		.unwrap()
+}
+
+async fn text(response: Response<Body>) -> String {
+	let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+	String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// A state keeping everything in `store`, with a database that's never connected to successfully
+async fn state(store: &MemoryStore) -> RingState {
+	sqlx::any::install_default_drivers();
+	let pool = AnyPoolOptions::new().connect_lazy("sqlite::memory:").unwrap();
+	let state = RingState::new(pool).with_store(store.clone());
+	state
+		.add_admin(
+			"admin".to_owned(),
+			"admin@example.com".to_owned(),
+			ADMIN_PASSWORD.to_owned(),
+			None,
+		)
+		.await
+		.unwrap();
+	state
+}
+
+fn client(state: &RingState) -> Client {
+	Client {
+		router: WebringerApp::new(state.clone()).into_router(),
+		cookie: None,
+	}
+}
+
+/// Serves a site that proves it's owned by whoever is joining, returning its url
+async fn serve_site() -> String {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let url = format!("http://{}", listener.local_addr().unwrap());
+	let hash = auth_hash(&url);
+	let router = Router::new().route("/webringer/auth", get(move || async move { hash }));
+	tokio::spawn(async move { axum::serve(listener, router).await });
+	url
+}
+
+async fn add_approved_sites(state: &RingState, urls: &[&str]) {
+	for url in urls {
+		state
+			.add_site(url, "owner@example.com", &SiteProfile::default())
+			.await
+			.unwrap();
+		state.approve_site(url, 1).await.unwrap();
+	}
+}
+
+#[tokio::test]
+async fn joining_verifying_and_approving_a_site() {
+	let store = MemoryStore::default();
+	let state = state(&store).await;
+	let mut client = client(&state);
+	let url = serve_site().await;
+
+	let response = client
+		.post(
+			"/join",
+			&[
+				("url", &url),
+				("email", "owner@example.com"),
+				("title", "My site"),
+			],
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::SEE_OTHER);
+	let unapproved = store.get_list_unapproved(1).await.unwrap();
+	assert_eq!(unapproved.len(), 1);
+	assert_eq!(unapproved[0].root_url, url);
+	assert_eq!(unapproved[0].email_verified_at, None);
+
+	// The token in the verification email can't be read back, so the email is changed to one with
+	// a known token
+	let site_id = store.get_site_id(1, &url).await.unwrap();
+	store
+		.change_site_email(1, site_id, "owner@example.com", "known-token")
+		.await
+		.unwrap();
+	let response = client.get("/join/verify?token=known-token").await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(store.get_list_unapproved(1).await.unwrap()[0].email_verified_at.is_some());
+
+	let response = client
+		.post(
+			"/login",
+			&[("username", "admin"), ("password", ADMIN_PASSWORD)],
+		)
+		.await;
+	assert_eq!(location(&response), "/");
+	let response = client.post("/admin/approve", &[("url", &url)]).await;
+	assert_eq!(location(&response), "/admin/view");
+
+	let list = text(client.get("/list").await).await;
+	assert!(list.contains(&url));
+	assert!(list.contains("My site"));
+	let member = client.get(&format!("/members/{site_id}")).await;
+	assert_eq!(member.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn navigating_the_ring() {
+	let store = MemoryStore::default();
+	let state = state(&store).await;
+	let mut client = client(&state);
+	add_approved_sites(
+		&state,
+		&["https://a.example", "https://b.example", "https://c.example"],
+	)
+	.await;
+
+	let response = client.get("/next?current=https://a.example").await;
+	assert_eq!(location(&response), "https://b.example");
+	let response = client.get("/prev?current=https://c.example").await;
+	assert_eq!(location(&response), "https://b.example");
+	// The ends of the ring lead back home
+	let response = client.get("/next?current=https://c.example").await;
+	assert_eq!(location(&response), "/");
+	let response = client.get("/random").await;
+	assert!(location(&response).ends_with(".example"));
+	let response = client.get("/next?current=https://unknown.example").await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let site_id = store.get_site_id(1, "https://b.example").await.unwrap();
+	state.set_site_tags(site_id, "Art").await.unwrap();
+	let response = client.get("/random?tag=art").await;
+	assert_eq!(location(&response), "https://b.example");
+	let list = text(client.get("/list?tag=art").await).await;
+	assert!(list.contains("https://b.example"));
+	assert!(!list.contains("https://a.example"));
+}
+
+#[tokio::test]
+async fn rings_come_from_the_store() {
+	let store = MemoryStore::default();
+	let state = state(&store).await;
+	let mut client = client(&state);
+
+	assert_eq!(
+		client.get("/r/art/next?current=https://a.example").await.status(),
+		StatusCode::NOT_FOUND
+	);
+
+	state.add_ring("art", "Art ring", None).await.unwrap();
+	let art = state.for_ring("art").await.unwrap();
+	add_approved_sites(&art, &["https://a.example", "https://b.example"]).await;
+	let response = client.get("/r/art/next?current=https://a.example").await;
+	assert_eq!(location(&response), "https://b.example");
+	// The default ring doesn't have the art ring's sites
+	let response = client.get("/next?current=https://a.example").await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let list = text(client.get("/r/art/list").await).await;
+	assert!(list.contains("Art ring"));
+}
+
+#[tokio::test]
+async fn members_editing_their_site() {
+	let store = MemoryStore::default();
+	let state = state(&store).await;
+	let mut client = client(&state);
+	add_approved_sites(&state, &["https://a.example"]).await;
+	let site_id = store.get_site_id(1, "https://a.example").await.unwrap();
+
+	let response = client.post("/member/login", &[("url", "https://a.example")]).await;
+	assert_eq!(location(&response), "/member/login");
+	let response = client.get("/member/login/verify?token=wrong").await;
+	assert_eq!(location(&response), "/member/login");
+	let response = client.get("/member").await;
+	assert_eq!(location(&response), "/member/login");
+
+	// The token in the login email can't be read back, so one with a known token is added
+	store
+		.add_member_login_token(
+			site_id,
+			&hex::encode(Sha256::digest("known-token")),
+			&sql::seconds_from_now(60),
+		)
+		.await
+		.unwrap();
+	let response = client.get("/member/login/verify?token=known-token").await;
+	assert_eq!(location(&response), "/member");
+	let dashboard = client.get("/member").await;
+	assert_eq!(dashboard.status(), StatusCode::OK);
+
+	client
+		.post(
+			"/member/profile",
+			&[("title", "New title"), ("owner_name", "Owner")],
+		)
+		.await;
+	client.post("/member/tags", &[("tags", "Art, Music")]).await;
+	client
+		.post("/member/email", &[("email", "new@example.com")])
+		.await;
+
+	let site = store.get_member_site(1, site_id).await.unwrap();
+	assert_eq!(site.profile.title.as_deref(), Some("New title"));
+	assert_eq!(site.profile.owner_name.as_deref(), Some("Owner"));
+	assert_eq!(site.email, "new@example.com");
+	assert_eq!(site.email_verified_at, None);
+	assert_eq!(store.get_site_tags(1, site_id).await.unwrap(), ["art", "music"]);
+	let member = text(client.get(&format!("/members/{site_id}")).await).await;
+	assert!(member.contains("New title"));
+
+	// Login links can only be used once
+	let response = client.get("/member/login/verify?token=known-token").await;
+	assert_eq!(location(&response), "/member/login");
+}
+
+#[tokio::test]
+async fn backups_are_refused() {
+	let store = MemoryStore::default();
+	let state = state(&store).await;
+	assert!(state.export().await.is_err());
+}
+
+#[tokio::test]
+async fn static_files_are_cached_and_compressed() {
+	let store = MemoryStore::default();
+	let state = state(&store).await;
+	let mut client = client(&state);
+
+	let gzip = |uri: &str| {
+		Request::get(uri)
+			.header(header::ACCEPT_ENCODING, "gzip")
+			.body(Body::empty())
+			.unwrap()
+	};
+	// The first request starts compressing the files in the background, and gets the file as it is
+	let mut response = client.send(gzip("/static/css/style.css")).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	for _ in 0..100 {
+		if response.headers().contains_key(header::CONTENT_ENCODING) {
+			break;
+		}
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		response = client.send(gzip("/static/css/style.css")).await;
+	}
+	assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+	assert_eq!(
+		response.headers()[header::CACHE_CONTROL],
+		"public, max-age=3600"
+	);
+	let etag = response.headers()[header::ETAG].clone();
+	let mut request = gzip("/static/css/style.css");
+	request.headers_mut().insert(header::IF_NONE_MATCH, etag);
+	assert_eq!(
+		client.send(request).await.status(),
+		StatusCode::NOT_MODIFIED
+	);
+
+	// The fonts are served by the instance itself, rather than a third party
+	let response = client
+		.get("/static/fonts/source-serif-4-regular.woff2")
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(
+		response.headers()[header::CACHE_CONTROL]
+			.to_str()
+			.unwrap()
+			.contains("immutable")
+	);
+	let response = client.get("/").await;
+	assert!(
+		response
+			.headers()
+			.contains_key(header::CONTENT_SECURITY_POLICY)
+	);
+	assert!(!text(response).await.contains("fonts.googleapis.com"));
+
+	assert_eq!(
+		client.get("/no-such-page").await.status(),
+		StatusCode::NOT_FOUND
+	);
+	// Pages that aren't there still find the stylesheet when the app is served under a path
+	let mut nested = Client {
+		router: WebringerApp::new(state.clone())
+			.nest_at("/webring")
+			.into_router(),
+		cookie: None,
+	};
+	let response = nested.get("/webring/no/such/page").await;
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	assert!(
+		text(response)
+			.await
+			.contains("href=\"/webring/static/css/style.css\"")
+	);
+}
+
+#[tokio::test]
+async fn buttons_are_served_from_the_instance() {
+	const PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really a png";
+	sqlx::any::install_default_drivers();
+	// Buttons are cached in the database, so this state keeps everything there
+	let pool = AnyPoolOptions::new()
+		.max_connections(1)
+		.connect("sqlite::memory:")
+		.await
+		.unwrap();
+	sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+	let state = RingState::new(pool);
+	let mut client = client(&state);
+
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let url = format!("http://{}", listener.local_addr().unwrap());
+	let router = Router::new()
+		.route("/button.png", get(|| async { PNG }))
+		.route("/button.svg", get(|| async { "<svg></svg>" }));
+	tokio::spawn(async move { axum::serve(listener, router).await });
+	for (site, button) in [
+		("https://png.example", "button.png"),
+		("https://svg.example", "button.svg"),
+	] {
+		let profile = SiteProfile {
+			button_url: Some(format!("{url}/{button}")),
+			..SiteProfile::default()
+		};
+		state
+			.add_site(site, "owner@example.com", &profile)
+			.await
+			.unwrap();
+		state.approve_site(site, 1).await.unwrap();
+	}
+	let sites = state.get_list_approved(None).await.unwrap();
+	let (png, svg) = (sites[0].site_id, sites[1].site_id);
+
+	assert_eq!(
+		client.get(&format!("/members/{png}/button")).await.status(),
+		StatusCode::NOT_FOUND
+	);
+	assert_eq!(state.refresh_buttons().await.unwrap(), 1);
+	// Neither button is due again until tomorrow
+	assert_eq!(state.refresh_buttons().await.unwrap(), 0);
+
+	let response = client.get(&format!("/members/{png}/button")).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+	assert_eq!(
+		response.headers()[header::X_CONTENT_TYPE_OPTIONS],
+		"nosniff"
+	);
+	let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+	assert_eq!(body, PNG);
+	assert_eq!(
+		client.get(&format!("/members/{svg}/button")).await.status(),
+		StatusCode::NOT_FOUND
+	);
+
+	let response = client.get("/list").await;
+	let csp = response.headers()[header::CONTENT_SECURITY_POLICY]
+		.to_str()
+		.unwrap()
+		.to_owned();
+	assert!(csp.contains("img-src 'self';"), "{csp}");
+	let list = text(response).await;
+	assert!(list.contains(&format!("src=\"/members/{png}/button\"")));
+	assert!(!list.contains(&url));
+}